@@ -0,0 +1,89 @@
+use eren_render_vulkan_3d::render::{render_item::RenderItem, renderer_3d::Renderer3D};
+use eren_render_vulkan_core::{
+    context::{GraphicsContext, GraphicsContextConfig},
+    renderer::Renderer,
+};
+
+const WIDTH: u32 = 800;
+const HEIGHT: u32 = 600;
+const FRAME_COUNT: usize = 10;
+
+fn main() {
+    env_logger::init();
+
+    let mut graphics_context = match GraphicsContext::with_config(GraphicsContextConfig {
+        gpu_profiling: true,
+        device_requirements: Renderer3D::device_requirements(),
+        ..Default::default()
+    }) {
+        Ok(graphics_context) => graphics_context,
+        Err(e) => panic!("Failed to create graphics context: {}", e),
+    };
+
+    if let Err(e) = graphics_context.init_headless(WIDTH, HEIGHT) {
+        panic!("Failed to initialize headless graphics context: {}", e);
+    }
+
+    let device_manager = graphics_context.device_manager.as_ref().unwrap();
+    let memory_allocator = graphics_context.memory_allocator.as_ref().unwrap();
+    let destruction_queue = graphics_context.destruction_queue.as_ref().unwrap();
+    let render_target = graphics_context.render_target_info().unwrap();
+
+    let mut renderer = match Renderer3D::new(
+        device_manager,
+        memory_allocator,
+        destruction_queue,
+        graphics_context.pipeline_cache(),
+        &render_target,
+    ) {
+        Ok(renderer) => renderer,
+        Err(e) => panic!("Failed to create renderer: {}", e),
+    };
+
+    let render_items: Vec<RenderItem> = Vec::new();
+
+    if let Some(gpu_profiler) = graphics_context.gpu_profiler() {
+        gpu_profiler.start_trace();
+    }
+
+    for frame in 0..FRAME_COUNT {
+        if frame == FRAME_COUNT - 1 {
+            graphics_context.request_frame_capture();
+        }
+
+        if let Err(e) = graphics_context.redraw(&mut renderer, &render_items) {
+            panic!("Failed to render frame {}: {}", frame, e);
+        }
+    }
+
+    println!(
+        "Rendered {} headless frames at {}x{}",
+        FRAME_COUNT, WIDTH, HEIGHT
+    );
+
+    if let Some(gpu_profiler) = graphics_context.gpu_profiler() {
+        for timing in gpu_profiler.last_frame_timings() {
+            println!("{}: {:.3} ms", timing.name, timing.duration_ms);
+        }
+
+        match gpu_profiler.stop_trace_to_file(std::path::Path::new("headless_trace.json")) {
+            Ok(_) => println!("Saved GPU trace to headless_trace.json"),
+            Err(e) => panic!("Failed to save GPU trace: {}", e),
+        }
+    }
+
+    let output_path = std::env::args()
+        .nth(1)
+        .unwrap_or_else(|| "headless.png".to_string());
+
+    match graphics_context.take_captured_frame() {
+        Some(frame) => match frame.save_png(&output_path) {
+            Ok(_) => println!("Saved last frame to {}", output_path),
+            Err(e) => panic!("Failed to save frame: {}", e),
+        },
+        None => panic!("No frame was captured"),
+    }
+
+    drop(renderer);
+    graphics_context.destroy();
+}
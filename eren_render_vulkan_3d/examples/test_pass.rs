@@ -1,7 +1,7 @@
 use std::sync::Arc;
 
 use eren_render_vulkan_3d::render::test_renderer::TestRenderer;
-use eren_render_vulkan_core::context::GraphicsContext;
+use eren_render_vulkan_core::context::{GraphicsContext, GraphicsContextError};
 use eren_window::window::{WindowConfig, WindowEventHandler, WindowLifecycleManager, WindowSize};
 use winit::window::Window;
 
@@ -25,22 +25,17 @@ struct TestWindowEventHandler {
 
 impl TestWindowEventHandler {
     fn recreate_renderer(&mut self) {
-        let instance_manager = self.graphics_context.instance_manager.as_ref().unwrap();
-        let physical_device_manager = self
-            .graphics_context
-            .physical_device_manager
-            .as_ref()
-            .unwrap();
         let device_manager = self.graphics_context.device_manager.as_ref().unwrap();
-        let swapchain_manager = self.graphics_context.swapchain_manager.as_ref().unwrap();
+        let memory_allocator = self.graphics_context.memory_allocator.as_ref().unwrap();
+        let destruction_queue = self.graphics_context.destruction_queue.as_ref().unwrap();
+        let render_target = self.graphics_context.render_target_info().unwrap();
 
         let renderer = match TestRenderer::new(
-            &instance_manager.instance,
-            physical_device_manager.physical_device,
-            device_manager.device.clone(),
-            &self.graphics_context.swapchain_image_views,
-            swapchain_manager.preferred_surface_format,
-            swapchain_manager.image_extent,
+            device_manager,
+            memory_allocator,
+            destruction_queue,
+            self.graphics_context.pipeline_cache(),
+            &render_target,
         ) {
             Ok(renderer) => renderer,
             Err(e) => show_error_popup_and_panic(e, "Failed to create renderer"),
@@ -48,6 +43,15 @@ impl TestWindowEventHandler {
 
         self.renderer = Some(renderer);
     }
+    fn recover_device_lost(&mut self) {
+        self.renderer = None;
+
+        if let Err(e) = self.graphics_context.recover_device_lost() {
+            show_error_popup_and_panic(e, "Failed to recover from device loss");
+        }
+
+        self.recreate_renderer();
+    }
 }
 
 impl WindowEventHandler for TestWindowEventHandler {
@@ -80,13 +84,11 @@ impl WindowEventHandler for TestWindowEventHandler {
     }
 
     fn redraw(&mut self) {
-        if let Some(renderer) = &self.renderer {
+        if let Some(renderer) = &mut self.renderer {
+            // Swapchain changes are handled by the renderer itself
             match self.graphics_context.redraw(renderer, &[]) {
-                Ok(renderer_needs_recreation) => {
-                    if renderer_needs_recreation {
-                        self.recreate_renderer();
-                    }
-                }
+                Ok(_) => {}
+                Err(GraphicsContextError::DeviceLost) => self.recover_device_lost(),
                 Err(e) => show_error_popup_and_panic(e, "Failed to redraw graphics context"),
             }
         }
@@ -99,6 +101,8 @@ impl WindowEventHandler for TestWindowEventHandler {
 }
 
 fn main() {
+    env_logger::init();
+
     match WindowLifecycleManager::new(
         WindowConfig {
             width: 800,
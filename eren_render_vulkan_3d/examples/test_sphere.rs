@@ -1,24 +1,46 @@
-use std::{collections::HashSet, sync::Arc};
+use std::{
+    sync::{Arc, Mutex},
+    time::Instant,
+};
 
-use ash::vk;
 use eren_render_vulkan_3d::render::{
-    render_item::{Material, Mesh, RenderItem},
+    camera::Camera,
+    mesh::Mesh,
+    render_item::{Material, RenderItem},
     renderer_3d::Renderer3D,
+    vertex::Vertex3D,
 };
 use eren_render_vulkan_core::{
-    context::GraphicsContext,
-    vulkan::memory::{MemoryError, create_buffer_with_memory},
+    context::{GraphicsContext, GraphicsContextError},
+    renderer::{DeviceResource, RendererError, RestoreContext},
+    vulkan::{destruction::DestructionQueue, upload::UploadManager},
 };
 use eren_window::window::{WindowConfig, WindowEventHandler, WindowLifecycleManager, WindowSize};
 use winit::window::Window;
 
 use native_dialog::{DialogBuilder, MessageLevel};
 
-const PLANE_VERTS: [[f32; 8]; 4] = [
-    [-1.0, 0.0, -1.0, 0.0, 1.0, 0.0, 0.0, 0.0],
-    [1.0, 0.0, -1.0, 0.0, 1.0, 0.0, 1.0, 0.0],
-    [1.0, 0.0, 1.0, 0.0, 1.0, 0.0, 1.0, 1.0],
-    [-1.0, 0.0, 1.0, 0.0, 1.0, 0.0, 0.0, 1.0],
+const PLANE_VERTS: [Vertex3D; 4] = [
+    Vertex3D {
+        position: [-1.0, 0.0, -1.0],
+        normal: [0.0, 1.0, 0.0],
+        uv: [0.0, 0.0],
+    },
+    Vertex3D {
+        position: [1.0, 0.0, -1.0],
+        normal: [0.0, 1.0, 0.0],
+        uv: [1.0, 0.0],
+    },
+    Vertex3D {
+        position: [1.0, 0.0, 1.0],
+        normal: [0.0, 1.0, 0.0],
+        uv: [1.0, 1.0],
+    },
+    Vertex3D {
+        position: [-1.0, 0.0, 1.0],
+        normal: [0.0, 1.0, 0.0],
+        uv: [0.0, 1.0],
+    },
 ];
 const PLANE_IDXS: [u32; 6] = [0, 1, 2, 2, 3, 0];
 
@@ -33,79 +55,8 @@ pub fn show_error_popup_and_panic<E: std::fmt::Display>(error: E, context: &str)
     panic!("{}: {}", context, error);
 }
 
-fn create_mesh_from_data(
-    instance: &ash::Instance,
-    physical_device: vk::PhysicalDevice,
-    device: &ash::Device,
-    vertices: &[[f32; 8]],
-    indices: &[u32],
-) -> Result<Arc<Mesh>, MemoryError> {
-    let vertex_buffer_size = (vertices.len() * size_of::<[f32; 8]>()) as vk::DeviceSize;
-    let index_buffer_size = (indices.len() * size_of::<u32>()) as vk::DeviceSize;
-
-    // Vertex buffer
-    let (vertex_buffer, vertex_memory) = create_buffer_with_memory(
-        instance,
-        physical_device,
-        device,
-        vertex_buffer_size,
-        vk::BufferUsageFlags::VERTEX_BUFFER,
-        vk::MemoryPropertyFlags::HOST_VISIBLE | vk::MemoryPropertyFlags::HOST_COHERENT,
-    )?;
-
-    // Map vertex buffer memory and copy data
-    unsafe {
-        let data_ptr = device
-            .map_memory(
-                vertex_memory,
-                0,
-                vertex_buffer_size,
-                vk::MemoryMapFlags::empty(),
-            )
-            .expect("Failed to map vertex memory") as *mut [f32; 8];
-
-        std::ptr::copy_nonoverlapping(vertices.as_ptr(), data_ptr, vertices.len());
-
-        device.unmap_memory(vertex_memory);
-    }
-
-    // Index buffer
-    let (index_buffer, index_memory) = create_buffer_with_memory(
-        instance,
-        physical_device,
-        device,
-        index_buffer_size,
-        vk::BufferUsageFlags::INDEX_BUFFER,
-        vk::MemoryPropertyFlags::HOST_VISIBLE | vk::MemoryPropertyFlags::HOST_COHERENT,
-    )?;
-
-    // Map index buffer memory and copy data
-    unsafe {
-        let data_ptr = device
-            .map_memory(
-                index_memory,
-                0,
-                index_buffer_size,
-                vk::MemoryMapFlags::empty(),
-            )
-            .expect("Failed to map index memory") as *mut u32;
-
-        std::ptr::copy_nonoverlapping(indices.as_ptr(), data_ptr, indices.len());
-
-        device.unmap_memory(index_memory);
-    }
-
-    Ok(Arc::new(Mesh {
-        vertex_buffer,
-        vertex_memory,
-        index_buffer,
-        index_memory,
-        index_count: indices.len() as u32,
-    }))
-}
-
-// Dummy sphere generator returning ([f32; 8] position+normal+uv, u32 indices).
-fn generate_uv_sphere(radius: f32, lon: u32, lat: u32) -> (Vec<[f32; 8]>, Vec<u32>) {
+// Dummy sphere generator returning (position+normal+uv vertices, u32 indices).
+fn generate_uv_sphere(radius: f32, lon: u32, lat: u32) -> (Vec<Vertex3D>, Vec<u32>) {
     let mut verts = Vec::new();
     let mut idxs = Vec::new();
     for y in 0..=lat {
@@ -122,7 +73,11 @@ fn generate_uv_sphere(radius: f32, lon: u32, lat: u32) -> (Vec<[f32; 8]>, Vec<u3
             let nx = pos[0] / radius;
             let ny = pos[1] / radius;
             let nz = pos[2] / radius;
-            verts.push([pos[0], pos[1], pos[2], nx, ny, nz, u, v]);
+            verts.push(Vertex3D {
+                position: pos,
+                normal: [nx, ny, nz],
+                uv: [u, v],
+            });
         }
     }
     // indices
@@ -133,142 +88,150 @@ fn generate_uv_sphere(radius: f32, lon: u32, lat: u32) -> (Vec<[f32; 8]>, Vec<u3
             idxs.extend_from_slice(&[i0, i1, i0 + 1, i0 + 1, i1, i1 + 1]);
         }
     }
-    (verts, idxs.iter().map(|&i| i as u32).collect())
+    (verts, idxs)
 }
 
-fn create_dummy_material(device: &ash::Device) -> Result<Arc<Material>, vk::Result> {
-    let layout_info = vk::DescriptorSetLayoutCreateInfo::default();
-    let descriptor_set_layout = unsafe { device.create_descriptor_set_layout(&layout_info, None)? };
-    let descriptor_set_layouts = vec![descriptor_set_layout];
+// Keeps the geometry on the CPU so the meshes can be rebuilt after the device is lost.
+struct Scene {
+    sphere_vertices: Vec<Vertex3D>,
+    sphere_indices: Vec<u32>,
+    render_items: Vec<RenderItem>,
+}
 
-    let pool_info = vk::DescriptorPoolCreateInfo::default()
-        .max_sets(1)
-        .pool_sizes(&[]);
+impl Scene {
+    fn new() -> Self {
+        let (sphere_vertices, sphere_indices) = generate_uv_sphere(1.0, 32, 16);
 
-    let descriptor_pool = unsafe { device.create_descriptor_pool(&pool_info, None)? };
+        Self {
+            sphere_vertices,
+            sphere_indices,
+            render_items: Vec::new(),
+        }
+    }
+
+    fn build(
+        &mut self,
+        device: &ash::Device,
+        upload_manager: &mut UploadManager,
+        destruction_queue: &DestructionQueue,
+    ) -> Result<(), RendererError> {
+        let plane_mesh = Arc::new(
+            Mesh::builder(&PLANE_VERTS)
+                .indices(&PLANE_IDXS)
+                .build(upload_manager, destruction_queue)
+                .map_err(scene_error)?,
+        );
+
+        let sphere_mesh = Arc::new(
+            Mesh::builder(&self.sphere_vertices)
+                .indices(&self.sphere_indices)
+                .build(upload_manager, destruction_queue)
+                .map_err(scene_error)?,
+        );
 
-    let alloc_info = vk::DescriptorSetAllocateInfo::default()
-        .descriptor_pool(descriptor_pool)
-        .set_layouts(&descriptor_set_layouts);
+        for mesh in [&plane_mesh, &sphere_mesh] {
+            mesh.wait_for_upload(upload_manager).map_err(scene_error)?;
+        }
 
-    let descriptor_sets = unsafe { device.allocate_descriptor_sets(&alloc_info)? };
+        let material =
+            Arc::new(Material::new(device, destruction_queue, &[]).map_err(scene_error)?);
 
-    Ok(Arc::new(Material {
-        descriptor_set_layout,
-        descriptor_pool,
-        descriptor_set: descriptor_sets[0],
-    }))
+        self.render_items = vec![
+            RenderItem {
+                mesh: plane_mesh,
+                material: material.clone(),
+                transform: glam::Mat4::IDENTITY,
+            },
+            RenderItem {
+                mesh: sphere_mesh,
+                material,
+                transform: glam::Mat4::IDENTITY,
+            },
+        ];
+
+        Ok(())
+    }
+}
+
+impl DeviceResource for Scene {
+    // Meshes and materials go through the destruction queue, which is flushed with the old device
+    fn release(&mut self) {
+        self.render_items.clear();
+    }
+
+    fn restore(&mut self, restore_context: &mut RestoreContext) -> Result<(), RendererError> {
+        self.build(
+            restore_context.device,
+            restore_context.upload_manager,
+            restore_context.destruction_queue,
+        )
+    }
+}
+
+fn scene_error<E: std::error::Error + Send + Sync + 'static>(error: E) -> RendererError {
+    RendererError::RestoreFailed {
+        source: Box::new(error),
+        vk_result: None,
+    }
 }
 
 struct TestWindowEventHandler {
     graphics_context: GraphicsContext,
     renderer: Option<Renderer3D>,
-    render_items: Vec<RenderItem>,
+    // Registered with the graphics context, which rebuilds it in `recover_device_lost`
+    scene: Arc<Mutex<Scene>>,
+    start_time: Instant,
 }
 
 impl TestWindowEventHandler {
     fn recreate_renderer(&mut self) {
-        let instance_manager = self.graphics_context.instance_manager.as_ref().unwrap();
-        let physical_device_manager = self
-            .graphics_context
-            .physical_device_manager
-            .as_ref()
-            .unwrap();
         let device_manager = self.graphics_context.device_manager.as_ref().unwrap();
-        let swapchain_manager = self.graphics_context.swapchain_manager.as_ref().unwrap();
+        let memory_allocator = self.graphics_context.memory_allocator.as_ref().unwrap();
+        let destruction_queue = self.graphics_context.destruction_queue.as_ref().unwrap();
+        let render_target = self.graphics_context.render_target_info().unwrap();
 
         let renderer = match Renderer3D::new(
-            &instance_manager.instance,
-            physical_device_manager.physical_device,
-            device_manager.device.clone(),
-            &self.graphics_context.swapchain_image_views,
-            swapchain_manager.preferred_surface_format,
-            swapchain_manager.image_extent,
+            device_manager,
+            memory_allocator,
+            destruction_queue,
+            self.graphics_context.pipeline_cache(),
+            &render_target,
         ) {
             Ok(renderer) => renderer,
             Err(e) => show_error_popup_and_panic(e, "Failed to create renderer"),
         };
 
         self.renderer = Some(renderer);
+    }
 
-        let plane_mesh = match create_mesh_from_data(
-            &instance_manager.instance,
-            physical_device_manager.physical_device,
-            &device_manager.device,
-            &PLANE_VERTS,
-            &PLANE_IDXS,
-        ) {
-            Ok(mesh) => mesh,
-            Err(e) => show_error_popup_and_panic(e, "Failed to create plane mesh"),
-        };
-
-        let (sphere_verts, sphere_idxs) = generate_uv_sphere(1.0, 32, 16);
+    fn build_scene(&mut self) {
+        let device_manager = self.graphics_context.device_manager.as_ref().unwrap();
+        let destruction_queue = self.graphics_context.destruction_queue.as_ref().unwrap();
+        let upload_manager = self.graphics_context.upload_manager.as_mut().unwrap();
 
-        let sphere_mesh = match create_mesh_from_data(
-            &instance_manager.instance,
-            physical_device_manager.physical_device,
+        if let Err(e) = self.scene.lock().unwrap().build(
             &device_manager.device,
-            &sphere_verts,
-            &sphere_idxs,
+            upload_manager,
+            destruction_queue,
         ) {
-            Ok(mesh) => mesh,
-            Err(e) => show_error_popup_and_panic(e, "Failed to create sphere mesh"),
-        };
-
-        let material = match create_dummy_material(&device_manager.device) {
-            Ok(material) => material,
-            Err(e) => show_error_popup_and_panic(e, "Failed to create dummy material"),
-        };
-
-        self.render_items.push(RenderItem {
-            mesh: plane_mesh,
-            material: material.clone(),
-            transform: glam::Mat4::IDENTITY,
-        });
-
-        self.render_items.push(RenderItem {
-            mesh: sphere_mesh,
-            material,
-            transform: glam::Mat4::IDENTITY,
-        });
+            show_error_popup_and_panic(e, "Failed to create scene");
+        }
     }
 
-    fn clear(&mut self) {
+    // The scene is restored by the graphics context; only the renderer is created again here.
+    fn recover_device_lost(&mut self) {
         self.renderer = None;
 
-        let device_manager = self.graphics_context.device_manager.as_ref().unwrap();
-        let device = &device_manager.device;
-
-        let mut unique_meshes = HashSet::new();
-        let mut unique_materials = HashSet::new();
-
-        for render_item in &self.render_items {
-            unique_meshes.insert(Arc::as_ptr(&render_item.mesh));
-            unique_materials.insert(Arc::as_ptr(&render_item.material));
-        }
-
-        for mesh_ptr in &unique_meshes {
-            let mesh = unsafe { Arc::from_raw(*mesh_ptr) };
-            unsafe {
-                device.destroy_buffer(mesh.vertex_buffer, None);
-                device.free_memory(mesh.vertex_memory, None);
-                device.destroy_buffer(mesh.index_buffer, None);
-                device.free_memory(mesh.index_memory, None);
-            }
-            std::mem::forget(mesh);
-        }
-
-        for material_ptr in &unique_materials {
-            let material = unsafe { Arc::from_raw(*material_ptr) };
-            unsafe {
-                device.destroy_descriptor_pool(material.descriptor_pool, None);
-                device.destroy_descriptor_set_layout(material.descriptor_set_layout, None);
-            }
-            std::mem::forget(material);
+        if let Err(e) = self.graphics_context.recover_device_lost() {
+            show_error_popup_and_panic(e, "Failed to recover from device loss");
         }
 
-        self.render_items.clear();
+        self.recreate_renderer();
+    }
 
+    fn clear(&mut self) {
+        self.renderer = None;
+        self.scene.lock().unwrap().release();
         self.graphics_context.destroy();
     }
 }
@@ -286,6 +249,7 @@ impl WindowEventHandler for TestWindowEventHandler {
             Err(e) => show_error_popup_and_panic(e, "Failed to initialize graphics context"),
         };
 
+        self.build_scene();
         self.recreate_renderer();
     }
 
@@ -302,13 +266,30 @@ impl WindowEventHandler for TestWindowEventHandler {
     }
 
     fn redraw(&mut self) {
-        if let Some(renderer) = &self.renderer {
-            match self.graphics_context.redraw(renderer, &self.render_items) {
-                Ok(renderer_needs_recreation) => {
-                    if renderer_needs_recreation {
-                        self.recreate_renderer();
-                    }
-                }
+        if let Some(renderer) = &mut self.renderer {
+            // Orbit the scene at the default camera's distance and height
+            let angle = self.start_time.elapsed().as_secs_f32() * 0.5;
+            let eye = glam::Vec3::new(
+                angle.cos() * 18_f32.sqrt(),
+                3.0,
+                angle.sin() * 18_f32.sqrt(),
+            );
+
+            if let Err(e) =
+                renderer.set_camera(Camera::default().look_at(eye, glam::Vec3::ZERO, glam::Vec3::Y))
+            {
+                show_error_popup_and_panic(e, "Failed to set camera");
+            }
+
+            // Swapchain changes are handled by the renderer itself
+            let redraw_result = {
+                let scene = self.scene.lock().unwrap();
+                self.graphics_context.redraw(renderer, &scene.render_items)
+            };
+
+            match redraw_result {
+                Ok(_) => {}
+                Err(GraphicsContextError::DeviceLost) => self.recover_device_lost(),
                 Err(e) => show_error_popup_and_panic(e, "Failed to redraw graphics context"),
             }
         }
@@ -320,6 +301,16 @@ impl WindowEventHandler for TestWindowEventHandler {
 }
 
 fn main() {
+    env_logger::init();
+
+    let mut graphics_context = match GraphicsContext::new() {
+        Ok(graphics_context) => graphics_context,
+        Err(e) => show_error_popup_and_panic(e, "Failed to create graphics context"),
+    };
+
+    let scene = Arc::new(Mutex::new(Scene::new()));
+    graphics_context.register_device_resource(&scene);
+
     match WindowLifecycleManager::new(
         WindowConfig {
             width: 800,
@@ -328,12 +319,10 @@ fn main() {
             canvas_id: None,
         },
         TestWindowEventHandler {
-            graphics_context: match GraphicsContext::new() {
-                Ok(graphics_context) => graphics_context,
-                Err(e) => show_error_popup_and_panic(e, "Failed to create graphics context"),
-            },
+            graphics_context,
             renderer: None,
-            render_items: Vec::new(),
+            scene,
+            start_time: Instant::now(),
         },
     )
     .start_event_loop()
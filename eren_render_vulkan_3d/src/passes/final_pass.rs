@@ -1,5 +1,15 @@
 use ash::vk;
-use eren_render_vulkan_core::renderer::FrameContext;
+use eren_render_vulkan_core::{
+    renderer::{FrameContext, RenderTargetInfo},
+    vulkan::{
+        debug::DebugUtils,
+        destruction::{DestructionQueue, ReleasedObject},
+        device::DeviceManager,
+        rendering::{
+            AttachmentImage, RenderingAttachment, RenderingLayout, RenderingPath, RenderingTarget,
+        },
+    },
+};
 use thiserror::Error;
 
 use crate::{constants::CLEAR_COLOR, shader::create_shader_module};
@@ -7,48 +17,75 @@ use crate::{constants::CLEAR_COLOR, shader::create_shader_module};
 const VERT_SHADER_BYTES: &[u8] = include_bytes!("../shaders/final.vert.spv");
 const FRAG_SHADER_BYTES: &[u8] = include_bytes!("../shaders/final.frag.spv");
 
-const CLEAR_VALUES: [vk::ClearValue; 1] = [vk::ClearValue {
+const CLEAR_VALUE: vk::ClearValue = vk::ClearValue {
     color: vk::ClearColorValue {
         float32: CLEAR_COLOR,
     },
-}];
+};
+
+// Must match the OUTPUT_TRANSFER values in final.frag
+const OUTPUT_TRANSFER_GAMMA: u32 = 0;
+const OUTPUT_TRANSFER_PQ: u32 = 1;
+const OUTPUT_TRANSFER_LINEAR: u32 = 2;
 
 #[derive(Debug, Error)]
 pub enum FinalPassError {
-    #[error("Failed to create render pass: {0}")]
-    RenderPassCreationFailed(String),
+    #[error("Failed to create rendering layout: {0}")]
+    RenderingLayoutCreationFailed(vk::Result),
 
-    #[error("Failed to create framebuffer: {0}")]
-    FramebufferCreationFailed(String),
+    #[error("Failed to create rendering target: {0}")]
+    RenderingTargetCreationFailed(vk::Result),
 
     #[error("Failed to create sampler: {0}")]
-    SamplerCreationFailed(String),
+    SamplerCreationFailed(vk::Result),
 
     #[error("Failed to create descriptor set layout: {0}")]
-    DescriptorSetLayoutCreationFailed(String),
+    DescriptorSetLayoutCreationFailed(vk::Result),
 
     #[error("Failed to create descriptor pool: {0}")]
-    DescriptorPoolCreationFailed(String),
+    DescriptorPoolCreationFailed(vk::Result),
 
     #[error("Failed to allocate descriptor set: {0}")]
-    DescriptorSetAllocationFailed(String),
+    DescriptorSetAllocationFailed(vk::Result),
 
     #[error("Failed to create pipeline layout: {0}")]
-    PipelineLayoutCreationFailed(String),
+    PipelineLayoutCreationFailed(vk::Result),
 
     #[error("Failed to create shader module: {0}")]
-    ShaderModuleCreationFailed(String),
+    ShaderModuleCreationFailed(vk::Result),
 
     #[error("Failed to create pipeline: {0}")]
-    PipelineCreationFailed(String),
+    PipelineCreationFailed(vk::Result),
+}
+
+impl FinalPassError {
+    pub fn vk_result(&self) -> Option<vk::Result> {
+        match self {
+            Self::RenderingLayoutCreationFailed(result)
+            | Self::RenderingTargetCreationFailed(result)
+            | Self::SamplerCreationFailed(result)
+            | Self::DescriptorSetLayoutCreationFailed(result)
+            | Self::DescriptorPoolCreationFailed(result)
+            | Self::DescriptorSetAllocationFailed(result)
+            | Self::PipelineLayoutCreationFailed(result)
+            | Self::ShaderModuleCreationFailed(result)
+            | Self::PipelineCreationFailed(result) => Some(*result),
+        }
+    }
 }
 
 pub struct FinalPass {
     device: ash::Device,
+    destruction_queue: DestructionQueue,
+    debug_utils: DebugUtils,
+    // Kept for rebuilding the pipeline when the swapchain format changes
+    pipeline_cache: vk::PipelineCache,
+    rendering_path: RenderingPath,
 
-    render_pass: vk::RenderPass,
-    swapchain_framebuffers: Vec<vk::Framebuffer>,
-    render_area: vk::Rect2D,
+    rendering_layout: RenderingLayout,
+    rendering_targets: Vec<RenderingTarget>,
+    format: vk::Format,
+    color_space: vk::ColorSpaceKHR,
 
     sampler: vk::Sampler,
     descriptor_pool: vk::DescriptorPool,
@@ -61,70 +98,53 @@ pub struct FinalPass {
 
 impl FinalPass {
     pub fn new(
-        device: ash::Device,
-        swapchain_image_views: &Vec<vk::ImageView>,
-        surface_format: vk::Format,
-        image_extent: vk::Extent2D,
+        device_manager: &DeviceManager,
+        destruction_queue: &DestructionQueue,
+        pipeline_cache: vk::PipelineCache,
+        render_target: &RenderTargetInfo,
         color_image_view: vk::ImageView,
     ) -> Result<Self, FinalPassError> {
-        let color_attachment = vk::AttachmentDescription2::default()
-            .format(surface_format)
-            .load_op(vk::AttachmentLoadOp::CLEAR)
-            .store_op(vk::AttachmentStoreOp::STORE)
-            .stencil_load_op(vk::AttachmentLoadOp::DONT_CARE)
-            .stencil_store_op(vk::AttachmentStoreOp::DONT_CARE)
-            .initial_layout(vk::ImageLayout::UNDEFINED)
-            .final_layout(vk::ImageLayout::PRESENT_SRC_KHR)
-            .samples(vk::SampleCountFlags::TYPE_1);
-
-        let color_attachment_ref = vk::AttachmentReference2::default()
-            .attachment(0)
-            .layout(vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL)
-            .aspect_mask(vk::ImageAspectFlags::COLOR);
-
-        let subpass = vk::SubpassDescription2::default()
-            .color_attachments(std::slice::from_ref(&color_attachment_ref))
-            .pipeline_bind_point(vk::PipelineBindPoint::GRAPHICS);
-
-        let subpass_dependency = vk::SubpassDependency2::default()
-            .src_subpass(vk::SUBPASS_EXTERNAL)
-            .src_stage_mask(vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT)
-            .dst_subpass(0)
-            .dst_stage_mask(vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT)
-            .dst_access_mask(vk::AccessFlags::COLOR_ATTACHMENT_WRITE);
-
-        let render_pass_info = vk::RenderPassCreateInfo2::default()
-            .attachments(std::slice::from_ref(&color_attachment))
-            .subpasses(std::slice::from_ref(&subpass))
-            .dependencies(std::slice::from_ref(&subpass_dependency));
-
-        let render_pass = unsafe {
-            device
-                .create_render_pass2(&render_pass_info, None)
-                .map_err(|e| FinalPassError::RenderPassCreationFailed(e.to_string()))?
-        };
+        Self::create(
+            device_manager.device.clone(),
+            destruction_queue,
+            &device_manager.debug_utils,
+            pipeline_cache,
+            RenderingPath::for_features(&device_manager.enabled_features),
+            render_target,
+            color_image_view,
+        )
+    }
 
-        let mut swapchain_framebuffers = Vec::new();
-        for &view in swapchain_image_views.iter() {
-            let framebuffer_info = vk::FramebufferCreateInfo::default()
-                .render_pass(render_pass)
-                .attachments(std::slice::from_ref(&view))
-                .width(image_extent.width)
-                .height(image_extent.height)
-                .layers(1);
-            let framebuffer = unsafe {
-                device
-                    .create_framebuffer(&framebuffer_info, None)
-                    .map_err(|e| FinalPassError::FramebufferCreationFailed(e.to_string()))?
-            };
-            swapchain_framebuffers.push(framebuffer);
-        }
+    fn create(
+        device: ash::Device,
+        destruction_queue: &DestructionQueue,
+        debug_utils: &DebugUtils,
+        pipeline_cache: vk::PipelineCache,
+        rendering_path: RenderingPath,
+        render_target: &RenderTargetInfo,
+        color_image_view: vk::ImageView,
+    ) -> Result<Self, FinalPassError> {
+        let swapchain_image_views = &render_target.image_views;
+
+        let rendering_layout = RenderingLayout::new(
+            &device,
+            destruction_queue,
+            rendering_path,
+            &[
+                RenderingAttachment::new(render_target.format, render_target.final_layout)
+                    .clear_value(CLEAR_VALUE),
+            ],
+            None,
+        )
+        .map_err(FinalPassError::RenderingLayoutCreationFailed)?;
+
+        let rendering_targets = create_rendering_targets(&rendering_layout, render_target)?;
 
         let sampler_create_info = vk::SamplerCreateInfo::default();
         let sampler = unsafe {
             device
                 .create_sampler(&sampler_create_info, None)
-                .map_err(|e| FinalPassError::SamplerCreationFailed(e.to_string()))?
+                .map_err(FinalPassError::SamplerCreationFailed)?
         };
 
         let descriptor_set_layout_binding = vk::DescriptorSetLayoutBinding::default()
@@ -139,56 +159,16 @@ impl FinalPass {
         let descriptor_set_layout = unsafe {
             device
                 .create_descriptor_set_layout(&descriptor_set_layout_info, None)
-                .map_err(|e| FinalPassError::DescriptorSetLayoutCreationFailed(e.to_string()))?
+                .map_err(FinalPassError::DescriptorSetLayoutCreationFailed)?
         };
 
-        let pool_size = vk::DescriptorPoolSize {
-            ty: vk::DescriptorType::COMBINED_IMAGE_SAMPLER,
-            descriptor_count: swapchain_image_views.len() as u32,
-        };
-
-        let pool_info = vk::DescriptorPoolCreateInfo::default()
-            .pool_sizes(std::slice::from_ref(&pool_size))
-            .max_sets(swapchain_image_views.len() as u32)
-            .flags(vk::DescriptorPoolCreateFlags::empty());
-
-        let descriptor_pool = unsafe {
-            device
-                .create_descriptor_pool(&pool_info, None)
-                .map_err(|e| FinalPassError::DescriptorPoolCreationFailed(e.to_string()))?
-        };
-
-        let alloc_info = vk::DescriptorSetAllocateInfo::default()
-            .descriptor_pool(descriptor_pool)
-            .set_layouts(std::slice::from_ref(&descriptor_set_layout));
-
-        let mut descriptor_sets: Vec<vk::DescriptorSet> = vec![];
-
-        for _ in 0..swapchain_image_views.len() {
-            let descriptor_set = unsafe {
-                device
-                    .allocate_descriptor_sets(&alloc_info)
-                    .map_err(|e| FinalPassError::DescriptorSetAllocationFailed(e.to_string()))?
-                    [0]
-            };
-
-            let image_info = vk::DescriptorImageInfo::default()
-                .image_layout(vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL)
-                .image_view(color_image_view)
-                .sampler(sampler);
-
-            let write = vk::WriteDescriptorSet::default()
-                .dst_set(descriptor_set)
-                .dst_binding(0)
-                .descriptor_type(vk::DescriptorType::COMBINED_IMAGE_SAMPLER)
-                .image_info(std::slice::from_ref(&image_info));
-
-            unsafe {
-                device.update_descriptor_sets(&[write], &[]);
-            }
-
-            descriptor_sets.push(descriptor_set);
-        }
+        let (descriptor_pool, descriptor_sets) = create_descriptor_sets(
+            &device,
+            descriptor_set_layout,
+            sampler,
+            color_image_view,
+            swapchain_image_views.len(),
+        )?;
 
         let pipeline_layout_info = vk::PipelineLayoutCreateInfo::default()
             .set_layouts(std::slice::from_ref(&descriptor_set_layout));
@@ -196,17 +176,32 @@ impl FinalPass {
         let pipeline_layout = unsafe {
             device
                 .create_pipeline_layout(&pipeline_layout_info, None)
-                .map_err(|e| FinalPassError::PipelineLayoutCreationFailed(e.to_string()))?
+                .map_err(FinalPassError::PipelineLayoutCreationFailed)?
         };
 
         let vertex_shader_module = create_shader_module(&device, VERT_SHADER_BYTES)
-            .map_err(|e| FinalPassError::ShaderModuleCreationFailed(e.to_string()))?;
+            .map_err(FinalPassError::ShaderModuleCreationFailed)?;
 
         let fragment_shader_module = create_shader_module(&device, FRAG_SHADER_BYTES)
-            .map_err(|e| FinalPassError::ShaderModuleCreationFailed(e.to_string()))?;
+            .map_err(FinalPassError::ShaderModuleCreationFailed)?;
 
         let main_function_name = std::ffi::CString::new("main").unwrap();
 
+        let output_transfer = match render_target.color_space {
+            vk::ColorSpaceKHR::HDR10_ST2084_EXT => OUTPUT_TRANSFER_PQ,
+            vk::ColorSpaceKHR::EXTENDED_SRGB_LINEAR_EXT => OUTPUT_TRANSFER_LINEAR,
+            _ => OUTPUT_TRANSFER_GAMMA,
+        };
+
+        let specialization_map_entry = vk::SpecializationMapEntry::default()
+            .constant_id(0)
+            .offset(0)
+            .size(std::mem::size_of::<u32>());
+        let specialization_data = output_transfer.to_ne_bytes();
+        let specialization_info = vk::SpecializationInfo::default()
+            .map_entries(std::slice::from_ref(&specialization_map_entry))
+            .data(&specialization_data);
+
         let shader_stages = [
             vk::PipelineShaderStageCreateInfo::default()
                 .stage(vk::ShaderStageFlags::VERTEX)
@@ -215,7 +210,8 @@ impl FinalPass {
             vk::PipelineShaderStageCreateInfo::default()
                 .stage(vk::ShaderStageFlags::FRAGMENT)
                 .module(fragment_shader_module)
-                .name(&main_function_name),
+                .name(&main_function_name)
+                .specialization_info(&specialization_info),
         ];
 
         let vertex_input_info = vk::PipelineVertexInputStateCreateInfo::default();
@@ -223,23 +219,14 @@ impl FinalPass {
         let input_assembly_info = vk::PipelineInputAssemblyStateCreateInfo::default()
             .topology(vk::PrimitiveTopology::TRIANGLE_LIST);
 
-        let viewport = vk::Viewport {
-            x: 0.,
-            y: 0.,
-            width: image_extent.width as f32,
-            height: image_extent.height as f32,
-            min_depth: 0.,
-            max_depth: 1.,
-        };
-
-        let scissors = vk::Rect2D {
-            offset: vk::Offset2D { x: 0, y: 0 },
-            extent: image_extent,
-        };
-
+        // Set while recording, so resizing doesn't require a new pipeline
         let viewport_info = vk::PipelineViewportStateCreateInfo::default()
-            .viewports(std::slice::from_ref(&viewport))
-            .scissors(std::slice::from_ref(&scissors));
+            .viewport_count(1)
+            .scissor_count(1);
+
+        let dynamic_states = [vk::DynamicState::VIEWPORT, vk::DynamicState::SCISSOR];
+        let dynamic_state_info =
+            vk::PipelineDynamicStateCreateInfo::default().dynamic_states(&dynamic_states);
 
         let rasterizer_info = vk::PipelineRasterizationStateCreateInfo::default()
             .line_width(1.0)
@@ -268,22 +255,25 @@ impl FinalPass {
         let color_blend_info = vk::PipelineColorBlendStateCreateInfo::default()
             .attachments(std::slice::from_ref(&color_blend_attachment));
 
-        let pipeline_info = vk::GraphicsPipelineCreateInfo::default()
-            .stages(&shader_stages)
-            .vertex_input_state(&vertex_input_info)
-            .input_assembly_state(&input_assembly_info)
-            .viewport_state(&viewport_info)
-            .rasterization_state(&rasterizer_info)
-            .multisample_state(&multisampler_info)
-            .color_blend_state(&color_blend_info)
-            .layout(pipeline_layout)
-            .render_pass(render_pass)
-            .subpass(0);
+        let mut rendering_info = rendering_layout.pipeline_rendering_info();
+        let pipeline_info = rendering_layout.pipeline_info(
+            vk::GraphicsPipelineCreateInfo::default()
+                .stages(&shader_stages)
+                .vertex_input_state(&vertex_input_info)
+                .input_assembly_state(&input_assembly_info)
+                .viewport_state(&viewport_info)
+                .rasterization_state(&rasterizer_info)
+                .multisample_state(&multisampler_info)
+                .color_blend_state(&color_blend_info)
+                .dynamic_state(&dynamic_state_info)
+                .layout(pipeline_layout),
+            &mut rendering_info,
+        );
 
         let pipeline = unsafe {
             device
-                .create_graphics_pipelines(vk::PipelineCache::null(), &[pipeline_info], None)
-                .map_err(|e| FinalPassError::PipelineCreationFailed(e.1.to_string()))?
+                .create_graphics_pipelines(pipeline_cache, &[pipeline_info], None)
+                .map_err(|e| FinalPassError::PipelineCreationFailed(e.1))?
         }[0];
 
         unsafe {
@@ -291,13 +281,17 @@ impl FinalPass {
             device.destroy_shader_module(fragment_shader_module, None);
         }
 
-        Ok(Self {
+        let pass = Self {
             device,
-            render_pass,
-            swapchain_framebuffers,
-            render_area: vk::Rect2D::default()
-                .offset(vk::Offset2D::default())
-                .extent(image_extent),
+            destruction_queue: destruction_queue.clone(),
+            debug_utils: debug_utils.clone(),
+            pipeline_cache,
+            rendering_path,
+
+            rendering_layout,
+            rendering_targets,
+            format: render_target.format,
+            color_space: render_target.color_space,
 
             sampler,
             descriptor_pool,
@@ -306,32 +300,116 @@ impl FinalPass {
 
             pipeline_layout,
             pipeline,
-        })
+        };
+        pass.set_debug_names();
+
+        Ok(pass)
     }
 
-    pub fn record(&self, frame_context: &FrameContext) {
-        let render_pass_begin_info = vk::RenderPassBeginInfo::default()
-            .render_pass(self.render_pass)
-            .framebuffer(self.swapchain_framebuffers[frame_context.image_index])
-            .render_area(self.render_area)
-            .clear_values(&CLEAR_VALUES);
+    // Only a new format or color space needs a new rendering layout and pipeline; otherwise just
+    // the rendering targets and descriptor sets are replaced.
+    pub fn resize(
+        &mut self,
+        render_target: &RenderTargetInfo,
+        color_image_view: vk::ImageView,
+    ) -> Result<(), FinalPassError> {
+        if render_target.format != self.format || render_target.color_space != self.color_space {
+            *self = FinalPass::create(
+                self.device.clone(),
+                &self.destruction_queue,
+                &self.debug_utils,
+                self.pipeline_cache,
+                self.rendering_path,
+                render_target,
+                color_image_view,
+            )?;
+            return Ok(());
+        }
 
-        let subpass_begin_info =
-            vk::SubpassBeginInfo::default().contents(vk::SubpassContents::INLINE);
+        let rendering_targets = create_rendering_targets(&self.rendering_layout, render_target)?;
+        let (descriptor_pool, descriptor_sets) = create_descriptor_sets(
+            &self.device,
+            self.descriptor_set_layout,
+            self.sampler,
+            color_image_view,
+            render_target.image_views.len(),
+        )?;
+
+        self.rendering_targets = rendering_targets;
+        self.destruction_queue
+            .release(ReleasedObject::DescriptorPool(std::mem::replace(
+                &mut self.descriptor_pool,
+                descriptor_pool,
+            )));
+
+        self.descriptor_sets = descriptor_sets;
+        self.set_debug_names();
+
+        Ok(())
+    }
 
-        unsafe {
-            self.device.cmd_begin_render_pass2(
-                frame_context.command_buffer,
-                &render_pass_begin_info,
-                &subpass_begin_info,
+    fn set_debug_names(&self) {
+        if !self.debug_utils.is_enabled() {
+            return;
+        }
+
+        let debug_utils = &self.debug_utils;
+        debug_utils.set_object_name(self.sampler, "Final pass sampler");
+        debug_utils.set_object_name(self.descriptor_pool, "Final pass descriptor pool");
+        debug_utils.set_object_name(
+            self.descriptor_set_layout,
+            "Final pass descriptor set layout",
+        );
+        self.rendering_layout
+            .set_debug_name(debug_utils, "Final pass render pass");
+        debug_utils.set_object_name(self.pipeline_layout, "Final pass pipeline layout");
+        debug_utils.set_object_name(self.pipeline, "Final pass pipeline");
+
+        for (index, rendering_target) in self.rendering_targets.iter().enumerate() {
+            rendering_target
+                .set_debug_name(debug_utils, &format!("Final pass framebuffer {}", index));
+        }
+        for (index, &descriptor_set) in self.descriptor_sets.iter().enumerate() {
+            debug_utils.set_object_name(
+                descriptor_set,
+                &format!("Final pass descriptor set {}", index),
             );
+        }
+    }
 
+    pub fn record(&self, frame_context: &FrameContext) {
+        let _gpu_scope = frame_context.gpu_scope("Final pass");
+        let _debug_label = frame_context.debug_label("Final pass");
+
+        let rendering_target = &self.rendering_targets[frame_context.image_index];
+        let render_area = rendering_target.render_area();
+
+        self.rendering_layout
+            .begin(frame_context.command_buffer, rendering_target);
+
+        unsafe {
             self.device.cmd_bind_pipeline(
                 frame_context.command_buffer,
                 vk::PipelineBindPoint::GRAPHICS,
                 self.pipeline,
             );
 
+            self.device.cmd_set_viewport(
+                frame_context.command_buffer,
+                0,
+                &[vk::Viewport {
+                    x: 0.,
+                    y: 0.,
+                    width: render_area.extent.width as f32,
+                    height: render_area.extent.height as f32,
+                    min_depth: 0.,
+                    max_depth: 1.,
+                }],
+            );
+
+            self.device
+                .cmd_set_scissor(frame_context.command_buffer, 0, &[render_area]);
+
             self.device.cmd_bind_descriptor_sets(
                 frame_context.command_buffer,
                 vk::PipelineBindPoint::GRAPHICS,
@@ -343,35 +421,98 @@ impl FinalPass {
 
             self.device
                 .cmd_draw(frame_context.command_buffer, 3, 1, 0, 0);
-
-            self.device
-                .cmd_end_render_pass2(frame_context.command_buffer, &vk::SubpassEndInfo::default());
         }
+
+        self.rendering_layout
+            .end(frame_context.command_buffer, rendering_target);
     }
 }
 
 impl Drop for FinalPass {
     fn drop(&mut self) {
-        unsafe {
-            self.device
-                .device_wait_idle()
-                .expect("Failed to wait for device idle");
+        self.destruction_queue.release_all([
+            ReleasedObject::Pipeline(self.pipeline),
+            ReleasedObject::PipelineLayout(self.pipeline_layout),
+            ReleasedObject::Sampler(self.sampler),
+            ReleasedObject::DescriptorPool(self.descriptor_pool),
+            ReleasedObject::DescriptorSetLayout(self.descriptor_set_layout),
+        ]);
+    }
+}
 
-            self.device.destroy_pipeline(self.pipeline, None);
-            self.device
-                .destroy_pipeline_layout(self.pipeline_layout, None);
+fn create_rendering_targets(
+    rendering_layout: &RenderingLayout,
+    render_target: &RenderTargetInfo,
+) -> Result<Vec<RenderingTarget>, FinalPassError> {
+    render_target
+        .images
+        .iter()
+        .zip(&render_target.image_views)
+        .map(|(&image, &image_view)| {
+            rendering_layout
+                .create_target(
+                    &[AttachmentImage::new(image, image_view)],
+                    None,
+                    render_target.extent,
+                )
+                .map_err(FinalPassError::RenderingTargetCreationFailed)
+        })
+        .collect()
+}
 
-            self.device.destroy_sampler(self.sampler, None);
-            self.device
-                .destroy_descriptor_pool(self.descriptor_pool, None);
-            self.device
-                .destroy_descriptor_set_layout(self.descriptor_set_layout, None);
+fn create_descriptor_sets(
+    device: &ash::Device,
+    descriptor_set_layout: vk::DescriptorSetLayout,
+    sampler: vk::Sampler,
+    color_image_view: vk::ImageView,
+    count: usize,
+) -> Result<(vk::DescriptorPool, Vec<vk::DescriptorSet>), FinalPassError> {
+    let pool_size = vk::DescriptorPoolSize {
+        ty: vk::DescriptorType::COMBINED_IMAGE_SAMPLER,
+        descriptor_count: count as u32,
+    };
+
+    let pool_info = vk::DescriptorPoolCreateInfo::default()
+        .pool_sizes(std::slice::from_ref(&pool_size))
+        .max_sets(count as u32)
+        .flags(vk::DescriptorPoolCreateFlags::empty());
+
+    let descriptor_pool = unsafe {
+        device
+            .create_descriptor_pool(&pool_info, None)
+            .map_err(FinalPassError::DescriptorPoolCreationFailed)?
+    };
+
+    let alloc_info = vk::DescriptorSetAllocateInfo::default()
+        .descriptor_pool(descriptor_pool)
+        .set_layouts(std::slice::from_ref(&descriptor_set_layout));
+
+    let mut descriptor_sets: Vec<vk::DescriptorSet> = vec![];
+
+    for _ in 0..count {
+        let descriptor_set = unsafe {
+            device
+                .allocate_descriptor_sets(&alloc_info)
+                .map_err(FinalPassError::DescriptorSetAllocationFailed)?[0]
+        };
 
-            for &framebuffer in self.swapchain_framebuffers.iter() {
-                self.device.destroy_framebuffer(framebuffer, None);
-            }
+        let image_info = vk::DescriptorImageInfo::default()
+            .image_layout(vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL)
+            .image_view(color_image_view)
+            .sampler(sampler);
 
-            self.device.destroy_render_pass(self.render_pass, None);
+        let write = vk::WriteDescriptorSet::default()
+            .dst_set(descriptor_set)
+            .dst_binding(0)
+            .descriptor_type(vk::DescriptorType::COMBINED_IMAGE_SAMPLER)
+            .image_info(std::slice::from_ref(&image_info));
+
+        unsafe {
+            device.update_descriptor_sets(&[write], &[]);
         }
+
+        descriptor_sets.push(descriptor_set);
     }
+
+    Ok((descriptor_pool, descriptor_sets))
 }
@@ -1,37 +1,105 @@
+use std::mem::ManuallyDrop;
+
 use ash::vk;
+use bytemuck::{Pod, Zeroable};
 use eren_render_vulkan_core::{
+    constants::MAX_FRAMES_IN_FLIGHT,
     renderer::FrameContext,
-    vulkan::memory::{MemoryError, create_buffer_with_memory, create_image_with_memory},
+    vulkan::{
+        allocator::{Allocation, MemoryAllocator},
+        debug::DebugUtils,
+        destruction::{DestructionQueue, ReleasedObject},
+        device::DeviceManager,
+        memory::MemoryError,
+        readback::ReadbackSource,
+        rendering::{
+            AttachmentImage, RenderingAttachment, RenderingLayout, RenderingPath, RenderingTarget,
+        },
+    },
 };
 use thiserror::Error;
 
 use crate::{
-    constants::CLEAR_COLOR, render::render_item::RenderItem, shader::create_shader_module,
+    constants::CLEAR_COLOR,
+    passes::shadow_pass::{MAX_CASCADES, ShadowFilter},
+    render::{
+        render_item::RenderItem,
+        vertex::{Vertex, Vertex3D},
+    },
+    shader::create_shader_module,
 };
 
 const VERT_SHADER_BYTES: &[u8] = include_bytes!("../shaders/geometry.vert.spv");
 const FRAG_SHADER_BYTES: &[u8] = include_bytes!("../shaders/geometry.frag.spv");
 
-const CLEAR_VALUES: [vk::ClearValue; 2] = [
-    vk::ClearValue {
-        color: vk::ClearColorValue {
-            float32: CLEAR_COLOR,
-        },
-    },
-    vk::ClearValue {
-        depth_stencil: vk::ClearDepthStencilValue {
-            depth: 1.0,
-            stencil: 0,
-        },
+const CLEAR_VALUE: vk::ClearValue = vk::ClearValue {
+    color: vk::ClearColorValue {
+        float32: CLEAR_COLOR,
     },
-];
+};
+
+pub const DEPTH_FORMAT: vk::Format = vk::Format::D32_SFLOAT;
+
+// Cameras that can be drawn in one frame, each with its own slot in the camera buffer
+pub const MAX_CAMERAS: usize = 8;
+
+// The camera buffer holds `MAX_CAMERAS` slots for every frame in flight, so a frame's cameras can
+// be written while the GPU still reads those of earlier frames.
+const CAMERA_SLOT_COUNT: usize = MAX_CAMERAS * MAX_FRAMES_IN_FLIGHT;
+
+// 256 is the largest minUniformBufferOffsetAlignment a device may have, so this stride is a valid
+// dynamic offset everywhere.
+const CAMERA_SLOT_SIZE: vk::DeviceSize =
+    std::mem::size_of::<CameraUBO>().next_multiple_of(256) as vk::DeviceSize;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct GeometryPassSettings {
+    // Maps the near plane to depth 1.0 and the far plane to 0.0, which spreads float precision
+    // evenly over distance. The camera projection must be built to match.
+    pub reverse_z: bool,
+    pub shadow_filter: ShadowFilter,
+}
 
+// std140 layout of `CameraUBO` in the geometry shaders
+#[repr(C)]
+#[derive(Clone, Copy)]
 pub struct CameraUBO {
     pub view_proj: glam::Mat4,
-    pub light_view_proj: glam::Mat4,
+    // View of the camera the cascades were fitted to, which picks the cascade per fragment
+    pub shadow_view: glam::Mat4,
+    pub cascade_view_proj: [glam::Mat4; MAX_CASCADES],
+    // View space distance where each cascade ends
+    pub cascade_splits: [f32; MAX_CASCADES],
     pub light_dir: glam::Vec3,
-    // Alignment padding to satisfy std140 layout
-    pub _pad: f32,
+    pub cascade_count: u32,
+}
+
+// Matches `ShadowParams` in geometry.frag, placed after the vertex stage's model matrix
+#[repr(C)]
+#[derive(Clone, Copy, Pod, Zeroable)]
+struct ShadowPushConstants {
+    filter_mode: u32,
+    pcf_radius: u32,
+    light_size: f32,
+}
+
+const SHADOW_PUSH_CONSTANTS_OFFSET: u32 = 64;
+
+impl ShadowPushConstants {
+    fn new(filter: ShadowFilter) -> Self {
+        match filter {
+            ShadowFilter::Pcf { radius } => Self {
+                filter_mode: 0,
+                pcf_radius: radius,
+                light_size: 0.0,
+            },
+            ShadowFilter::Pcss { light_size } => Self {
+                filter_mode: 1,
+                pcf_radius: 0,
+                light_size,
+            },
+        }
+    }
 }
 
 #[derive(Debug, Error)]
@@ -40,57 +108,86 @@ pub enum GeometryPassError {
     CreateImageFailed(MemoryError),
 
     #[error("Failed to create image view: {0}")]
-    CreateImageViewFailed(String),
+    CreateImageViewFailed(vk::Result),
 
     #[error("Failed to create buffer: {0}")]
     CreateBufferFailed(MemoryError),
 
-    #[error("Failed to create render pass: {0}")]
-    RenderPassCreationFailed(String),
+    #[error("Failed to create rendering layout: {0}")]
+    RenderingLayoutCreationFailed(vk::Result),
 
-    #[error("Failed to create framebuffer: {0}")]
-    FramebufferCreationFailed(String),
+    #[error("Failed to create rendering target: {0}")]
+    RenderingTargetCreationFailed(vk::Result),
 
     #[error("Failed to create sampler: {0}")]
-    SamplerCreationFailed(String),
+    SamplerCreationFailed(vk::Result),
 
     #[error("Failed to create descriptor set layout: {0}")]
-    DescriptorSetLayoutCreationFailed(String),
+    DescriptorSetLayoutCreationFailed(vk::Result),
 
     #[error("Failed to create descriptor pool: {0}")]
-    DescriptorPoolCreationFailed(String),
+    DescriptorPoolCreationFailed(vk::Result),
 
     #[error("Failed to allocate descriptor set: {0}")]
-    DescriptorSetAllocationFailed(String),
+    DescriptorSetAllocationFailed(vk::Result),
 
     #[error("Failed to create pipeline layout: {0}")]
-    PipelineLayoutCreationFailed(String),
+    PipelineLayoutCreationFailed(vk::Result),
 
     #[error("Failed to create shader module: {0}")]
-    ShaderModuleCreationFailed(String),
+    ShaderModuleCreationFailed(vk::Result),
 
     #[error("Failed to create pipeline: {0}")]
-    PipelineCreationFailed(String),
+    PipelineCreationFailed(vk::Result),
 
     #[error("Failed to map memory: {0}")]
-    MemoryMappingFailed(String),
+    MemoryMappingFailed(MemoryError),
+}
+
+impl GeometryPassError {
+    pub fn vk_result(&self) -> Option<vk::Result> {
+        match self {
+            Self::CreateImageViewFailed(result)
+            | Self::RenderingLayoutCreationFailed(result)
+            | Self::RenderingTargetCreationFailed(result)
+            | Self::SamplerCreationFailed(result)
+            | Self::DescriptorSetLayoutCreationFailed(result)
+            | Self::DescriptorPoolCreationFailed(result)
+            | Self::DescriptorSetAllocationFailed(result)
+            | Self::PipelineLayoutCreationFailed(result)
+            | Self::ShaderModuleCreationFailed(result)
+            | Self::PipelineCreationFailed(result) => Some(*result),
+            Self::CreateImageFailed(e)
+            | Self::CreateBufferFailed(e)
+            | Self::MemoryMappingFailed(e) => e.vk_result(),
+        }
+    }
 }
 
 pub struct GeometryPass {
     device: ash::Device,
+    destruction_queue: DestructionQueue,
+    debug_utils: DebugUtils,
 
     color_image: vk::Image,
-    color_image_memory: vk::DeviceMemory,
+    color_image_allocation: ManuallyDrop<Allocation>,
     pub color_image_view: vk::ImageView,
+    color_format: vk::Format,
+
+    depth_image: vk::Image,
+    depth_image_allocation: ManuallyDrop<Allocation>,
+    // Left in DEPTH_STENCIL_READ_ONLY_OPTIMAL for later passes to sample
+    pub depth_image_view: vk::ImageView,
+    reverse_z: bool,
 
     camera_buffer: vk::Buffer,
-    camera_buffer_memory: vk::DeviceMemory,
+    camera_buffer_allocation: ManuallyDrop<Allocation>,
 
-    render_pass: vk::RenderPass,
-    framebuffer: vk::Framebuffer,
-    render_area: vk::Rect2D,
+    rendering_layout: RenderingLayout,
+    rendering_target: RenderingTarget,
 
     shadow_sampler: vk::Sampler,
+    shadow_depth_sampler: vk::Sampler,
     descriptor_pool: vk::DescriptorPool,
     camera_descriptor_set_layout: vk::DescriptorSetLayout,
     camera_descriptor_set: vk::DescriptorSet,
@@ -99,118 +196,75 @@ pub struct GeometryPass {
 
     pipeline_layout: vk::PipelineLayout,
     pipeline: vk::Pipeline,
+
+    shadow_filter: ShadowFilter,
 }
 
 impl GeometryPass {
     pub fn new(
-        instance: &ash::Instance,
-        physical_device: vk::PhysicalDevice,
-        device: ash::Device,
+        device_manager: &DeviceManager,
+        allocator: &MemoryAllocator,
+        destruction_queue: &DestructionQueue,
+        pipeline_cache: vk::PipelineCache,
         image_extent: vk::Extent2D,
         shadow_depth_image_view: vk::ImageView,
+        settings: &GeometryPassSettings,
     ) -> Result<Self, GeometryPassError> {
+        let device = device_manager.device.clone();
         let color_format = vk::Format::R8G8B8A8_UNORM;
 
-        let image_info = vk::ImageCreateInfo::default()
-            .image_type(vk::ImageType::TYPE_2D)
-            .format(color_format)
-            .extent(vk::Extent3D {
-                width: image_extent.width,
-                height: image_extent.height,
-                depth: 1,
-            })
-            .mip_levels(1)
-            .array_layers(1)
-            .samples(vk::SampleCountFlags::TYPE_1)
-            .tiling(vk::ImageTiling::OPTIMAL)
-            .usage(vk::ImageUsageFlags::COLOR_ATTACHMENT | vk::ImageUsageFlags::SAMPLED)
-            .initial_layout(vk::ImageLayout::UNDEFINED);
-
-        let (color_image, color_image_memory) = create_image_with_memory(
-            instance,
-            physical_device,
-            &device,
-            &image_info,
-            vk::MemoryPropertyFlags::DEVICE_LOCAL,
-        )
-        .map_err(|e| GeometryPassError::CreateImageFailed(e))?;
+        let (camera_buffer, camera_buffer_allocation) = allocator
+            .create_buffer(
+                CAMERA_SLOT_SIZE * CAMERA_SLOT_COUNT as vk::DeviceSize,
+                vk::BufferUsageFlags::UNIFORM_BUFFER,
+                vk::MemoryPropertyFlags::HOST_VISIBLE | vk::MemoryPropertyFlags::HOST_COHERENT,
+            )
+            .map_err(GeometryPassError::CreateBufferFailed)?;
 
-        let camera_buffer_size = std::mem::size_of::<CameraUBO>() as vk::DeviceSize;
-        let (camera_buffer, camera_buffer_memory) = create_buffer_with_memory(
-            instance,
-            physical_device,
+        let rendering_layout = RenderingLayout::new(
             &device,
-            camera_buffer_size,
-            vk::BufferUsageFlags::UNIFORM_BUFFER,
-            vk::MemoryPropertyFlags::HOST_VISIBLE | vk::MemoryPropertyFlags::HOST_COHERENT,
+            destruction_queue,
+            RenderingPath::for_features(&device_manager.enabled_features),
+            &[
+                RenderingAttachment::new(color_format, vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL)
+                    .clear_value(CLEAR_VALUE),
+            ],
+            Some(
+                RenderingAttachment::new(
+                    DEPTH_FORMAT,
+                    vk::ImageLayout::DEPTH_STENCIL_READ_ONLY_OPTIMAL,
+                )
+                .clear_value(depth_clear_value(settings.reverse_z)),
+            ),
         )
-        .map_err(|e| GeometryPassError::CreateBufferFailed(e))?;
-
-        let image_view_info = vk::ImageViewCreateInfo::default()
-            .image(color_image)
-            .view_type(vk::ImageViewType::TYPE_2D)
-            .format(color_format)
-            .subresource_range(
-                vk::ImageSubresourceRange::default()
-                    .aspect_mask(vk::ImageAspectFlags::COLOR)
-                    .base_mip_level(0)
-                    .level_count(1)
-                    .base_array_layer(0)
-                    .layer_count(1),
-            );
-
-        let color_image_view: vk::ImageView = unsafe {
-            device
-                .create_image_view(&image_view_info, None)
-                .map_err(|e| GeometryPassError::CreateImageViewFailed(e.to_string()))?
-        };
-
-        let color_attachment = vk::AttachmentDescription2::default()
-            .format(color_format)
-            .load_op(vk::AttachmentLoadOp::CLEAR)
-            .store_op(vk::AttachmentStoreOp::STORE)
-            .stencil_load_op(vk::AttachmentLoadOp::DONT_CARE)
-            .stencil_store_op(vk::AttachmentStoreOp::DONT_CARE)
-            .initial_layout(vk::ImageLayout::UNDEFINED)
-            .final_layout(vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL)
-            .samples(vk::SampleCountFlags::TYPE_1);
-
-        let color_attachment_ref = vk::AttachmentReference2::default()
-            .attachment(0)
-            .layout(vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL)
-            .aspect_mask(vk::ImageAspectFlags::COLOR);
-
-        let subpass = vk::SubpassDescription2::default()
-            .color_attachments(std::slice::from_ref(&color_attachment_ref))
-            .pipeline_bind_point(vk::PipelineBindPoint::GRAPHICS);
-
-        let render_pass_info = vk::RenderPassCreateInfo2::default()
-            .attachments(std::slice::from_ref(&color_attachment))
-            .subpasses(std::slice::from_ref(&subpass));
-
-        let render_pass = unsafe {
-            device
-                .create_render_pass2(&render_pass_info, None)
-                .map_err(|e| GeometryPassError::RenderPassCreationFailed(e.to_string()))?
-        };
-
-        let framebuffer_info = vk::FramebufferCreateInfo::default()
-            .render_pass(render_pass)
-            .attachments(std::slice::from_ref(&color_image_view))
-            .width(image_extent.width)
-            .height(image_extent.height)
-            .layers(1);
+        .map_err(GeometryPassError::RenderingLayoutCreationFailed)?;
 
-        let framebuffer = unsafe {
-            device
-                .create_framebuffer(&framebuffer_info, None)
-                .map_err(|e| GeometryPassError::FramebufferCreationFailed(e.to_string()))?
+        let (color_attachment, depth_attachment) = create_attachment_images(
+            &device,
+            allocator,
+            destruction_queue,
+            color_format,
+            image_extent,
+        )?;
+        let rendering_target = match rendering_layout.create_target(
+            &[AttachmentImage::new(color_attachment.0, color_attachment.2)],
+            Some(AttachmentImage::new(depth_attachment.0, depth_attachment.2)),
+            image_extent,
+        ) {
+            Ok(rendering_target) => rendering_target,
+            Err(e) => {
+                release_attachment_image(destruction_queue, color_attachment);
+                release_attachment_image(destruction_queue, depth_attachment);
+                return Err(GeometryPassError::RenderingTargetCreationFailed(e));
+            }
         };
+        let (color_image, color_image_allocation, color_image_view) = color_attachment;
+        let (depth_image, depth_image_allocation, depth_image_view) = depth_attachment;
 
         let camera_descriptor_set_layout_binding = vk::DescriptorSetLayoutBinding::default()
             .binding(0)
             .descriptor_count(1)
-            .descriptor_type(vk::DescriptorType::UNIFORM_BUFFER)
+            .descriptor_type(vk::DescriptorType::UNIFORM_BUFFER_DYNAMIC)
             .stage_flags(vk::ShaderStageFlags::VERTEX | vk::ShaderStageFlags::FRAGMENT);
 
         let camera_descriptor_set_layout_info = vk::DescriptorSetLayoutCreateInfo::default()
@@ -219,16 +273,19 @@ impl GeometryPass {
         let camera_descriptor_set_layout = unsafe {
             device
                 .create_descriptor_set_layout(&camera_descriptor_set_layout_info, None)
-                .map_err(|e| GeometryPassError::DescriptorSetLayoutCreationFailed(e.to_string()))?
+                .map_err(GeometryPassError::DescriptorSetLayoutCreationFailed)?
         };
 
+        // Outside the map reads as depth 1.0, i.e. lit
         let shadow_sampler_info = vk::SamplerCreateInfo {
             mag_filter: vk::Filter::LINEAR,
             min_filter: vk::Filter::LINEAR,
             mipmap_mode: vk::SamplerMipmapMode::NEAREST,
-            address_mode_u: vk::SamplerAddressMode::CLAMP_TO_EDGE,
-            address_mode_v: vk::SamplerAddressMode::CLAMP_TO_EDGE,
-            address_mode_w: vk::SamplerAddressMode::CLAMP_TO_EDGE,
+            address_mode_u: vk::SamplerAddressMode::CLAMP_TO_BORDER,
+            address_mode_v: vk::SamplerAddressMode::CLAMP_TO_BORDER,
+            address_mode_w: vk::SamplerAddressMode::CLAMP_TO_BORDER,
+            compare_enable: vk::TRUE,
+            compare_op: vk::CompareOp::LESS_OR_EQUAL,
             border_color: vk::BorderColor::FLOAT_OPAQUE_WHITE,
             min_lod: 0.0,
             max_lod: 1.0,
@@ -238,32 +295,52 @@ impl GeometryPass {
         let shadow_sampler = unsafe {
             device
                 .create_sampler(&shadow_sampler_info, None)
-                .map_err(|e| GeometryPassError::SamplerCreationFailed(e.to_string()))?
+                .map_err(GeometryPassError::SamplerCreationFailed)?
         };
 
-        let shadow_descriptor_set_layout_binding = vk::DescriptorSetLayoutBinding::default()
-            .binding(0)
-            .descriptor_count(1)
-            .descriptor_type(vk::DescriptorType::COMBINED_IMAGE_SAMPLER)
-            .stage_flags(vk::ShaderStageFlags::FRAGMENT);
+        let shadow_depth_sampler_info = vk::SamplerCreateInfo {
+            mag_filter: vk::Filter::NEAREST,
+            min_filter: vk::Filter::NEAREST,
+            compare_enable: vk::FALSE,
+            ..shadow_sampler_info
+        };
+
+        let shadow_depth_sampler = unsafe {
+            device
+                .create_sampler(&shadow_depth_sampler_info, None)
+                .map_err(GeometryPassError::SamplerCreationFailed)?
+        };
+
+        let shadow_descriptor_set_layout_bindings = [
+            vk::DescriptorSetLayoutBinding::default()
+                .binding(0)
+                .descriptor_count(1)
+                .descriptor_type(vk::DescriptorType::COMBINED_IMAGE_SAMPLER)
+                .stage_flags(vk::ShaderStageFlags::FRAGMENT),
+            vk::DescriptorSetLayoutBinding::default()
+                .binding(1)
+                .descriptor_count(1)
+                .descriptor_type(vk::DescriptorType::COMBINED_IMAGE_SAMPLER)
+                .stage_flags(vk::ShaderStageFlags::FRAGMENT),
+        ];
 
         let shadow_descriptor_set_layout_info = vk::DescriptorSetLayoutCreateInfo::default()
-            .bindings(std::slice::from_ref(&shadow_descriptor_set_layout_binding));
+            .bindings(&shadow_descriptor_set_layout_bindings);
 
         let shadow_descriptor_set_layout = unsafe {
             device
                 .create_descriptor_set_layout(&shadow_descriptor_set_layout_info, None)
-                .map_err(|e| GeometryPassError::DescriptorSetLayoutCreationFailed(e.to_string()))?
+                .map_err(GeometryPassError::DescriptorSetLayoutCreationFailed)?
         };
 
         let pool_sizes = [
             vk::DescriptorPoolSize {
-                ty: vk::DescriptorType::UNIFORM_BUFFER,
+                ty: vk::DescriptorType::UNIFORM_BUFFER_DYNAMIC,
                 descriptor_count: 1,
             },
             vk::DescriptorPoolSize {
                 ty: vk::DescriptorType::COMBINED_IMAGE_SAMPLER,
-                descriptor_count: 1,
+                descriptor_count: 2,
             },
         ];
 
@@ -274,7 +351,7 @@ impl GeometryPass {
         let descriptor_pool = unsafe {
             device
                 .create_descriptor_pool(&pool_info, None)
-                .map_err(|e| GeometryPassError::DescriptorPoolCreationFailed(e.to_string()))?
+                .map_err(GeometryPassError::DescriptorPoolCreationFailed)?
         };
 
         let camera_alloc_info = vk::DescriptorSetAllocateInfo::default()
@@ -284,18 +361,18 @@ impl GeometryPass {
         let camera_descriptor_set = unsafe {
             device
                 .allocate_descriptor_sets(&camera_alloc_info)
-                .map_err(|e| GeometryPassError::DescriptorSetAllocationFailed(e.to_string()))?[0]
+                .map_err(GeometryPassError::DescriptorSetAllocationFailed)?[0]
         };
 
         let camera_buffer_info = vk::DescriptorBufferInfo::default()
             .buffer(camera_buffer)
             .offset(0)
-            .range(camera_buffer_size);
+            .range(std::mem::size_of::<CameraUBO>() as vk::DeviceSize);
 
         let camera_write = vk::WriteDescriptorSet::default()
             .dst_set(camera_descriptor_set)
             .dst_binding(0)
-            .descriptor_type(vk::DescriptorType::UNIFORM_BUFFER)
+            .descriptor_type(vk::DescriptorType::UNIFORM_BUFFER_DYNAMIC)
             .buffer_info(std::slice::from_ref(&camera_buffer_info));
 
         unsafe {
@@ -309,47 +386,62 @@ impl GeometryPass {
         let shadow_descriptor_set = unsafe {
             device
                 .allocate_descriptor_sets(&shadow_alloc_info)
-                .map_err(|e| GeometryPassError::DescriptorSetAllocationFailed(e.to_string()))?[0]
+                .map_err(GeometryPassError::DescriptorSetAllocationFailed)?[0]
         };
 
-        let shadow_image_info = vk::DescriptorImageInfo::default()
-            .image_layout(vk::ImageLayout::DEPTH_STENCIL_READ_ONLY_OPTIMAL)
-            .image_view(shadow_depth_image_view)
-            .sampler(shadow_sampler);
-
-        let shadow_write = vk::WriteDescriptorSet::default()
-            .dst_set(shadow_descriptor_set)
-            .dst_binding(0)
-            .descriptor_type(vk::DescriptorType::COMBINED_IMAGE_SAMPLER)
-            .image_info(std::slice::from_ref(&shadow_image_info));
+        let shadow_image_infos = [shadow_sampler, shadow_depth_sampler].map(|sampler| {
+            vk::DescriptorImageInfo::default()
+                .image_layout(vk::ImageLayout::DEPTH_STENCIL_READ_ONLY_OPTIMAL)
+                .image_view(shadow_depth_image_view)
+                .sampler(sampler)
+        });
+
+        let shadow_writes = [
+            vk::WriteDescriptorSet::default()
+                .dst_set(shadow_descriptor_set)
+                .dst_binding(0)
+                .descriptor_type(vk::DescriptorType::COMBINED_IMAGE_SAMPLER)
+                .image_info(std::slice::from_ref(&shadow_image_infos[0])),
+            vk::WriteDescriptorSet::default()
+                .dst_set(shadow_descriptor_set)
+                .dst_binding(1)
+                .descriptor_type(vk::DescriptorType::COMBINED_IMAGE_SAMPLER)
+                .image_info(std::slice::from_ref(&shadow_image_infos[1])),
+        ];
 
         unsafe {
-            device.update_descriptor_sets(&[shadow_write], &[]);
+            device.update_descriptor_sets(&shadow_writes, &[]);
         }
 
         let set_layouts = [camera_descriptor_set_layout, shadow_descriptor_set_layout];
 
-        // Pipeline layout with descriptor set + push constant
-        let push_constant_range = vk::PushConstantRange::default()
-            .stage_flags(vk::ShaderStageFlags::VERTEX)
-            .offset(0)
-            .size(64); // mat4
+        // Pipeline layout with descriptor sets + push constants
+        let push_constant_ranges = [
+            vk::PushConstantRange::default()
+                .stage_flags(vk::ShaderStageFlags::VERTEX)
+                .offset(0)
+                .size(64), // mat4
+            vk::PushConstantRange::default()
+                .stage_flags(vk::ShaderStageFlags::FRAGMENT)
+                .offset(SHADOW_PUSH_CONSTANTS_OFFSET)
+                .size(std::mem::size_of::<ShadowPushConstants>() as u32),
+        ];
 
         let pipeline_layout_info = vk::PipelineLayoutCreateInfo::default()
             .set_layouts(&set_layouts)
-            .push_constant_ranges(std::slice::from_ref(&push_constant_range));
+            .push_constant_ranges(&push_constant_ranges);
 
         let pipeline_layout = unsafe {
             device
                 .create_pipeline_layout(&pipeline_layout_info, None)
-                .map_err(|e| GeometryPassError::PipelineLayoutCreationFailed(e.to_string()))?
+                .map_err(GeometryPassError::PipelineLayoutCreationFailed)?
         };
 
         let vertex_shader_module = create_shader_module(&device, VERT_SHADER_BYTES)
-            .map_err(|e| GeometryPassError::ShaderModuleCreationFailed(e.to_string()))?;
+            .map_err(GeometryPassError::ShaderModuleCreationFailed)?;
 
         let fragment_shader_module = create_shader_module(&device, FRAG_SHADER_BYTES)
-            .map_err(|e| GeometryPassError::ShaderModuleCreationFailed(e.to_string()))?;
+            .map_err(GeometryPassError::ShaderModuleCreationFailed)?;
 
         let main_function_name = std::ffi::CString::new("main").unwrap();
 
@@ -364,32 +456,8 @@ impl GeometryPass {
                 .name(&main_function_name),
         ];
 
-        let binding_description = vk::VertexInputBindingDescription {
-            binding: 0,
-            stride: std::mem::size_of::<[f32; 8]>() as u32,
-            input_rate: vk::VertexInputRate::VERTEX,
-        };
-
-        let attribute_descriptions = [
-            vk::VertexInputAttributeDescription {
-                location: 0,
-                binding: 0,
-                format: vk::Format::R32G32B32_SFLOAT,
-                offset: 0,
-            },
-            vk::VertexInputAttributeDescription {
-                location: 1,
-                binding: 0,
-                format: vk::Format::R32G32B32_SFLOAT,
-                offset: 12, // 3 * 4 bytes
-            },
-            vk::VertexInputAttributeDescription {
-                location: 2,
-                binding: 0,
-                format: vk::Format::R32G32_SFLOAT,
-                offset: 24, // 3 * 4 + 3 * 4 bytes
-            },
-        ];
+        let binding_description = Vertex3D::LAYOUT.binding_description();
+        let attribute_descriptions = Vertex3D::LAYOUT.attribute_descriptions();
 
         let vertex_input_info = vk::PipelineVertexInputStateCreateInfo::default()
             .vertex_binding_descriptions(std::slice::from_ref(&binding_description))
@@ -398,23 +466,14 @@ impl GeometryPass {
         let input_assembly_info = vk::PipelineInputAssemblyStateCreateInfo::default()
             .topology(vk::PrimitiveTopology::TRIANGLE_LIST);
 
-        let viewport = vk::Viewport {
-            x: 0.,
-            y: 0.,
-            width: image_extent.width as f32,
-            height: image_extent.height as f32,
-            min_depth: 0.,
-            max_depth: 1.,
-        };
-
-        let scissors = vk::Rect2D {
-            offset: vk::Offset2D { x: 0, y: 0 },
-            extent: image_extent,
-        };
-
+        // Set while recording, so resizing doesn't require a new pipeline
         let viewport_info = vk::PipelineViewportStateCreateInfo::default()
-            .viewports(std::slice::from_ref(&viewport))
-            .scissors(std::slice::from_ref(&scissors));
+            .viewport_count(1)
+            .scissor_count(1);
+
+        let dynamic_states = [vk::DynamicState::VIEWPORT, vk::DynamicState::SCISSOR];
+        let dynamic_state_info =
+            vk::PipelineDynamicStateCreateInfo::default().dynamic_states(&dynamic_states);
 
         let rasterizer_info = vk::PipelineRasterizationStateCreateInfo::default()
             .line_width(1.0)
@@ -422,6 +481,15 @@ impl GeometryPass {
             .cull_mode(vk::CullModeFlags::NONE)
             .polygon_mode(vk::PolygonMode::FILL);
 
+        let depth_stencil_info = vk::PipelineDepthStencilStateCreateInfo::default()
+            .depth_test_enable(true)
+            .depth_write_enable(true)
+            .depth_compare_op(if settings.reverse_z {
+                vk::CompareOp::GREATER_OR_EQUAL
+            } else {
+                vk::CompareOp::LESS_OR_EQUAL
+            });
+
         let multisampler_info = vk::PipelineMultisampleStateCreateInfo::default()
             .rasterization_samples(vk::SampleCountFlags::TYPE_1);
 
@@ -443,22 +511,26 @@ impl GeometryPass {
         let color_blend_info = vk::PipelineColorBlendStateCreateInfo::default()
             .attachments(std::slice::from_ref(&color_blend_attachment));
 
-        let pipeline_info = vk::GraphicsPipelineCreateInfo::default()
-            .stages(&shader_stages)
-            .vertex_input_state(&vertex_input_info)
-            .input_assembly_state(&input_assembly_info)
-            .viewport_state(&viewport_info)
-            .rasterization_state(&rasterizer_info)
-            .multisample_state(&multisampler_info)
-            .color_blend_state(&color_blend_info)
-            .layout(pipeline_layout)
-            .render_pass(render_pass)
-            .subpass(0);
+        let mut rendering_info = rendering_layout.pipeline_rendering_info();
+        let pipeline_info = rendering_layout.pipeline_info(
+            vk::GraphicsPipelineCreateInfo::default()
+                .stages(&shader_stages)
+                .vertex_input_state(&vertex_input_info)
+                .input_assembly_state(&input_assembly_info)
+                .viewport_state(&viewport_info)
+                .rasterization_state(&rasterizer_info)
+                .multisample_state(&multisampler_info)
+                .depth_stencil_state(&depth_stencil_info)
+                .color_blend_state(&color_blend_info)
+                .dynamic_state(&dynamic_state_info)
+                .layout(pipeline_layout),
+            &mut rendering_info,
+        );
 
         let pipeline = unsafe {
             device
-                .create_graphics_pipelines(vk::PipelineCache::null(), &[pipeline_info], None)
-                .map_err(|e| GeometryPassError::PipelineCreationFailed(e.1.to_string()))?
+                .create_graphics_pipelines(pipeline_cache, &[pipeline_info], None)
+                .map_err(|e| GeometryPassError::PipelineCreationFailed(e.1))?
         }[0];
 
         unsafe {
@@ -466,23 +538,29 @@ impl GeometryPass {
             device.destroy_shader_module(fragment_shader_module, None);
         }
 
-        Ok(Self {
+        let pass = Self {
             device,
+            destruction_queue: destruction_queue.clone(),
+            debug_utils: device_manager.debug_utils.clone(),
 
             color_image,
-            color_image_memory,
+            color_image_allocation: ManuallyDrop::new(color_image_allocation),
             color_image_view,
+            color_format,
+
+            depth_image,
+            depth_image_allocation: ManuallyDrop::new(depth_image_allocation),
+            depth_image_view,
+            reverse_z: settings.reverse_z,
 
             camera_buffer,
-            camera_buffer_memory,
+            camera_buffer_allocation: ManuallyDrop::new(camera_buffer_allocation),
 
-            render_pass,
-            framebuffer,
-            render_area: vk::Rect2D::default()
-                .offset(vk::Offset2D::default())
-                .extent(image_extent),
+            rendering_layout,
+            rendering_target,
 
             shadow_sampler,
+            shadow_depth_sampler,
             descriptor_pool,
             camera_descriptor_set_layout,
             camera_descriptor_set,
@@ -491,134 +569,386 @@ impl GeometryPass {
 
             pipeline_layout,
             pipeline,
-        })
+
+            shadow_filter: settings.shadow_filter,
+        };
+        pass.set_debug_names();
+
+        Ok(pass)
     }
 
-    pub fn upload_camera_buffer(&self, camera: &CameraUBO) -> Result<(), GeometryPassError> {
-        unsafe {
-            self.device
-                .map_memory(
-                    self.camera_buffer_memory,
-                    0,
-                    std::mem::size_of::<CameraUBO>() as vk::DeviceSize,
-                    vk::MemoryMapFlags::empty(),
-                )
-                .map_err(|e| GeometryPassError::MemoryMappingFailed(e.to_string()))
-                .and_then(|ptr| {
-                    std::ptr::copy_nonoverlapping(camera, ptr as *mut CameraUBO, 1);
-                    Ok(())
-                })
-                .map_err(|e| GeometryPassError::MemoryMappingFailed(e.to_string()))?;
-        }
+    // Replaces the color and depth targets; the old ones are released once frames in flight are
+    // done with them.
+    pub fn resize(
+        &mut self,
+        allocator: &MemoryAllocator,
+        image_extent: vk::Extent2D,
+    ) -> Result<(), GeometryPassError> {
+        let (color_attachment, depth_attachment) = create_attachment_images(
+            &self.device,
+            allocator,
+            &self.destruction_queue,
+            self.color_format,
+            image_extent,
+        )?;
+        self.rendering_target = match self.rendering_layout.create_target(
+            &[AttachmentImage::new(color_attachment.0, color_attachment.2)],
+            Some(AttachmentImage::new(depth_attachment.0, depth_attachment.2)),
+            image_extent,
+        ) {
+            Ok(rendering_target) => rendering_target,
+            Err(e) => {
+                release_attachment_image(&self.destruction_queue, color_attachment);
+                release_attachment_image(&self.destruction_queue, depth_attachment);
+                return Err(GeometryPassError::RenderingTargetCreationFailed(e));
+            }
+        };
+        let (color_image, color_image_allocation, color_image_view) = color_attachment;
+        let (depth_image, depth_image_allocation, depth_image_view) = depth_attachment;
+
+        let old_color_image_allocation = std::mem::replace(
+            &mut self.color_image_allocation,
+            ManuallyDrop::new(color_image_allocation),
+        );
+        let old_depth_image_allocation = std::mem::replace(
+            &mut self.depth_image_allocation,
+            ManuallyDrop::new(depth_image_allocation),
+        );
+
+        self.destruction_queue.release_all([
+            ReleasedObject::ImageView(std::mem::replace(
+                &mut self.color_image_view,
+                color_image_view,
+            )),
+            ReleasedObject::Image(std::mem::replace(&mut self.color_image, color_image)),
+            ReleasedObject::Allocation(ManuallyDrop::into_inner(old_color_image_allocation)),
+            ReleasedObject::ImageView(std::mem::replace(
+                &mut self.depth_image_view,
+                depth_image_view,
+            )),
+            ReleasedObject::Image(std::mem::replace(&mut self.depth_image, depth_image)),
+            ReleasedObject::Allocation(ManuallyDrop::into_inner(old_depth_image_allocation)),
+        ]);
+
+        self.set_debug_names();
 
         Ok(())
     }
 
-    pub fn record(&self, frame_context: &FrameContext, render_items: &[RenderItem]) {
-        let render_pass_begin_info = vk::RenderPassBeginInfo::default()
-            .render_pass(self.render_pass)
-            .framebuffer(self.framebuffer)
-            .render_area(self.render_area)
-            .clear_values(&CLEAR_VALUES);
+    pub fn color_readback_source(&self) -> ReadbackSource {
+        ReadbackSource {
+            image: self.color_image,
+            format: self.color_format,
+            color_space: vk::ColorSpaceKHR::SRGB_NONLINEAR,
+            extent: self.rendering_target.render_area().extent,
+            layout: vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
+        }
+    }
+
+    pub fn image_extent(&self) -> vk::Extent2D {
+        self.rendering_target.render_area().extent
+    }
 
-        let subpass_begin_info =
-            vk::SubpassBeginInfo::default().contents(vk::SubpassContents::INLINE);
+    pub fn is_reverse_z(&self) -> bool {
+        self.reverse_z
+    }
 
-        unsafe {
-            self.device.cmd_begin_render_pass2(
-                frame_context.command_buffer,
-                &render_pass_begin_info,
-                &subpass_begin_info,
-            );
+    pub fn set_shadow_filter(&mut self, shadow_filter: ShadowFilter) {
+        self.shadow_filter = shadow_filter;
+    }
 
+    // `camera_index` is the camera's index in the viewports passed to `record`. Only the slots of
+    // `frame_index` are written, which the GPU is done with once that frame's fence has signaled.
+    pub fn upload_camera_buffer(
+        &self,
+        frame_index: usize,
+        camera_index: usize,
+        camera: &CameraUBO,
+    ) -> Result<(), GeometryPassError> {
+        self.camera_buffer_allocation
+            .write(
+                camera_slot_offset(frame_index, camera_index),
+                std::slice::from_ref(camera),
+            )
+            .map_err(GeometryPassError::MemoryMappingFailed)
+    }
+
+    fn set_debug_names(&self) {
+        if !self.debug_utils.is_enabled() {
+            return;
+        }
+
+        let debug_utils = &self.debug_utils;
+        debug_utils.set_object_name(self.color_image, "Geometry pass color image");
+        debug_utils.set_object_name(self.color_image_view, "Geometry pass color image view");
+        debug_utils.set_object_name(self.depth_image, "Geometry pass depth image");
+        debug_utils.set_object_name(self.depth_image_view, "Geometry pass depth image view");
+        debug_utils.set_object_name(self.camera_buffer, "Geometry pass camera buffer");
+        debug_utils.set_object_name(self.shadow_sampler, "Geometry pass shadow sampler");
+        debug_utils.set_object_name(
+            self.shadow_depth_sampler,
+            "Geometry pass shadow depth sampler",
+        );
+        debug_utils.set_object_name(self.descriptor_pool, "Geometry pass descriptor pool");
+        debug_utils.set_object_name(
+            self.camera_descriptor_set_layout,
+            "Geometry pass camera descriptor set layout",
+        );
+        debug_utils.set_object_name(
+            self.camera_descriptor_set,
+            "Geometry pass camera descriptor set",
+        );
+        debug_utils.set_object_name(
+            self.shadow_descriptor_set_layout,
+            "Geometry pass shadow descriptor set layout",
+        );
+        debug_utils.set_object_name(
+            self.shadow_descriptor_set,
+            "Geometry pass shadow descriptor set",
+        );
+        self.rendering_layout
+            .set_debug_name(debug_utils, "Geometry pass render pass");
+        self.rendering_target
+            .set_debug_name(debug_utils, "Geometry pass framebuffer");
+        debug_utils.set_object_name(self.pipeline_layout, "Geometry pass pipeline layout");
+        debug_utils.set_object_name(self.pipeline, "Geometry pass pipeline");
+    }
+
+    // Draws the items once per camera, into that camera's part of the target. `viewports[i]` goes
+    // with the camera uploaded at index `i`.
+    pub fn record(
+        &self,
+        frame_context: &FrameContext,
+        render_items: &[RenderItem],
+        viewports: &[vk::Rect2D],
+    ) {
+        let _gpu_scope = frame_context.gpu_scope("Geometry pass");
+        let _debug_label = frame_context.debug_label("Geometry pass");
+
+        self.rendering_layout
+            .begin(frame_context.command_buffer, &self.rendering_target);
+
+        unsafe {
             self.device.cmd_bind_pipeline(
                 frame_context.command_buffer,
                 vk::PipelineBindPoint::GRAPHICS,
                 self.pipeline,
             );
 
-            self.device.cmd_bind_descriptor_sets(
+            self.device.cmd_push_constants(
                 frame_context.command_buffer,
-                vk::PipelineBindPoint::GRAPHICS,
                 self.pipeline_layout,
-                0,
-                &[self.camera_descriptor_set, self.shadow_descriptor_set],
-                &[],
+                vk::ShaderStageFlags::FRAGMENT,
+                SHADOW_PUSH_CONSTANTS_OFFSET,
+                bytemuck::bytes_of(&ShadowPushConstants::new(self.shadow_filter)),
             );
 
-            for render_item in render_items {
-                self.device.cmd_bind_vertex_buffers(
-                    frame_context.command_buffer,
-                    0,
-                    &[render_item.mesh.vertex_buffer],
-                    &[0],
-                );
+            for (camera_index, viewport) in viewports.iter().take(MAX_CAMERAS).enumerate() {
+                // Vulkan rejects empty viewports, e.g. of a camera squeezed out by rounding
+                if viewport.extent.width == 0 || viewport.extent.height == 0 {
+                    continue;
+                }
 
-                self.device.cmd_bind_index_buffer(
+                self.device.cmd_set_viewport(
                     frame_context.command_buffer,
-                    render_item.mesh.index_buffer,
                     0,
-                    vk::IndexType::UINT32,
+                    &[vk::Viewport {
+                        x: viewport.offset.x as f32,
+                        y: viewport.offset.y as f32,
+                        width: viewport.extent.width as f32,
+                        height: viewport.extent.height as f32,
+                        min_depth: 0.,
+                        max_depth: 1.,
+                    }],
                 );
 
-                let mat_ref: &[f32; 16] = std::mem::transmute(&render_item.transform);
-                let bytes: &[u8] = std::slice::from_raw_parts(
-                    mat_ref.as_ptr() as *const u8,
-                    std::mem::size_of::<[f32; 16]>(),
-                );
+                self.device
+                    .cmd_set_scissor(frame_context.command_buffer, 0, &[*viewport]);
 
-                self.device.cmd_push_constants(
+                self.device.cmd_bind_descriptor_sets(
                     frame_context.command_buffer,
+                    vk::PipelineBindPoint::GRAPHICS,
                     self.pipeline_layout,
-                    vk::ShaderStageFlags::VERTEX,
                     0,
-                    bytes,
+                    &[self.camera_descriptor_set, self.shadow_descriptor_set],
+                    &[camera_slot_offset(frame_context.frame_index, camera_index) as u32],
                 );
 
-                self.device.cmd_draw_indexed(
-                    frame_context.command_buffer,
-                    render_item.mesh.index_count,
-                    1,
-                    0,
-                    0,
-                    0,
-                );
+                for render_item in render_items {
+                    let mat_ref: &[f32; 16] = std::mem::transmute(&render_item.transform);
+                    let bytes: &[u8] = std::slice::from_raw_parts(
+                        mat_ref.as_ptr() as *const u8,
+                        std::mem::size_of::<[f32; 16]>(),
+                    );
+
+                    self.device.cmd_push_constants(
+                        frame_context.command_buffer,
+                        self.pipeline_layout,
+                        vk::ShaderStageFlags::VERTEX,
+                        0,
+                        bytes,
+                    );
+
+                    render_item.mesh.record_draw(frame_context.command_buffer);
+                }
             }
-
-            self.device
-                .cmd_end_render_pass2(frame_context.command_buffer, &vk::SubpassEndInfo::default());
         }
+
+        self.rendering_layout
+            .end(frame_context.command_buffer, &self.rendering_target);
     }
 }
 
 impl Drop for GeometryPass {
     fn drop(&mut self) {
-        unsafe {
-            self.device
-                .device_wait_idle()
-                .expect("Failed to wait for device idle");
-
-            self.device.destroy_pipeline(self.pipeline, None);
-            self.device
-                .destroy_pipeline_layout(self.pipeline_layout, None);
-
-            self.device.destroy_sampler(self.shadow_sampler, None);
-            self.device
-                .destroy_descriptor_pool(self.descriptor_pool, None);
-            self.device
-                .destroy_descriptor_set_layout(self.camera_descriptor_set_layout, None);
-            self.device
-                .destroy_descriptor_set_layout(self.shadow_descriptor_set_layout, None);
-
-            self.device.destroy_framebuffer(self.framebuffer, None);
-            self.device.destroy_render_pass(self.render_pass, None);
-
-            self.device.destroy_buffer(self.camera_buffer, None);
-            self.device.free_memory(self.camera_buffer_memory, None);
-
-            self.device.destroy_image_view(self.color_image_view, None);
-            self.device.destroy_image(self.color_image, None);
-            self.device.free_memory(self.color_image_memory, None);
+        // Frames still in flight may use these, so they are destroyed once those frames finish.
+        self.destruction_queue.release_all([
+            ReleasedObject::Pipeline(self.pipeline),
+            ReleasedObject::PipelineLayout(self.pipeline_layout),
+            ReleasedObject::Sampler(self.shadow_sampler),
+            ReleasedObject::Sampler(self.shadow_depth_sampler),
+            ReleasedObject::DescriptorPool(self.descriptor_pool),
+            ReleasedObject::DescriptorSetLayout(self.camera_descriptor_set_layout),
+            ReleasedObject::DescriptorSetLayout(self.shadow_descriptor_set_layout),
+            ReleasedObject::Buffer(self.camera_buffer),
+            ReleasedObject::Allocation(unsafe {
+                ManuallyDrop::take(&mut self.camera_buffer_allocation)
+            }),
+            ReleasedObject::ImageView(self.color_image_view),
+            ReleasedObject::Image(self.color_image),
+            ReleasedObject::Allocation(unsafe {
+                ManuallyDrop::take(&mut self.color_image_allocation)
+            }),
+            ReleasedObject::ImageView(self.depth_image_view),
+            ReleasedObject::Image(self.depth_image),
+            ReleasedObject::Allocation(unsafe {
+                ManuallyDrop::take(&mut self.depth_image_allocation)
+            }),
+        ]);
+    }
+}
+
+fn camera_slot_offset(frame_index: usize, camera_index: usize) -> vk::DeviceSize {
+    CAMERA_SLOT_SIZE * (frame_index * MAX_CAMERAS + camera_index) as vk::DeviceSize
+}
+
+fn depth_clear_value(reverse_z: bool) -> vk::ClearValue {
+    vk::ClearValue {
+        depth_stencil: vk::ClearDepthStencilValue {
+            depth: if reverse_z { 0.0 } else { 1.0 },
+            stencil: 0,
+        },
+    }
+}
+
+type AttachmentImageParts = (vk::Image, Allocation, vk::ImageView);
+
+// Either both attachments come back or neither is left behind.
+fn create_attachment_images(
+    device: &ash::Device,
+    allocator: &MemoryAllocator,
+    destruction_queue: &DestructionQueue,
+    color_format: vk::Format,
+    image_extent: vk::Extent2D,
+) -> Result<(AttachmentImageParts, AttachmentImageParts), GeometryPassError> {
+    let color_attachment = create_attachment_image(
+        device,
+        allocator,
+        destruction_queue,
+        color_format,
+        image_extent,
+    )?;
+
+    match create_attachment_image(
+        device,
+        allocator,
+        destruction_queue,
+        DEPTH_FORMAT,
+        image_extent,
+    ) {
+        Ok(depth_attachment) => Ok((color_attachment, depth_attachment)),
+        Err(e) => {
+            release_attachment_image(destruction_queue, color_attachment);
+            Err(e)
         }
     }
 }
+
+fn release_attachment_image(
+    destruction_queue: &DestructionQueue,
+    (image, image_allocation, image_view): AttachmentImageParts,
+) {
+    destruction_queue.release_all([
+        ReleasedObject::ImageView(image_view),
+        ReleasedObject::Image(image),
+        ReleasedObject::Allocation(image_allocation),
+    ]);
+}
+
+// Sampled by later passes either way; the color target is also read back.
+fn create_attachment_image(
+    device: &ash::Device,
+    allocator: &MemoryAllocator,
+    destruction_queue: &DestructionQueue,
+    format: vk::Format,
+    image_extent: vk::Extent2D,
+) -> Result<AttachmentImageParts, GeometryPassError> {
+    let (usage, aspect_mask) = if format == DEPTH_FORMAT {
+        (
+            vk::ImageUsageFlags::DEPTH_STENCIL_ATTACHMENT | vk::ImageUsageFlags::SAMPLED,
+            vk::ImageAspectFlags::DEPTH,
+        )
+    } else {
+        (
+            vk::ImageUsageFlags::COLOR_ATTACHMENT
+                | vk::ImageUsageFlags::SAMPLED
+                | vk::ImageUsageFlags::TRANSFER_SRC,
+            vk::ImageAspectFlags::COLOR,
+        )
+    };
+
+    let image_info = vk::ImageCreateInfo::default()
+        .image_type(vk::ImageType::TYPE_2D)
+        .format(format)
+        .extent(vk::Extent3D {
+            width: image_extent.width,
+            height: image_extent.height,
+            depth: 1,
+        })
+        .mip_levels(1)
+        .array_layers(1)
+        .samples(vk::SampleCountFlags::TYPE_1)
+        .tiling(vk::ImageTiling::OPTIMAL)
+        .usage(usage)
+        .initial_layout(vk::ImageLayout::UNDEFINED);
+
+    let (image, image_allocation) = allocator
+        .create_image(&image_info, vk::MemoryPropertyFlags::DEVICE_LOCAL)
+        .map_err(GeometryPassError::CreateImageFailed)?;
+
+    let image_view_info = vk::ImageViewCreateInfo::default()
+        .image(image)
+        .view_type(vk::ImageViewType::TYPE_2D)
+        .format(format)
+        .subresource_range(
+            vk::ImageSubresourceRange::default()
+                .aspect_mask(aspect_mask)
+                .base_mip_level(0)
+                .level_count(1)
+                .base_array_layer(0)
+                .layer_count(1),
+        );
+
+    let image_view = match unsafe { device.create_image_view(&image_view_info, None) } {
+        Ok(image_view) => image_view,
+        Err(e) => {
+            destruction_queue.release_all([
+                ReleasedObject::Image(image),
+                ReleasedObject::Allocation(image_allocation),
+            ]);
+            return Err(GeometryPassError::CreateImageViewFailed(e));
+        }
+    };
+
+    Ok((image, image_allocation, image_view))
+}
@@ -1,23 +1,90 @@
+use std::mem::ManuallyDrop;
+
 use ash::vk;
 use eren_render_vulkan_core::{
+    constants::MAX_FRAMES_IN_FLIGHT,
     renderer::FrameContext,
-    vulkan::memory::{MemoryError, create_buffer_with_memory, create_image_with_memory},
+    vulkan::{
+        allocator::{Allocation, MemoryAllocator},
+        debug::DebugUtils,
+        destruction::{DestructionQueue, ReleasedObject},
+        device::DeviceManager,
+        memory::MemoryError,
+        rendering::{
+            AttachmentImage, RenderingAttachment, RenderingLayout, RenderingPath, RenderingTarget,
+        },
+    },
 };
 use thiserror::Error;
 
-use crate::{render::render_item::RenderItem, shader::create_shader_module};
+use crate::{
+    render::{
+        render_item::RenderItem,
+        vertex::{Vertex, Vertex3D},
+    },
+    shader::create_shader_module,
+};
 
 const VERT_SHADER_BYTES: &[u8] = include_bytes!("../shaders/shadow.vert.spv");
 
-const CLEAR_VALUES: [vk::ClearValue; 1] = [vk::ClearValue {
+// Matches the array sizes in shadow.vert and geometry.frag
+pub const MAX_CASCADES: usize = 4;
+
+// One slot per frame in flight, padded so each slot starts at a valid dynamic offset
+const LIGHT_VP_SLOT_SIZE: vk::DeviceSize =
+    std::mem::size_of::<LightVP>().next_multiple_of(256) as vk::DeviceSize;
+
+const CLEAR_VALUE: vk::ClearValue = vk::ClearValue {
     depth_stencil: vk::ClearDepthStencilValue {
         depth: 1.0,
         stencil: 0,
     },
-}];
+};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ShadowFilter {
+    // Box kernel of hardware-filtered depth comparisons, `radius` texels on each side
+    Pcf { radius: u32 },
+    // Percentage-closer soft shadows: the kernel widens with the distance to the blocker.
+    // `light_size` is the penumbra width in shadow map UV per unit of light space depth.
+    Pcss { light_size: f32 },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ShadowSettings {
+    // Width and height of each cascade's layer, independent of the render target
+    pub map_size: u32,
+    // Up to `MAX_CASCADES`. Like `map_size`, fixed once the pass is created.
+    pub cascade_count: u32,
+    // Blend between uniform (0.0) and logarithmic (1.0) splits of the view distance
+    pub split_lambda: f32,
+    // Fits each cascade to a sphere snapped to whole texels, trading resolution for edges that
+    // don't shimmer when the camera moves
+    pub stabilize: bool,
+    // Constant and slope-scaled depth bias applied while rendering the map, against acne
+    pub depth_bias: f32,
+    pub slope_bias: f32,
+    pub filter: ShadowFilter,
+}
 
+impl Default for ShadowSettings {
+    fn default() -> Self {
+        Self {
+            map_size: 2048,
+            cascade_count: 4,
+            split_lambda: 0.75,
+            stabilize: true,
+            depth_bias: 1.25,
+            slope_bias: 1.75,
+            filter: ShadowFilter::Pcf { radius: 1 },
+        }
+    }
+}
+
+#[derive(Clone, Copy)]
 pub struct LightVP {
-    pub light_view_proj: glam::Mat4,
+    // One per cascade; only the first `ShadowSettings::cascade_count` are used
+    pub light_view_proj: [glam::Mat4; MAX_CASCADES],
 }
 
 #[derive(Debug, Error)]
@@ -26,69 +93,102 @@ pub enum ShadowPassError {
     CreateImageFailed(MemoryError),
 
     #[error("Failed to create image view: {0}")]
-    CreateImageViewFailed(String),
+    CreateImageViewFailed(vk::Result),
 
-    #[error("Failed to create render pass: {0}")]
-    CreateRenderPassFailed(String),
+    #[error("Failed to create rendering layout: {0}")]
+    RenderingLayoutCreationFailed(vk::Result),
 
-    #[error("Failed to create framebuffer: {0}")]
-    FramebufferCreationFailed(String),
+    #[error("Failed to create rendering target: {0}")]
+    RenderingTargetCreationFailed(vk::Result),
 
     #[error("Failed to create buffer: {0}")]
     CreateBufferFailed(MemoryError),
 
     #[error("Failed to create descriptor set layout: {0}")]
-    DescriptorSetLayoutCreationFailed(String),
+    DescriptorSetLayoutCreationFailed(vk::Result),
 
     #[error("Failed to create descriptor pool: {0}")]
-    DescriptorPoolCreationFailed(String),
+    DescriptorPoolCreationFailed(vk::Result),
 
     #[error("Failed to allocate descriptor set: {0}")]
-    DescriptorSetAllocationFailed(String),
+    DescriptorSetAllocationFailed(vk::Result),
 
     #[error("Failed to create pipeline layout: {0}")]
-    PipelineLayoutCreationFailed(String),
+    PipelineLayoutCreationFailed(vk::Result),
 
     #[error("Failed to create shader module: {0}")]
-    ShaderModuleCreationFailed(String),
+    ShaderModuleCreationFailed(vk::Result),
 
     #[error("Failed to create pipeline: {0}")]
-    PipelineCreationFailed(String),
+    PipelineCreationFailed(vk::Result),
 
     #[error("Failed to map memory: {0}")]
-    MemoryMappingFailed(String),
+    MemoryMappingFailed(MemoryError),
+}
+
+impl ShadowPassError {
+    pub fn vk_result(&self) -> Option<vk::Result> {
+        match self {
+            Self::CreateImageViewFailed(result)
+            | Self::RenderingLayoutCreationFailed(result)
+            | Self::RenderingTargetCreationFailed(result)
+            | Self::DescriptorSetLayoutCreationFailed(result)
+            | Self::DescriptorPoolCreationFailed(result)
+            | Self::DescriptorSetAllocationFailed(result)
+            | Self::PipelineLayoutCreationFailed(result)
+            | Self::ShaderModuleCreationFailed(result)
+            | Self::PipelineCreationFailed(result) => Some(*result),
+            Self::CreateImageFailed(e)
+            | Self::CreateBufferFailed(e)
+            | Self::MemoryMappingFailed(e) => e.vk_result(),
+        }
+    }
 }
 
 pub struct ShadowPass {
     device: ash::Device,
+    destruction_queue: DestructionQueue,
+    debug_utils: DebugUtils,
 
     depth_image: vk::Image,
-    depth_image_memory: vk::DeviceMemory,
+    depth_image_allocation: ManuallyDrop<Allocation>,
+    // All cascades, for sampling
     pub depth_image_view: vk::ImageView,
+    // One per cascade, to render into
+    cascade_image_views: Vec<vk::ImageView>,
 
     light_vp_buffer: vk::Buffer,
-    light_vp_buffer_memory: vk::DeviceMemory,
+    light_vp_buffer_allocation: ManuallyDrop<Allocation>,
 
     descriptor_pool: vk::DescriptorPool,
     descriptor_set_layout: vk::DescriptorSetLayout,
     descriptor_set: vk::DescriptorSet,
 
-    render_pass: vk::RenderPass,
-    framebuffer: vk::Framebuffer,
-    render_area: vk::Rect2D,
+    rendering_layout: RenderingLayout,
+    rendering_targets: Vec<RenderingTarget>,
 
     pipeline_layout: vk::PipelineLayout,
     pipeline: vk::Pipeline,
+
+    depth_bias: f32,
+    slope_bias: f32,
 }
 
 impl ShadowPass {
     pub fn new(
-        instance: &ash::Instance,
-        physical_device: vk::PhysicalDevice,
-        device: ash::Device,
-        image_extent: vk::Extent2D,
+        device_manager: &DeviceManager,
+        allocator: &MemoryAllocator,
+        destruction_queue: &DestructionQueue,
+        pipeline_cache: vk::PipelineCache,
+        settings: &ShadowSettings,
     ) -> Result<Self, ShadowPassError> {
+        let device = device_manager.device.clone();
         let depth_format = vk::Format::D32_SFLOAT;
+        let image_extent = vk::Extent2D {
+            width: settings.map_size,
+            height: settings.map_size,
+        };
+        let cascade_count = settings.cascade_count.clamp(1, MAX_CASCADES as u32);
 
         let image_info = vk::ImageCreateInfo::default()
             .image_type(vk::ImageType::TYPE_2D)
@@ -99,96 +199,79 @@ impl ShadowPass {
                 depth: 1,
             })
             .mip_levels(1)
-            .array_layers(1)
+            .array_layers(cascade_count)
             .samples(vk::SampleCountFlags::TYPE_1)
             .tiling(vk::ImageTiling::OPTIMAL)
             .usage(vk::ImageUsageFlags::DEPTH_STENCIL_ATTACHMENT | vk::ImageUsageFlags::SAMPLED)
             .sharing_mode(vk::SharingMode::EXCLUSIVE)
             .initial_layout(vk::ImageLayout::UNDEFINED);
 
-        let (depth_image, depth_image_memory) = create_image_with_memory(
-            instance,
-            physical_device,
-            &device,
-            &image_info,
-            vk::MemoryPropertyFlags::DEVICE_LOCAL,
-        )
-        .map_err(|e| ShadowPassError::CreateImageFailed(e))?;
-
-        let depth_image_view_info = vk::ImageViewCreateInfo::default()
-            .image(depth_image)
-            .view_type(vk::ImageViewType::TYPE_2D)
-            .format(depth_format)
-            .subresource_range(
-                vk::ImageSubresourceRange::default()
-                    .aspect_mask(vk::ImageAspectFlags::DEPTH)
-                    .base_mip_level(0)
-                    .level_count(1)
-                    .base_array_layer(0)
-                    .layer_count(1),
-            );
-
-        let depth_image_view: vk::ImageView = unsafe {
-            device
-                .create_image_view(&depth_image_view_info, None)
-                .map_err(|e| ShadowPassError::CreateImageViewFailed(e.to_string()))?
-        };
-
-        let depth_attachment = vk::AttachmentDescription2::default()
-            .format(depth_format)
-            .samples(vk::SampleCountFlags::TYPE_1)
-            .load_op(vk::AttachmentLoadOp::CLEAR)
-            .store_op(vk::AttachmentStoreOp::STORE)
-            .initial_layout(vk::ImageLayout::UNDEFINED)
-            .final_layout(vk::ImageLayout::DEPTH_STENCIL_READ_ONLY_OPTIMAL);
-
-        let depth_attachment_ref = vk::AttachmentReference2::default()
-            .attachment(0)
-            .layout(vk::ImageLayout::DEPTH_STENCIL_ATTACHMENT_OPTIMAL)
-            .aspect_mask(vk::ImageAspectFlags::DEPTH);
-
-        let subpass = vk::SubpassDescription2::default()
-            .pipeline_bind_point(vk::PipelineBindPoint::GRAPHICS)
-            .depth_stencil_attachment(&depth_attachment_ref);
+        let (depth_image, depth_image_allocation) = allocator
+            .create_image(&image_info, vk::MemoryPropertyFlags::DEVICE_LOCAL)
+            .map_err(ShadowPassError::CreateImageFailed)?;
 
-        let render_pass_info = vk::RenderPassCreateInfo2::default()
-            .attachments(std::slice::from_ref(&depth_attachment))
-            .subpasses(std::slice::from_ref(&subpass));
-
-        let render_pass = unsafe {
-            device
-                .create_render_pass2(&render_pass_info, None)
-                .map_err(|e| ShadowPassError::CreateRenderPassFailed(e.to_string()))?
-        };
-
-        let framebuffer_info = vk::FramebufferCreateInfo::default()
-            .render_pass(render_pass)
-            .attachments(std::slice::from_ref(&depth_image_view))
-            .width(image_extent.width)
-            .height(image_extent.height)
-            .layers(1);
-
-        let framebuffer = unsafe {
-            device
-                .create_framebuffer(&framebuffer_info, None)
-                .map_err(|e| ShadowPassError::FramebufferCreationFailed(e.to_string()))?
-        };
+        let depth_image_view = create_depth_image_view(
+            &device,
+            depth_image,
+            depth_format,
+            vk::ImageViewType::TYPE_2D_ARRAY,
+            0..cascade_count,
+        )?;
+
+        let cascade_image_views = (0..cascade_count)
+            .map(|cascade| {
+                create_depth_image_view(
+                    &device,
+                    depth_image,
+                    depth_format,
+                    vk::ImageViewType::TYPE_2D,
+                    cascade..cascade + 1,
+                )
+            })
+            .collect::<Result<Vec<_>, _>>()?;
 
-        let light_vp_buffer_size = std::mem::size_of::<LightVP>() as vk::DeviceSize;
-        let (light_vp_buffer, light_vp_buffer_memory) = create_buffer_with_memory(
-            instance,
-            physical_device,
+        let rendering_layout = RenderingLayout::new(
             &device,
-            light_vp_buffer_size,
-            vk::BufferUsageFlags::UNIFORM_BUFFER,
-            vk::MemoryPropertyFlags::HOST_VISIBLE | vk::MemoryPropertyFlags::HOST_COHERENT,
+            destruction_queue,
+            RenderingPath::for_features(&device_manager.enabled_features),
+            &[],
+            Some(
+                RenderingAttachment::new(
+                    depth_format,
+                    vk::ImageLayout::DEPTH_STENCIL_READ_ONLY_OPTIMAL,
+                )
+                .clear_value(CLEAR_VALUE),
+            ),
         )
-        .map_err(|e| ShadowPassError::CreateBufferFailed(e))?;
+        .map_err(ShadowPassError::RenderingLayoutCreationFailed)?;
+
+        let rendering_targets = cascade_image_views
+            .iter()
+            .zip(0..cascade_count)
+            .map(|(&cascade_image_view, cascade)| {
+                rendering_layout.create_target(
+                    &[],
+                    Some(
+                        AttachmentImage::new(depth_image, cascade_image_view).array_layer(cascade),
+                    ),
+                    image_extent,
+                )
+            })
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(ShadowPassError::RenderingTargetCreationFailed)?;
+
+        let (light_vp_buffer, light_vp_buffer_allocation) = allocator
+            .create_buffer(
+                LIGHT_VP_SLOT_SIZE * MAX_FRAMES_IN_FLIGHT as vk::DeviceSize,
+                vk::BufferUsageFlags::UNIFORM_BUFFER,
+                vk::MemoryPropertyFlags::HOST_VISIBLE | vk::MemoryPropertyFlags::HOST_COHERENT,
+            )
+            .map_err(ShadowPassError::CreateBufferFailed)?;
 
         // Descriptor Set Layout
         let ubo_layout_binding = vk::DescriptorSetLayoutBinding::default()
             .binding(0)
-            .descriptor_type(vk::DescriptorType::UNIFORM_BUFFER)
+            .descriptor_type(vk::DescriptorType::UNIFORM_BUFFER_DYNAMIC)
             .descriptor_count(1)
             .stage_flags(vk::ShaderStageFlags::VERTEX);
 
@@ -198,12 +281,12 @@ impl ShadowPass {
         let descriptor_set_layout = unsafe {
             device
                 .create_descriptor_set_layout(&layout_info, None)
-                .map_err(|e| ShadowPassError::DescriptorSetLayoutCreationFailed(e.to_string()))?
+                .map_err(ShadowPassError::DescriptorSetLayoutCreationFailed)?
         };
 
         // Descriptor Pool
         let pool_size = vk::DescriptorPoolSize {
-            ty: vk::DescriptorType::UNIFORM_BUFFER,
+            ty: vk::DescriptorType::UNIFORM_BUFFER_DYNAMIC,
             descriptor_count: 1,
         };
 
@@ -214,7 +297,7 @@ impl ShadowPass {
         let descriptor_pool = unsafe {
             device
                 .create_descriptor_pool(&descriptor_pool_info, None)
-                .map_err(|e| ShadowPassError::DescriptorPoolCreationFailed(e.to_string()))?
+                .map_err(ShadowPassError::DescriptorPoolCreationFailed)?
         };
 
         // Descriptor Set Allocation
@@ -225,19 +308,19 @@ impl ShadowPass {
         let descriptor_set = unsafe {
             device
                 .allocate_descriptor_sets(&alloc_info)
-                .map_err(|e| ShadowPassError::DescriptorSetAllocationFailed(e.to_string()))?[0]
+                .map_err(ShadowPassError::DescriptorSetAllocationFailed)?[0]
         };
 
         // Descriptor Write
         let buffer_info = vk::DescriptorBufferInfo::default()
             .buffer(light_vp_buffer)
             .offset(0)
-            .range(light_vp_buffer_size);
+            .range(std::mem::size_of::<LightVP>() as vk::DeviceSize);
 
         let write = vk::WriteDescriptorSet::default()
             .dst_set(descriptor_set)
             .dst_binding(0)
-            .descriptor_type(vk::DescriptorType::UNIFORM_BUFFER)
+            .descriptor_type(vk::DescriptorType::UNIFORM_BUFFER_DYNAMIC)
             .buffer_info(std::slice::from_ref(&buffer_info));
 
         unsafe {
@@ -248,7 +331,7 @@ impl ShadowPass {
         let push_constant_range = vk::PushConstantRange::default()
             .stage_flags(vk::ShaderStageFlags::VERTEX)
             .offset(0)
-            .size(64); // mat4
+            .size(68); // mat4 + cascade index
 
         let pipeline_layout_info = vk::PipelineLayoutCreateInfo::default()
             .set_layouts(std::slice::from_ref(&descriptor_set_layout))
@@ -257,11 +340,11 @@ impl ShadowPass {
         let pipeline_layout = unsafe {
             device
                 .create_pipeline_layout(&pipeline_layout_info, None)
-                .map_err(|e| ShadowPassError::PipelineLayoutCreationFailed(e.to_string()))?
+                .map_err(ShadowPassError::PipelineLayoutCreationFailed)?
         };
 
         let vertex_shader_module = create_shader_module(&device, VERT_SHADER_BYTES)
-            .map_err(|e| ShadowPassError::ShaderModuleCreationFailed(e.to_string()))?;
+            .map_err(ShadowPassError::ShaderModuleCreationFailed)?;
 
         let main_function_name = std::ffi::CString::new("main").unwrap();
 
@@ -270,20 +353,17 @@ impl ShadowPass {
             .module(vertex_shader_module)
             .name(&main_function_name);
 
-        let binding_description = vk::VertexInputBindingDescription::default()
-            .binding(0)
-            .stride(std::mem::size_of::<[f32; 3]>() as u32) // vec3 position
-            .input_rate(vk::VertexInputRate::VERTEX);
-
-        let attribute_description = vk::VertexInputAttributeDescription::default()
-            .location(0)
-            .binding(0)
-            .format(vk::Format::R32G32B32_SFLOAT)
-            .offset(0);
+        // Only the position is read, but the stride has to match the full vertex.
+        let binding_description = Vertex3D::LAYOUT.binding_description();
+        let attribute_descriptions: Vec<vk::VertexInputAttributeDescription> = Vertex3D::LAYOUT
+            .attribute_descriptions()
+            .into_iter()
+            .filter(|attribute| attribute.location == 0)
+            .collect();
 
         let vertex_input_info = vk::PipelineVertexInputStateCreateInfo::default()
             .vertex_binding_descriptions(std::slice::from_ref(&binding_description))
-            .vertex_attribute_descriptions(std::slice::from_ref(&attribute_description));
+            .vertex_attribute_descriptions(&attribute_descriptions);
 
         let input_assembly_info = vk::PipelineInputAssemblyStateCreateInfo::default()
             .topology(vk::PrimitiveTopology::TRIANGLE_LIST);
@@ -310,7 +390,13 @@ impl ShadowPass {
             .line_width(1.0)
             .front_face(vk::FrontFace::COUNTER_CLOCKWISE)
             .cull_mode(vk::CullModeFlags::BACK)
-            .polygon_mode(vk::PolygonMode::FILL);
+            .polygon_mode(vk::PolygonMode::FILL)
+            .depth_bias_enable(true);
+
+        // Set while recording, so the bias can be tuned without a new pipeline
+        let dynamic_states = [vk::DynamicState::DEPTH_BIAS];
+        let dynamic_state_info =
+            vk::PipelineDynamicStateCreateInfo::default().dynamic_states(&dynamic_states);
 
         let depth_stencil_info = vk::PipelineDepthStencilStateCreateInfo::default()
             .depth_test_enable(true)
@@ -320,173 +406,218 @@ impl ShadowPass {
         let multisampler_info = vk::PipelineMultisampleStateCreateInfo::default()
             .rasterization_samples(vk::SampleCountFlags::TYPE_1);
 
-        let pipeline_info = vk::GraphicsPipelineCreateInfo::default()
-            .stages(std::slice::from_ref(&shader_stage))
-            .vertex_input_state(&vertex_input_info)
-            .input_assembly_state(&input_assembly_info)
-            .viewport_state(&viewport_info)
-            .rasterization_state(&rasterizer_info)
-            .multisample_state(&multisampler_info)
-            .depth_stencil_state(&depth_stencil_info)
-            .layout(pipeline_layout)
-            .render_pass(render_pass)
-            .subpass(0);
+        let mut rendering_info = rendering_layout.pipeline_rendering_info();
+        let pipeline_info = rendering_layout.pipeline_info(
+            vk::GraphicsPipelineCreateInfo::default()
+                .stages(std::slice::from_ref(&shader_stage))
+                .vertex_input_state(&vertex_input_info)
+                .input_assembly_state(&input_assembly_info)
+                .viewport_state(&viewport_info)
+                .rasterization_state(&rasterizer_info)
+                .multisample_state(&multisampler_info)
+                .depth_stencil_state(&depth_stencil_info)
+                .dynamic_state(&dynamic_state_info)
+                .layout(pipeline_layout),
+            &mut rendering_info,
+        );
 
         let pipeline = unsafe {
             device
-                .create_graphics_pipelines(vk::PipelineCache::null(), &[pipeline_info], None)
-                .map_err(|e| ShadowPassError::PipelineCreationFailed(e.1.to_string()))?
+                .create_graphics_pipelines(pipeline_cache, &[pipeline_info], None)
+                .map_err(|e| ShadowPassError::PipelineCreationFailed(e.1))?
         }[0];
 
         unsafe { device.destroy_shader_module(vertex_shader_module, None) };
 
-        Ok(Self {
+        let pass = Self {
             device,
+            destruction_queue: destruction_queue.clone(),
+            debug_utils: device_manager.debug_utils.clone(),
 
             depth_image,
-            depth_image_memory,
+            depth_image_allocation: ManuallyDrop::new(depth_image_allocation),
             depth_image_view,
+            cascade_image_views,
 
             light_vp_buffer,
-            light_vp_buffer_memory,
+            light_vp_buffer_allocation: ManuallyDrop::new(light_vp_buffer_allocation),
 
             descriptor_pool,
             descriptor_set_layout,
             descriptor_set,
 
-            render_pass,
-            framebuffer,
-            render_area: vk::Rect2D::default()
-                .offset(vk::Offset2D::default())
-                .extent(image_extent),
+            rendering_layout,
+            rendering_targets,
 
             pipeline_layout,
             pipeline,
-        })
+
+            depth_bias: settings.depth_bias,
+            slope_bias: settings.slope_bias,
+        };
+        pass.set_debug_names();
+
+        Ok(pass)
     }
 
-    pub fn upload_light_vp_buffer(&self, light_vp: &LightVP) -> Result<(), ShadowPassError> {
-        unsafe {
-            self.device
-                .map_memory(
-                    self.light_vp_buffer_memory,
-                    0,
-                    std::mem::size_of::<LightVP>() as vk::DeviceSize,
-                    vk::MemoryMapFlags::empty(),
-                )
-                .map_err(|e| ShadowPassError::MemoryMappingFailed(e.to_string()))
-                .and_then(|ptr| {
-                    std::ptr::copy_nonoverlapping(light_vp, ptr as *mut LightVP, 1);
-                    Ok(())
-                })
-                .map_err(|e| ShadowPassError::MemoryMappingFailed(e.to_string()))?;
+    // Writes the slot `record` reads for frames with the same `FrameContext::frame_index`.
+    pub fn upload_light_vp_buffer(
+        &self,
+        frame_index: usize,
+        light_vp: &LightVP,
+    ) -> Result<(), ShadowPassError> {
+        self.light_vp_buffer_allocation
+            .write(
+                LIGHT_VP_SLOT_SIZE * frame_index as vk::DeviceSize,
+                std::slice::from_ref(light_vp),
+            )
+            .map_err(ShadowPassError::MemoryMappingFailed)
+    }
+
+    pub fn set_bias(&mut self, depth_bias: f32, slope_bias: f32) {
+        self.depth_bias = depth_bias;
+        self.slope_bias = slope_bias;
+    }
+
+    fn set_debug_names(&self) {
+        if !self.debug_utils.is_enabled() {
+            return;
         }
 
-        Ok(())
+        let debug_utils = &self.debug_utils;
+        debug_utils.set_object_name(self.depth_image, "Shadow pass depth image");
+        debug_utils.set_object_name(self.depth_image_view, "Shadow pass depth image view");
+        for cascade_image_view in &self.cascade_image_views {
+            debug_utils.set_object_name(*cascade_image_view, "Shadow pass cascade image view");
+        }
+        debug_utils.set_object_name(self.light_vp_buffer, "Shadow pass light VP buffer");
+        debug_utils.set_object_name(self.descriptor_pool, "Shadow pass descriptor pool");
+        debug_utils.set_object_name(
+            self.descriptor_set_layout,
+            "Shadow pass descriptor set layout",
+        );
+        debug_utils.set_object_name(self.descriptor_set, "Shadow pass descriptor set");
+        self.rendering_layout
+            .set_debug_name(debug_utils, "Shadow pass render pass");
+        for rendering_target in &self.rendering_targets {
+            rendering_target.set_debug_name(debug_utils, "Shadow pass cascade framebuffer");
+        }
+        debug_utils.set_object_name(self.pipeline_layout, "Shadow pass pipeline layout");
+        debug_utils.set_object_name(self.pipeline, "Shadow pass pipeline");
     }
 
     pub fn record(&self, frame_context: &FrameContext, render_items: &[RenderItem]) {
-        let render_pass_begin_info = vk::RenderPassBeginInfo::default()
-            .render_pass(self.render_pass)
-            .framebuffer(self.framebuffer)
-            .render_area(self.render_area)
-            .clear_values(&CLEAR_VALUES);
+        let _gpu_scope = frame_context.gpu_scope("Shadow pass");
+        let _debug_label = frame_context.debug_label("Shadow pass");
 
-        let subpass_begin_info =
-            vk::SubpassBeginInfo::default().contents(vk::SubpassContents::INLINE);
+        for (cascade, rendering_target) in self.rendering_targets.iter().enumerate() {
+            self.rendering_layout
+                .begin(frame_context.command_buffer, rendering_target);
 
-        unsafe {
-            self.device.cmd_begin_render_pass2(
-                frame_context.command_buffer,
-                &render_pass_begin_info,
-                &subpass_begin_info,
-            );
-
-            self.device.cmd_bind_pipeline(
-                frame_context.command_buffer,
-                vk::PipelineBindPoint::GRAPHICS,
-                self.pipeline,
-            );
-
-            self.device.cmd_bind_descriptor_sets(
-                frame_context.command_buffer,
-                vk::PipelineBindPoint::GRAPHICS,
-                self.pipeline_layout,
-                0,
-                &[self.descriptor_set],
-                &[],
-            );
-
-            for render_item in render_items {
-                self.device.cmd_bind_vertex_buffers(
+            unsafe {
+                self.device.cmd_bind_pipeline(
                     frame_context.command_buffer,
-                    0,
-                    &[render_item.mesh.vertex_buffer],
-                    &[0],
+                    vk::PipelineBindPoint::GRAPHICS,
+                    self.pipeline,
                 );
 
-                self.device.cmd_bind_index_buffer(
+                self.device.cmd_set_depth_bias(
                     frame_context.command_buffer,
-                    render_item.mesh.index_buffer,
-                    0,
-                    vk::IndexType::UINT32,
+                    self.depth_bias,
+                    0.0,
+                    self.slope_bias,
                 );
 
-                let mat_ref: &[f32; 16] = std::mem::transmute(&render_item.transform);
-                let bytes: &[u8] = std::slice::from_raw_parts(
-                    mat_ref.as_ptr() as *const u8,
-                    std::mem::size_of::<[f32; 16]>(),
+                self.device.cmd_bind_descriptor_sets(
+                    frame_context.command_buffer,
+                    vk::PipelineBindPoint::GRAPHICS,
+                    self.pipeline_layout,
+                    0,
+                    &[self.descriptor_set],
+                    &[(LIGHT_VP_SLOT_SIZE * frame_context.frame_index as vk::DeviceSize) as u32],
                 );
 
                 self.device.cmd_push_constants(
                     frame_context.command_buffer,
                     self.pipeline_layout,
                     vk::ShaderStageFlags::VERTEX,
-                    0,
-                    bytes,
+                    64,
+                    &(cascade as u32).to_ne_bytes(),
                 );
 
-                self.device.cmd_draw_indexed(
-                    frame_context.command_buffer,
-                    render_item.mesh.index_count,
-                    1,
-                    0,
-                    0,
-                    0,
-                );
+                for render_item in render_items {
+                    let mat_ref: &[f32; 16] = std::mem::transmute(&render_item.transform);
+                    let bytes: &[u8] = std::slice::from_raw_parts(
+                        mat_ref.as_ptr() as *const u8,
+                        std::mem::size_of::<[f32; 16]>(),
+                    );
+
+                    self.device.cmd_push_constants(
+                        frame_context.command_buffer,
+                        self.pipeline_layout,
+                        vk::ShaderStageFlags::VERTEX,
+                        0,
+                        bytes,
+                    );
+
+                    render_item.mesh.record_draw(frame_context.command_buffer);
+                }
             }
 
-            self.device
-                .cmd_end_render_pass2(frame_context.command_buffer, &vk::SubpassEndInfo::default());
+            self.rendering_layout
+                .end(frame_context.command_buffer, rendering_target);
         }
     }
 }
 
 impl Drop for ShadowPass {
     fn drop(&mut self) {
-        unsafe {
-            self.device
-                .device_wait_idle()
-                .expect("Failed to wait for device idle");
-
-            self.device.destroy_pipeline(self.pipeline, None);
-            self.device
-                .destroy_pipeline_layout(self.pipeline_layout, None);
-
-            self.device
-                .destroy_descriptor_pool(self.descriptor_pool, None);
-            self.device
-                .destroy_descriptor_set_layout(self.descriptor_set_layout, None);
-
-            self.device.destroy_buffer(self.light_vp_buffer, None);
-            self.device.free_memory(self.light_vp_buffer_memory, None);
-
-            self.device.destroy_framebuffer(self.framebuffer, None);
-            self.device.destroy_render_pass(self.render_pass, None);
+        self.destruction_queue.release_all(
+            self.cascade_image_views
+                .drain(..)
+                .map(ReleasedObject::ImageView),
+        );
+        self.destruction_queue.release_all([
+            ReleasedObject::Pipeline(self.pipeline),
+            ReleasedObject::PipelineLayout(self.pipeline_layout),
+            ReleasedObject::DescriptorPool(self.descriptor_pool),
+            ReleasedObject::DescriptorSetLayout(self.descriptor_set_layout),
+            ReleasedObject::Buffer(self.light_vp_buffer),
+            ReleasedObject::Allocation(unsafe {
+                ManuallyDrop::take(&mut self.light_vp_buffer_allocation)
+            }),
+            ReleasedObject::ImageView(self.depth_image_view),
+            ReleasedObject::Image(self.depth_image),
+            ReleasedObject::Allocation(unsafe {
+                ManuallyDrop::take(&mut self.depth_image_allocation)
+            }),
+        ]);
+    }
+}
 
-            self.device.destroy_image_view(self.depth_image_view, None);
-            self.device.destroy_image(self.depth_image, None);
-            self.device.free_memory(self.depth_image_memory, None);
-        }
+fn create_depth_image_view(
+    device: &ash::Device,
+    depth_image: vk::Image,
+    depth_format: vk::Format,
+    view_type: vk::ImageViewType,
+    layers: std::ops::Range<u32>,
+) -> Result<vk::ImageView, ShadowPassError> {
+    let image_view_info = vk::ImageViewCreateInfo::default()
+        .image(depth_image)
+        .view_type(view_type)
+        .format(depth_format)
+        .subresource_range(
+            vk::ImageSubresourceRange::default()
+                .aspect_mask(vk::ImageAspectFlags::DEPTH)
+                .base_mip_level(0)
+                .level_count(1)
+                .base_array_layer(layers.start)
+                .layer_count(layers.len() as u32),
+        );
+
+    unsafe {
+        device
+            .create_image_view(&image_view_info, None)
+            .map_err(ShadowPassError::CreateImageViewFailed)
     }
 }
@@ -1,7 +1,18 @@
+use std::mem::ManuallyDrop;
+
 use ash::vk;
 use eren_render_vulkan_core::{
     renderer::FrameContext,
-    vulkan::memory::{MemoryError, create_image_with_memory},
+    vulkan::{
+        allocator::{Allocation, MemoryAllocator},
+        debug::DebugUtils,
+        destruction::{DestructionQueue, ReleasedObject},
+        device::DeviceManager,
+        memory::MemoryError,
+        rendering::{
+            AttachmentImage, RenderingAttachment, RenderingLayout, RenderingPath, RenderingTarget,
+        },
+    },
 };
 use thiserror::Error;
 
@@ -10,19 +21,11 @@ use crate::{constants::CLEAR_COLOR, shader::create_shader_module};
 const VERT_SHADER_BYTES: &[u8] = include_bytes!("../shaders/test.vert.spv");
 const FRAG_SHADER_BYTES: &[u8] = include_bytes!("../shaders/test.frag.spv");
 
-const CLEAR_VALUES: [vk::ClearValue; 2] = [
-    vk::ClearValue {
-        color: vk::ClearColorValue {
-            float32: CLEAR_COLOR,
-        },
+const CLEAR_VALUE: vk::ClearValue = vk::ClearValue {
+    color: vk::ClearColorValue {
+        float32: CLEAR_COLOR,
     },
-    vk::ClearValue {
-        depth_stencil: vk::ClearDepthStencilValue {
-            depth: 1.0,
-            stencil: 0,
-        },
-    },
-];
+};
 
 #[derive(Debug, Error)]
 pub enum TestPassError {
@@ -30,37 +33,54 @@ pub enum TestPassError {
     CreateImageFailed(MemoryError),
 
     #[error("Failed to create image view: {0}")]
-    CreateImageViewFailed(String),
+    CreateImageViewFailed(vk::Result),
 
-    #[error("Failed to create render pass: {0}")]
-    RenderPassCreationFailed(String),
+    #[error("Failed to create rendering layout: {0}")]
+    RenderingLayoutCreationFailed(vk::Result),
 
-    #[error("Failed to create framebuffer: {0}")]
-    FramebufferCreationFailed(String),
+    #[error("Failed to create rendering target: {0}")]
+    RenderingTargetCreationFailed(vk::Result),
 
     #[error("Failed to create descriptor set layout: {0}")]
-    DescriptorSetLayoutCreationFailed(String),
+    DescriptorSetLayoutCreationFailed(vk::Result),
 
     #[error("Failed to create pipeline layout: {0}")]
-    PipelineLayoutCreationFailed(String),
+    PipelineLayoutCreationFailed(vk::Result),
 
     #[error("Failed to create shader module: {0}")]
-    ShaderModuleCreationFailed(String),
+    ShaderModuleCreationFailed(vk::Result),
 
     #[error("Failed to create pipeline: {0}")]
-    PipelineCreationFailed(String),
+    PipelineCreationFailed(vk::Result),
+}
+
+impl TestPassError {
+    pub fn vk_result(&self) -> Option<vk::Result> {
+        match self {
+            Self::CreateImageViewFailed(result)
+            | Self::RenderingLayoutCreationFailed(result)
+            | Self::RenderingTargetCreationFailed(result)
+            | Self::DescriptorSetLayoutCreationFailed(result)
+            | Self::PipelineLayoutCreationFailed(result)
+            | Self::ShaderModuleCreationFailed(result)
+            | Self::PipelineCreationFailed(result) => Some(*result),
+            Self::CreateImageFailed(e) => e.vk_result(),
+        }
+    }
 }
 
 pub struct TestPass {
     device: ash::Device,
+    destruction_queue: DestructionQueue,
+    debug_utils: DebugUtils,
 
     color_image: vk::Image,
-    color_image_memory: vk::DeviceMemory,
+    color_image_allocation: ManuallyDrop<Allocation>,
     pub color_image_view: vk::ImageView,
+    color_format: vk::Format,
 
-    render_pass: vk::RenderPass,
-    framebuffer: vk::Framebuffer,
-    render_area: vk::Rect2D,
+    rendering_layout: RenderingLayout,
+    rendering_target: RenderingTarget,
 
     pipeline_layout: vk::PipelineLayout,
     pipeline: vk::Pipeline,
@@ -68,111 +88,48 @@ pub struct TestPass {
 
 impl TestPass {
     pub fn new(
-        instance: &ash::Instance,
-        physical_device: vk::PhysicalDevice,
-        device: ash::Device,
+        device_manager: &DeviceManager,
+        allocator: &MemoryAllocator,
+        destruction_queue: &DestructionQueue,
+        pipeline_cache: vk::PipelineCache,
         image_extent: vk::Extent2D,
     ) -> Result<Self, TestPassError> {
+        let device = device_manager.device.clone();
         let color_format = vk::Format::R8G8B8A8_UNORM;
 
-        let image_info = vk::ImageCreateInfo::default()
-            .image_type(vk::ImageType::TYPE_2D)
-            .format(color_format)
-            .extent(vk::Extent3D {
-                width: image_extent.width,
-                height: image_extent.height,
-                depth: 1,
-            })
-            .mip_levels(1)
-            .array_layers(1)
-            .samples(vk::SampleCountFlags::TYPE_1)
-            .tiling(vk::ImageTiling::OPTIMAL)
-            .usage(vk::ImageUsageFlags::COLOR_ATTACHMENT | vk::ImageUsageFlags::SAMPLED)
-            .initial_layout(vk::ImageLayout::UNDEFINED);
-
-        let (image, image_memory) = create_image_with_memory(
-            instance,
-            physical_device,
+        let rendering_layout = RenderingLayout::new(
             &device,
-            &image_info,
-            vk::MemoryPropertyFlags::DEVICE_LOCAL,
+            destruction_queue,
+            RenderingPath::for_features(&device_manager.enabled_features),
+            &[
+                RenderingAttachment::new(color_format, vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL)
+                    .clear_value(CLEAR_VALUE),
+            ],
+            None,
         )
-        .map_err(|e| TestPassError::CreateImageFailed(e))?;
-
-        let image_view_info = vk::ImageViewCreateInfo::default()
-            .image(image)
-            .view_type(vk::ImageViewType::TYPE_2D)
-            .format(color_format)
-            .subresource_range(
-                vk::ImageSubresourceRange::default()
-                    .aspect_mask(vk::ImageAspectFlags::COLOR)
-                    .base_mip_level(0)
-                    .level_count(1)
-                    .base_array_layer(0)
-                    .layer_count(1),
-            );
-
-        let image_view: vk::ImageView = unsafe {
-            device
-                .create_image_view(&image_view_info, None)
-                .map_err(|e| TestPassError::CreateImageViewFailed(e.to_string()))?
-        };
+        .map_err(TestPassError::RenderingLayoutCreationFailed)?;
 
-        let color_attachment = vk::AttachmentDescription2::default()
-            .format(color_format)
-            .load_op(vk::AttachmentLoadOp::CLEAR)
-            .store_op(vk::AttachmentStoreOp::STORE)
-            .stencil_load_op(vk::AttachmentLoadOp::DONT_CARE)
-            .stencil_store_op(vk::AttachmentStoreOp::DONT_CARE)
-            .initial_layout(vk::ImageLayout::UNDEFINED)
-            .final_layout(vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL)
-            .samples(vk::SampleCountFlags::TYPE_1);
-
-        let color_attachment_ref = vk::AttachmentReference2::default()
-            .attachment(0)
-            .layout(vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL)
-            .aspect_mask(vk::ImageAspectFlags::COLOR);
-
-        let subpass = vk::SubpassDescription2::default()
-            .color_attachments(std::slice::from_ref(&color_attachment_ref))
-            .pipeline_bind_point(vk::PipelineBindPoint::GRAPHICS);
-
-        let render_pass_info = vk::RenderPassCreateInfo2::default()
-            .attachments(std::slice::from_ref(&color_attachment))
-            .subpasses(std::slice::from_ref(&subpass));
-
-        let render_pass = unsafe {
-            device
-                .create_render_pass2(&render_pass_info, None)
-                .map_err(|e| TestPassError::RenderPassCreationFailed(e.to_string()))?
-        };
-
-        let framebuffer_info = vk::FramebufferCreateInfo::default()
-            .render_pass(render_pass)
-            .attachments(std::slice::from_ref(&image_view))
-            .width(image_extent.width)
-            .height(image_extent.height)
-            .layers(1);
-
-        let framebuffer = unsafe {
-            device
-                .create_framebuffer(&framebuffer_info, None)
-                .map_err(|e| TestPassError::FramebufferCreationFailed(e.to_string()))?
-        };
+        let (image, image_allocation, image_view, rendering_target) = create_color_target(
+            &device,
+            allocator,
+            &rendering_layout,
+            color_format,
+            image_extent,
+        )?;
 
         let pipeline_layout_info = vk::PipelineLayoutCreateInfo::default();
 
         let pipeline_layout = unsafe {
             device
                 .create_pipeline_layout(&pipeline_layout_info, None)
-                .map_err(|e| TestPassError::PipelineLayoutCreationFailed(e.to_string()))?
+                .map_err(TestPassError::PipelineLayoutCreationFailed)?
         };
 
         let vertex_shader_module = create_shader_module(&device, VERT_SHADER_BYTES)
-            .map_err(|e| TestPassError::ShaderModuleCreationFailed(e.to_string()))?;
+            .map_err(TestPassError::ShaderModuleCreationFailed)?;
 
         let fragment_shader_module = create_shader_module(&device, FRAG_SHADER_BYTES)
-            .map_err(|e| TestPassError::ShaderModuleCreationFailed(e.to_string()))?;
+            .map_err(TestPassError::ShaderModuleCreationFailed)?;
 
         let main_function_name = std::ffi::CString::new("main").unwrap();
 
@@ -192,23 +149,14 @@ impl TestPass {
         let input_assembly_info = vk::PipelineInputAssemblyStateCreateInfo::default()
             .topology(vk::PrimitiveTopology::POINT_LIST);
 
-        let viewport = vk::Viewport {
-            x: 0.,
-            y: 0.,
-            width: image_extent.width as f32,
-            height: image_extent.height as f32,
-            min_depth: 0.,
-            max_depth: 1.,
-        };
-
-        let scissors = vk::Rect2D {
-            offset: vk::Offset2D { x: 0, y: 0 },
-            extent: image_extent,
-        };
-
+        // Set while recording, so resizing doesn't require a new pipeline
         let viewport_info = vk::PipelineViewportStateCreateInfo::default()
-            .viewports(std::slice::from_ref(&viewport))
-            .scissors(std::slice::from_ref(&scissors));
+            .viewport_count(1)
+            .scissor_count(1);
+
+        let dynamic_states = [vk::DynamicState::VIEWPORT, vk::DynamicState::SCISSOR];
+        let dynamic_state_info =
+            vk::PipelineDynamicStateCreateInfo::default().dynamic_states(&dynamic_states);
 
         let rasterizer_info = vk::PipelineRasterizationStateCreateInfo::default()
             .line_width(1.0)
@@ -237,22 +185,25 @@ impl TestPass {
         let color_blend_info = vk::PipelineColorBlendStateCreateInfo::default()
             .attachments(std::slice::from_ref(&color_blend_attachment));
 
-        let pipeline_info = vk::GraphicsPipelineCreateInfo::default()
-            .stages(&shader_stages)
-            .vertex_input_state(&vertex_input_info)
-            .input_assembly_state(&input_assembly_info)
-            .viewport_state(&viewport_info)
-            .rasterization_state(&rasterizer_info)
-            .multisample_state(&multisampler_info)
-            .color_blend_state(&color_blend_info)
-            .layout(pipeline_layout)
-            .render_pass(render_pass)
-            .subpass(0);
+        let mut rendering_info = rendering_layout.pipeline_rendering_info();
+        let pipeline_info = rendering_layout.pipeline_info(
+            vk::GraphicsPipelineCreateInfo::default()
+                .stages(&shader_stages)
+                .vertex_input_state(&vertex_input_info)
+                .input_assembly_state(&input_assembly_info)
+                .viewport_state(&viewport_info)
+                .rasterization_state(&rasterizer_info)
+                .multisample_state(&multisampler_info)
+                .color_blend_state(&color_blend_info)
+                .dynamic_state(&dynamic_state_info)
+                .layout(pipeline_layout),
+            &mut rendering_info,
+        );
 
         let pipeline = unsafe {
             device
-                .create_graphics_pipelines(vk::PipelineCache::null(), &[pipeline_info], None)
-                .map_err(|e| TestPassError::PipelineCreationFailed(e.1.to_string()))?
+                .create_graphics_pipelines(pipeline_cache, &[pipeline_info], None)
+                .map_err(|e| TestPassError::PipelineCreationFailed(e.1))?
         }[0];
 
         unsafe {
@@ -260,73 +211,181 @@ impl TestPass {
             device.destroy_shader_module(fragment_shader_module, None);
         }
 
-        Ok(Self {
+        let pass = Self {
             device,
+            destruction_queue: destruction_queue.clone(),
+            debug_utils: device_manager.debug_utils.clone(),
 
-            render_pass,
-            framebuffer,
-            render_area: vk::Rect2D::default()
-                .offset(vk::Offset2D::default())
-                .extent(image_extent),
+            rendering_layout,
+            rendering_target,
 
             color_image: image,
-            color_image_memory: image_memory,
+            color_image_allocation: ManuallyDrop::new(image_allocation),
             color_image_view: image_view,
+            color_format,
 
             pipeline_layout,
             pipeline,
-        })
+        };
+        pass.set_debug_names();
+
+        Ok(pass)
+    }
+
+    // Replaces the color target; the old one is released once frames in flight are done with it.
+    pub fn resize(
+        &mut self,
+        allocator: &MemoryAllocator,
+        image_extent: vk::Extent2D,
+    ) -> Result<(), TestPassError> {
+        let (image, image_allocation, image_view, rendering_target) = create_color_target(
+            &self.device,
+            allocator,
+            &self.rendering_layout,
+            self.color_format,
+            image_extent,
+        )?;
+
+        let old_image_allocation = std::mem::replace(
+            &mut self.color_image_allocation,
+            ManuallyDrop::new(image_allocation),
+        );
+
+        self.rendering_target = rendering_target;
+        self.destruction_queue.release_all([
+            ReleasedObject::ImageView(std::mem::replace(&mut self.color_image_view, image_view)),
+            ReleasedObject::Image(std::mem::replace(&mut self.color_image, image)),
+            ReleasedObject::Allocation(ManuallyDrop::into_inner(old_image_allocation)),
+        ]);
+
+        self.set_debug_names();
+
+        Ok(())
+    }
+
+    fn set_debug_names(&self) {
+        if !self.debug_utils.is_enabled() {
+            return;
+        }
+
+        let debug_utils = &self.debug_utils;
+        debug_utils.set_object_name(self.color_image, "Test pass color image");
+        debug_utils.set_object_name(self.color_image_view, "Test pass color image view");
+        self.rendering_layout
+            .set_debug_name(debug_utils, "Test pass render pass");
+        self.rendering_target
+            .set_debug_name(debug_utils, "Test pass framebuffer");
+        debug_utils.set_object_name(self.pipeline_layout, "Test pass pipeline layout");
+        debug_utils.set_object_name(self.pipeline, "Test pass pipeline");
     }
 
     pub fn record(&self, frame_context: &FrameContext) {
-        let render_pass_begin_info = vk::RenderPassBeginInfo::default()
-            .render_pass(self.render_pass)
-            .framebuffer(self.framebuffer)
-            .render_area(self.render_area)
-            .clear_values(&CLEAR_VALUES);
+        let _gpu_scope = frame_context.gpu_scope("Test pass");
+        let _debug_label = frame_context.debug_label("Test pass");
 
-        let subpass_begin_info =
-            vk::SubpassBeginInfo::default().contents(vk::SubpassContents::INLINE);
+        let render_area = self.rendering_target.render_area();
 
-        unsafe {
-            self.device.cmd_begin_render_pass2(
-                frame_context.command_buffer,
-                &render_pass_begin_info,
-                &subpass_begin_info,
-            );
+        self.rendering_layout
+            .begin(frame_context.command_buffer, &self.rendering_target);
 
+        unsafe {
             self.device.cmd_bind_pipeline(
                 frame_context.command_buffer,
                 vk::PipelineBindPoint::GRAPHICS,
                 self.pipeline,
             );
 
+            self.device.cmd_set_viewport(
+                frame_context.command_buffer,
+                0,
+                &[vk::Viewport {
+                    x: 0.,
+                    y: 0.,
+                    width: render_area.extent.width as f32,
+                    height: render_area.extent.height as f32,
+                    min_depth: 0.,
+                    max_depth: 1.,
+                }],
+            );
+
             self.device
-                .cmd_draw(frame_context.command_buffer, 1, 1, 0, 0);
+                .cmd_set_scissor(frame_context.command_buffer, 0, &[render_area]);
 
             self.device
-                .cmd_end_render_pass2(frame_context.command_buffer, &vk::SubpassEndInfo::default());
+                .cmd_draw(frame_context.command_buffer, 1, 1, 0, 0);
         }
+
+        self.rendering_layout
+            .end(frame_context.command_buffer, &self.rendering_target);
     }
 }
 
 impl Drop for TestPass {
     fn drop(&mut self) {
-        unsafe {
-            self.device
-                .device_wait_idle()
-                .expect("Failed to wait for device idle");
-
-            self.device.destroy_pipeline(self.pipeline, None);
-            self.device
-                .destroy_pipeline_layout(self.pipeline_layout, None);
+        self.destruction_queue.release_all([
+            ReleasedObject::Pipeline(self.pipeline),
+            ReleasedObject::PipelineLayout(self.pipeline_layout),
+            ReleasedObject::ImageView(self.color_image_view),
+            ReleasedObject::Image(self.color_image),
+            ReleasedObject::Allocation(unsafe {
+                ManuallyDrop::take(&mut self.color_image_allocation)
+            }),
+        ]);
+    }
+}
 
-            self.device.destroy_framebuffer(self.framebuffer, None);
-            self.device.destroy_render_pass(self.render_pass, None);
+fn create_color_target(
+    device: &ash::Device,
+    allocator: &MemoryAllocator,
+    rendering_layout: &RenderingLayout,
+    color_format: vk::Format,
+    image_extent: vk::Extent2D,
+) -> Result<(vk::Image, Allocation, vk::ImageView, RenderingTarget), TestPassError> {
+    let image_info = vk::ImageCreateInfo::default()
+        .image_type(vk::ImageType::TYPE_2D)
+        .format(color_format)
+        .extent(vk::Extent3D {
+            width: image_extent.width,
+            height: image_extent.height,
+            depth: 1,
+        })
+        .mip_levels(1)
+        .array_layers(1)
+        .samples(vk::SampleCountFlags::TYPE_1)
+        .tiling(vk::ImageTiling::OPTIMAL)
+        .usage(vk::ImageUsageFlags::COLOR_ATTACHMENT | vk::ImageUsageFlags::SAMPLED)
+        .initial_layout(vk::ImageLayout::UNDEFINED);
+
+    let (image, image_allocation) = allocator
+        .create_image(&image_info, vk::MemoryPropertyFlags::DEVICE_LOCAL)
+        .map_err(TestPassError::CreateImageFailed)?;
+
+    let image_view_info = vk::ImageViewCreateInfo::default()
+        .image(image)
+        .view_type(vk::ImageViewType::TYPE_2D)
+        .format(color_format)
+        .subresource_range(
+            vk::ImageSubresourceRange::default()
+                .aspect_mask(vk::ImageAspectFlags::COLOR)
+                .base_mip_level(0)
+                .level_count(1)
+                .base_array_layer(0)
+                .layer_count(1),
+        );
+
+    let image_view: vk::ImageView = unsafe {
+        device
+            .create_image_view(&image_view_info, None)
+            .map_err(TestPassError::CreateImageViewFailed)?
+    };
+
+    let rendering_target = rendering_layout
+        .create_target(
+            &[AttachmentImage::new(image, image_view)],
+            None,
+            image_extent,
+        )
+        .map_err(TestPassError::RenderingTargetCreationFailed)?;
 
-            self.device.destroy_image_view(self.color_image_view, None);
-            self.device.destroy_image(self.color_image, None);
-            self.device.free_memory(self.color_image_memory, None);
-        }
-    }
+    Ok((image, image_allocation, image_view, rendering_target))
 }
@@ -0,0 +1,173 @@
+use ash::vk;
+use glam::{Mat4, Vec2, Vec3};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Projection {
+    // Vertical field of view in radians
+    Perspective { fov_y: f32 },
+    // Height of the view volume in world units; the width follows the aspect ratio
+    Orthographic { height: f32 },
+}
+
+// Fractions of the camera's target, so the viewport follows resizes. The origin is the top left.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct CameraViewport {
+    pub x: f32,
+    pub y: f32,
+    pub width: f32,
+    pub height: f32,
+}
+
+impl CameraViewport {
+    pub const FULL: Self = Self {
+        x: 0.0,
+        y: 0.0,
+        width: 1.0,
+        height: 1.0,
+    };
+
+    pub fn to_rect(&self, target_extent: vk::Extent2D) -> vk::Rect2D {
+        let target_width = target_extent.width as f32;
+        let target_height = target_extent.height as f32;
+
+        let x = (self.x * target_width).round().clamp(0.0, target_width);
+        let y = (self.y * target_height).round().clamp(0.0, target_height);
+        let width = (self.width * target_width)
+            .round()
+            .clamp(0.0, target_width - x);
+        let height = (self.height * target_height)
+            .round()
+            .clamp(0.0, target_height - y);
+
+        vk::Rect2D {
+            offset: vk::Offset2D {
+                x: x as i32,
+                y: y as i32,
+            },
+            extent: vk::Extent2D {
+                width: width as u32,
+                height: height as u32,
+            },
+        }
+    }
+}
+
+// Returned by `Renderer3D::create_camera_target`, and only meaningful to that renderer
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct CameraTargetId(pub(crate) usize);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum CameraTarget {
+    // The renderer's output, which the final pass writes to the swapchain or offscreen image
+    #[default]
+    Main,
+    Offscreen(CameraTargetId),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Camera {
+    pub projection: Projection,
+    // Camera to world; the camera looks down its local -Z with +Y up
+    pub transform: Mat4,
+    pub near: f32,
+    pub far: f32,
+    pub viewport: CameraViewport,
+    pub target: CameraTarget,
+}
+
+impl Default for Camera {
+    fn default() -> Self {
+        Self::perspective(45_f32.to_radians(), 0.1, 100.0).look_at(
+            Vec3::new(3.0, 3.0, 3.0),
+            Vec3::ZERO,
+            Vec3::Y,
+        )
+    }
+}
+
+impl Camera {
+    pub fn perspective(fov_y: f32, near: f32, far: f32) -> Self {
+        Self {
+            projection: Projection::Perspective { fov_y },
+            transform: Mat4::IDENTITY,
+            near,
+            far,
+            viewport: CameraViewport::FULL,
+            target: CameraTarget::Main,
+        }
+    }
+
+    pub fn orthographic(height: f32, near: f32, far: f32) -> Self {
+        Self {
+            projection: Projection::Orthographic { height },
+            ..Self::perspective(0.0, near, far)
+        }
+    }
+
+    pub fn look_at(mut self, eye: Vec3, target: Vec3, up: Vec3) -> Self {
+        self.transform = Mat4::look_at_rh(eye, target, up).inverse();
+        self
+    }
+
+    pub fn transform(mut self, transform: Mat4) -> Self {
+        self.transform = transform;
+        self
+    }
+
+    pub fn viewport(mut self, viewport: CameraViewport) -> Self {
+        self.viewport = viewport;
+        self
+    }
+
+    pub fn target(mut self, target: CameraTarget) -> Self {
+        self.target = target;
+        self
+    }
+
+    pub fn view_matrix(&self) -> Mat4 {
+        self.transform.inverse()
+    }
+
+    // Of the camera's viewport within a target of `target_extent`.
+    pub fn aspect(&self, target_extent: vk::Extent2D) -> f32 {
+        let extent = self.viewport.to_rect(target_extent).extent;
+
+        extent.width.max(1) as f32 / extent.height.max(1) as f32
+    }
+
+    // With `reverse_z`, the near plane maps to depth 1.0 and the far plane to 0.0.
+    pub fn projection_matrix(&self, aspect: f32, reverse_z: bool) -> Mat4 {
+        let (near, far) = if reverse_z {
+            (self.far, self.near)
+        } else {
+            (self.near, self.far)
+        };
+
+        match self.projection {
+            Projection::Perspective { fov_y } => Mat4::perspective_rh(fov_y, aspect, near, far),
+            Projection::Orthographic { height } => {
+                let half_height = height * 0.5;
+                let half_width = half_height * aspect;
+
+                Mat4::orthographic_rh(
+                    -half_width,
+                    half_width,
+                    -half_height,
+                    half_height,
+                    near,
+                    far,
+                )
+            }
+        }
+    }
+
+    // Half the width and height of the view volume at `depth` in front of the camera.
+    pub fn half_extents_at(&self, depth: f32, aspect: f32) -> Vec2 {
+        let half_height = match self.projection {
+            Projection::Perspective { fov_y } => depth * (fov_y * 0.5).tan(),
+            Projection::Orthographic { height } => height * 0.5,
+        };
+
+        Vec2::new(half_height * aspect, half_height)
+    }
+}
@@ -0,0 +1,220 @@
+use std::mem::ManuallyDrop;
+
+use ash::vk;
+use eren_render_vulkan_core::vulkan::{
+    allocator::Allocation,
+    destruction::{DestructionQueue, ReleasedObject},
+    upload::{UploadManager, UploadManagerError, UploadTicket},
+};
+use thiserror::Error;
+
+use crate::render::vertex::{Vertex, VertexLayout};
+
+#[derive(Debug, Error)]
+pub enum MeshError {
+    #[error("Mesh has no vertices")]
+    NoVertices,
+
+    #[error("Failed to upload mesh data: {0}")]
+    UploadFailed(#[from] UploadManagerError),
+}
+
+enum MeshIndices<'a> {
+    U16(&'a [u16]),
+    U32(&'a [u32]),
+}
+
+pub struct MeshBuilder<'a, V: Vertex> {
+    vertices: &'a [V],
+    indices: Option<MeshIndices<'a>>,
+}
+
+impl<'a, V: Vertex> MeshBuilder<'a, V> {
+    pub fn indices(mut self, indices: &'a [u32]) -> Self {
+        self.indices = Some(MeshIndices::U32(indices));
+        self
+    }
+
+    pub fn indices_u16(mut self, indices: &'a [u16]) -> Self {
+        self.indices = Some(MeshIndices::U16(indices));
+        self
+    }
+
+    pub fn build(
+        self,
+        upload_manager: &mut UploadManager,
+        destruction_queue: &DestructionQueue,
+    ) -> Result<Mesh, MeshError> {
+        if self.vertices.is_empty() {
+            return Err(MeshError::NoVertices);
+        }
+
+        let (vertex_buffer, vertex_allocation, vertex_ticket) = upload_manager
+            .create_buffer_with_data(self.vertices, vk::BufferUsageFlags::VERTEX_BUFFER)?;
+
+        let mut mesh = Mesh {
+            device: upload_manager.device().clone(),
+            destruction_queue: destruction_queue.clone(),
+            vertex_buffer,
+            vertex_allocation: ManuallyDrop::new(vertex_allocation),
+            vertex_count: self.vertices.len() as u32,
+            vertex_layout: V::LAYOUT,
+            index_buffer: None,
+            vertex_upload_ticket: vertex_ticket,
+        };
+
+        // Assigned to `mesh` right away so a failed index upload still frees the vertex buffer.
+        mesh.index_buffer =
+            match self.indices {
+                Some(MeshIndices::U16(indices)) if !indices.is_empty() => Some(
+                    MeshIndexBuffer::new(upload_manager, indices, vk::IndexType::UINT16)?,
+                ),
+                Some(MeshIndices::U32(indices)) if !indices.is_empty() => Some(
+                    MeshIndexBuffer::new(upload_manager, indices, vk::IndexType::UINT32)?,
+                ),
+                _ => None,
+            };
+
+        Ok(mesh)
+    }
+}
+
+struct MeshIndexBuffer {
+    buffer: vk::Buffer,
+    allocation: Allocation,
+    index_type: vk::IndexType,
+    index_count: u32,
+    upload_ticket: UploadTicket,
+}
+
+impl MeshIndexBuffer {
+    fn new<I: Copy>(
+        upload_manager: &mut UploadManager,
+        indices: &[I],
+        index_type: vk::IndexType,
+    ) -> Result<Self, MeshError> {
+        let (buffer, allocation, upload_ticket) =
+            upload_manager.create_buffer_with_data(indices, vk::BufferUsageFlags::INDEX_BUFFER)?;
+
+        Ok(Self {
+            buffer,
+            allocation,
+            index_type,
+            index_count: indices.len() as u32,
+            upload_ticket,
+        })
+    }
+}
+
+pub struct Mesh {
+    device: ash::Device,
+    destruction_queue: DestructionQueue,
+
+    vertex_buffer: vk::Buffer,
+    vertex_allocation: ManuallyDrop<Allocation>,
+    vertex_count: u32,
+    vertex_layout: VertexLayout,
+
+    index_buffer: Option<MeshIndexBuffer>,
+    // The mesh must not be drawn before this and the index upload have completed
+    vertex_upload_ticket: UploadTicket,
+}
+
+impl Mesh {
+    pub fn builder<V: Vertex>(vertices: &[V]) -> MeshBuilder<'_, V> {
+        MeshBuilder {
+            vertices,
+            indices: None,
+        }
+    }
+
+    pub fn vertex_layout(&self) -> &VertexLayout {
+        &self.vertex_layout
+    }
+
+    pub fn vertex_count(&self) -> u32 {
+        self.vertex_count
+    }
+
+    pub fn index_count(&self) -> Option<u32> {
+        self.index_buffer
+            .as_ref()
+            .map(|index_buffer| index_buffer.index_count)
+    }
+
+    pub fn upload_tickets(&self) -> impl Iterator<Item = UploadTicket> + '_ {
+        std::iter::once(self.vertex_upload_ticket).chain(
+            self.index_buffer
+                .as_ref()
+                .map(|index_buffer| index_buffer.upload_ticket),
+        )
+    }
+
+    // Also lets the upload manager reuse the staging space of finished uploads.
+    pub fn is_uploaded(&self, upload_manager: &mut UploadManager) -> Result<bool, MeshError> {
+        for ticket in self.upload_tickets() {
+            if !upload_manager.is_complete(ticket)? {
+                return Ok(false);
+            }
+        }
+
+        Ok(true)
+    }
+
+    // Meant for before the first frame that draws the mesh.
+    pub fn wait_for_upload(&self, upload_manager: &mut UploadManager) -> Result<(), MeshError> {
+        for ticket in self.upload_tickets() {
+            upload_manager.wait(ticket)?;
+        }
+
+        Ok(())
+    }
+
+    pub(crate) fn record_draw(&self, command_buffer: vk::CommandBuffer) {
+        unsafe {
+            self.device
+                .cmd_bind_vertex_buffers(command_buffer, 0, &[self.vertex_buffer], &[0]);
+
+            match &self.index_buffer {
+                Some(index_buffer) => {
+                    self.device.cmd_bind_index_buffer(
+                        command_buffer,
+                        index_buffer.buffer,
+                        0,
+                        index_buffer.index_type,
+                    );
+
+                    self.device.cmd_draw_indexed(
+                        command_buffer,
+                        index_buffer.index_count,
+                        1,
+                        0,
+                        0,
+                        0,
+                    );
+                }
+                None => {
+                    self.device
+                        .cmd_draw(command_buffer, self.vertex_count, 1, 0, 0);
+                }
+            }
+        }
+    }
+}
+
+impl Drop for Mesh {
+    fn drop(&mut self) {
+        // Frames still in flight may draw this mesh, so its buffers outlive it until they finish.
+        self.destruction_queue.release_all([
+            ReleasedObject::Buffer(self.vertex_buffer),
+            ReleasedObject::Allocation(unsafe { ManuallyDrop::take(&mut self.vertex_allocation) }),
+        ]);
+
+        if let Some(index_buffer) = self.index_buffer.take() {
+            self.destruction_queue.release_all([
+                ReleasedObject::Buffer(index_buffer.buffer),
+                ReleasedObject::Allocation(index_buffer.allocation),
+            ]);
+        }
+    }
+}
@@ -1,3 +1,7 @@
+pub mod camera;
+pub mod mesh;
 pub mod render_item;
 pub mod renderer_3d;
+pub mod shadow_cascades;
 pub mod test_renderer;
+pub mod vertex;
@@ -1,21 +1,101 @@
 use std::sync::Arc;
 
 use ash::vk;
+use eren_render_vulkan_core::vulkan::destruction::{DestructionQueue, ReleasedObject};
+use thiserror::Error;
 
-pub struct Mesh {
-    pub vertex_buffer: vk::Buffer,
-    pub vertex_memory: vk::DeviceMemory,
-    pub index_buffer: vk::Buffer,
-    pub index_memory: vk::DeviceMemory,
-    pub index_count: u32,
+use crate::render::mesh::Mesh;
+
+#[derive(Debug, Error)]
+pub enum MaterialError {
+    #[error("Failed to create descriptor set layout: {0}")]
+    DescriptorSetLayoutCreationFailed(vk::Result),
+
+    #[error("Failed to create descriptor pool: {0}")]
+    DescriptorPoolCreationFailed(vk::Result),
+
+    #[error("Failed to allocate descriptor set: {0}")]
+    DescriptorSetAllocationFailed(vk::Result),
 }
 
 pub struct Material {
+    destruction_queue: DestructionQueue,
+
     pub descriptor_set_layout: vk::DescriptorSetLayout,
-    pub descriptor_pool: vk::DescriptorPool,
+    descriptor_pool: vk::DescriptorPool,
     pub descriptor_set: vk::DescriptorSet,
 }
 
+impl Material {
+    // One descriptor set laid out by `bindings`; empty `bindings` give a material that binds nothing.
+    pub fn new(
+        device: &ash::Device,
+        destruction_queue: &DestructionQueue,
+        bindings: &[vk::DescriptorSetLayoutBinding],
+    ) -> Result<Self, MaterialError> {
+        let layout_info = vk::DescriptorSetLayoutCreateInfo::default().bindings(bindings);
+
+        let descriptor_set_layout = unsafe {
+            device
+                .create_descriptor_set_layout(&layout_info, None)
+                .map_err(MaterialError::DescriptorSetLayoutCreationFailed)?
+        };
+
+        let pool_sizes: Vec<vk::DescriptorPoolSize> = bindings
+            .iter()
+            .map(|binding| vk::DescriptorPoolSize {
+                ty: binding.descriptor_type,
+                descriptor_count: binding.descriptor_count,
+            })
+            .collect();
+
+        let pool_info = vk::DescriptorPoolCreateInfo::default()
+            .max_sets(1)
+            .pool_sizes(&pool_sizes);
+
+        // Nothing has used the objects yet, so a failure destroys them right away
+        let descriptor_pool = match unsafe { device.create_descriptor_pool(&pool_info, None) } {
+            Ok(descriptor_pool) => descriptor_pool,
+            Err(e) => {
+                unsafe { device.destroy_descriptor_set_layout(descriptor_set_layout, None) };
+                return Err(MaterialError::DescriptorPoolCreationFailed(e));
+            }
+        };
+
+        let alloc_info = vk::DescriptorSetAllocateInfo::default()
+            .descriptor_pool(descriptor_pool)
+            .set_layouts(std::slice::from_ref(&descriptor_set_layout));
+
+        let descriptor_set = match unsafe { device.allocate_descriptor_sets(&alloc_info) } {
+            Ok(descriptor_sets) => descriptor_sets[0],
+            Err(e) => {
+                unsafe {
+                    device.destroy_descriptor_pool(descriptor_pool, None);
+                    device.destroy_descriptor_set_layout(descriptor_set_layout, None);
+                }
+                return Err(MaterialError::DescriptorSetAllocationFailed(e));
+            }
+        };
+
+        Ok(Self {
+            destruction_queue: destruction_queue.clone(),
+            descriptor_set_layout,
+            descriptor_pool,
+            descriptor_set,
+        })
+    }
+}
+
+impl Drop for Material {
+    fn drop(&mut self) {
+        // The set is freed along with its pool
+        self.destruction_queue.release_all([
+            ReleasedObject::DescriptorPool(self.descriptor_pool),
+            ReleasedObject::DescriptorSetLayout(self.descriptor_set_layout),
+        ]);
+    }
+}
+
 pub struct RenderItem {
     pub mesh: Arc<Mesh>,
     pub material: Arc<Material>,
@@ -1,14 +1,29 @@
 use ash::vk;
-use eren_render_vulkan_core::renderer::{FrameContext, Renderer};
+use eren_render_vulkan_core::{
+    renderer::{FrameContext, RenderTargetInfo, Renderer, RendererError, ResizeContext},
+    vulkan::{
+        allocator::MemoryAllocator,
+        destruction::DestructionQueue,
+        device::DeviceManager,
+        features::{DeviceFeature, DeviceRequirements},
+        readback::ReadbackSource,
+    },
+};
 use thiserror::Error;
 
 use crate::{
     passes::{
         final_pass::{FinalPass, FinalPassError},
-        geometry_pass::{CameraUBO, GeometryPass, GeometryPassError},
-        shadow_pass::{LightVP, ShadowPass, ShadowPassError},
+        geometry_pass::{
+            CameraUBO, GeometryPass, GeometryPassError, GeometryPassSettings, MAX_CAMERAS,
+        },
+        shadow_pass::{LightVP, ShadowFilter, ShadowPass, ShadowPassError, ShadowSettings},
+    },
+    render::{
+        camera::{Camera, CameraTarget, CameraTargetId},
+        render_item::RenderItem,
+        shadow_cascades::ShadowCascades,
     },
-    render::render_item::RenderItem,
 };
 
 #[derive(Debug, Error)]
@@ -21,89 +36,404 @@ pub enum Renderer3DError {
 
     #[error("Failed to create final pass: {0}")]
     FinalPassCreationFailed(#[from] FinalPassError),
+
+    #[error("Expected between 1 and {MAX_CAMERAS} cameras, got {0}")]
+    InvalidCameraCount(usize),
+
+    #[error("Camera target {0:?} does not exist")]
+    UnknownCameraTarget(CameraTargetId),
+
+    #[error("Camera target {0:?} is still used by a camera")]
+    CameraTargetInUse(CameraTargetId),
+}
+
+impl Renderer3DError {
+    pub fn vk_result(&self) -> Option<vk::Result> {
+        match self {
+            Self::ShadowPassCreationFailed(e) => e.vk_result(),
+            Self::GeometryPassCreationFailed(e) => e.vk_result(),
+            Self::FinalPassCreationFailed(e) => e.vk_result(),
+            Self::InvalidCameraCount(_)
+            | Self::UnknownCameraTarget(_)
+            | Self::CameraTargetInUse(_) => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct Renderer3DSettings {
+    pub shadows: ShadowSettings,
+    // See `GeometryPassSettings::reverse_z`
+    pub reverse_z: bool,
 }
 
 pub struct Renderer3D {
     shadow_pass: ShadowPass,
     geometry_pass: GeometryPass,
     final_pass: FinalPass,
+
+    settings: Renderer3DSettings,
+    image_extent: vk::Extent2D,
+    cameras: Vec<Camera>,
+    // Indexed by `CameraTargetId`; removed targets leave a hole so ids are never reused
+    camera_targets: Vec<Option<GeometryPass>>,
 }
 
 impl Renderer3D {
     pub fn new(
-        instance: &ash::Instance,
-        physical_device: vk::PhysicalDevice,
-        device: ash::Device,
-        swapchain_image_views: &Vec<vk::ImageView>,
-        surface_format: vk::Format,
-        image_extent: vk::Extent2D,
+        device_manager: &DeviceManager,
+        allocator: &MemoryAllocator,
+        destruction_queue: &DestructionQueue,
+        pipeline_cache: vk::PipelineCache,
+        render_target: &RenderTargetInfo,
     ) -> Result<Self, Renderer3DError> {
-        let shadow_pass = ShadowPass::new(instance, physical_device, device.clone(), image_extent)?;
+        Self::with_settings(
+            device_manager,
+            allocator,
+            destruction_queue,
+            pipeline_cache,
+            render_target,
+            &Renderer3DSettings::default(),
+        )
+    }
+
+    pub fn with_settings(
+        device_manager: &DeviceManager,
+        allocator: &MemoryAllocator,
+        destruction_queue: &DestructionQueue,
+        pipeline_cache: vk::PipelineCache,
+        render_target: &RenderTargetInfo,
+        settings: &Renderer3DSettings,
+    ) -> Result<Self, Renderer3DError> {
+        let image_extent = render_target.extent;
+
+        let shadow_pass = ShadowPass::new(
+            device_manager,
+            allocator,
+            destruction_queue,
+            pipeline_cache,
+            &settings.shadows,
+        )?;
 
         let geometry_pass = GeometryPass::new(
-            instance,
-            physical_device,
-            device.clone(),
+            device_manager,
+            allocator,
+            destruction_queue,
+            pipeline_cache,
             image_extent,
             shadow_pass.depth_image_view,
+            &geometry_pass_settings(settings),
         )?;
 
         let final_pass = FinalPass::new(
-            device.clone(),
-            swapchain_image_views,
-            surface_format,
-            image_extent,
+            device_manager,
+            destruction_queue,
+            pipeline_cache,
+            render_target,
             geometry_pass.color_image_view,
         )?;
 
-        let proj = glam::Mat4::perspective_rh(
-            45_f32.to_radians(),
-            image_extent.width as f32 / image_extent.height as f32,
-            0.1,
-            100.0,
-        );
-        let view = glam::Mat4::look_at_rh(
-            glam::Vec3::new(3.0, 3.0, 3.0), // eye
-            glam::Vec3::ZERO,               // at
-            glam::Vec3::Y,                  // up
-        );
-        let view_proj = proj * view;
-
-        let light_view = glam::Mat4::look_at_rh(
-            glam::Vec3::new(4.0, 5.0, 2.0),
-            glam::Vec3::ZERO,
-            glam::Vec3::Y,
-        );
-        let light_proj = glam::Mat4::orthographic_rh(-6.0, 6.0, -6.0, 6.0, 0.1, 20.0);
-        let light_vp = light_proj * light_view;
-
-        shadow_pass.upload_light_vp_buffer(&LightVP {
-            light_view_proj: light_vp,
-        })?;
-
-        geometry_pass.upload_camera_buffer(&CameraUBO {
-            view_proj,
-            light_view_proj: light_vp,
-            light_dir: glam::Vec3::new(
-                -light_view.z_axis.x,
-                -light_view.z_axis.y,
-                -light_view.z_axis.z,
-            ),
-            _pad: 0.0,
-        })?;
-
         Ok(Self {
             shadow_pass,
             geometry_pass,
             final_pass,
+
+            settings: *settings,
+            image_extent,
+            cameras: vec![Camera::default()],
+            camera_targets: Vec::new(),
         })
     }
 }
 
+impl Renderer3D {
+    // Rebuilds what depends on the render target; pipelines, the shadow map and scene resources stay.
+    pub fn resize(&mut self, resize_context: &ResizeContext) -> Result<(), Renderer3DError> {
+        let render_target = resize_context.render_target;
+
+        self.geometry_pass
+            .resize(resize_context.allocator, render_target.extent)?;
+        self.final_pass
+            .resize(render_target, self.geometry_pass.color_image_view)?;
+
+        // Camera aspect ratios, and the cascades fitted to them, follow the target
+        self.image_extent = render_target.extent;
+
+        Ok(())
+    }
+
+    pub fn cameras(&self) -> &[Camera] {
+        &self.cameras
+    }
+
+    // Meant to be called every frame the camera moves, before `GraphicsContext::redraw`.
+    pub fn set_camera(&mut self, camera: Camera) -> Result<(), Renderer3DError> {
+        self.set_cameras(&[camera])
+    }
+
+    // Each camera draws the scene into its viewport of its target, in order. Shadows are fitted to
+    // the first one.
+    pub fn set_cameras(&mut self, cameras: &[Camera]) -> Result<(), Renderer3DError> {
+        if cameras.is_empty() || cameras.len() > MAX_CAMERAS {
+            return Err(Renderer3DError::InvalidCameraCount(cameras.len()));
+        }
+
+        for camera in cameras {
+            match camera.target {
+                CameraTarget::Offscreen(id) if self.target_pass(camera.target).is_none() => {
+                    return Err(Renderer3DError::UnknownCameraTarget(id));
+                }
+                _ => {}
+            }
+        }
+
+        self.cameras.clear();
+        self.cameras.extend_from_slice(cameras);
+
+        Ok(())
+    }
+
+    // A color and depth image of its own that cameras can draw into instead of the main output,
+    // e.g. for a minimap or a mirror. The color image is left in SHADER_READ_ONLY_OPTIMAL.
+    pub fn create_camera_target(
+        &mut self,
+        device_manager: &DeviceManager,
+        allocator: &MemoryAllocator,
+        destruction_queue: &DestructionQueue,
+        pipeline_cache: vk::PipelineCache,
+        extent: vk::Extent2D,
+    ) -> Result<CameraTargetId, Renderer3DError> {
+        let geometry_pass = GeometryPass::new(
+            device_manager,
+            allocator,
+            destruction_queue,
+            pipeline_cache,
+            extent,
+            self.shadow_pass.depth_image_view,
+            &geometry_pass_settings(&self.settings),
+        )?;
+
+        self.camera_targets.push(Some(geometry_pass));
+
+        Ok(CameraTargetId(self.camera_targets.len() - 1))
+    }
+
+    pub fn resize_camera_target(
+        &mut self,
+        id: CameraTargetId,
+        allocator: &MemoryAllocator,
+        extent: vk::Extent2D,
+    ) -> Result<(), Renderer3DError> {
+        let geometry_pass = self
+            .camera_targets
+            .get_mut(id.0)
+            .and_then(Option::as_mut)
+            .ok_or(Renderer3DError::UnknownCameraTarget(id))?;
+
+        Ok(geometry_pass.resize(allocator, extent)?)
+    }
+
+    pub fn remove_camera_target(&mut self, id: CameraTargetId) -> Result<(), Renderer3DError> {
+        if self
+            .cameras
+            .iter()
+            .any(|camera| camera.target == CameraTarget::Offscreen(id))
+        {
+            return Err(Renderer3DError::CameraTargetInUse(id));
+        }
+
+        // Frames in flight may still draw into it; the pass defers its destruction until they finish
+        match self.camera_targets.get_mut(id.0).and_then(Option::take) {
+            Some(_) => Ok(()),
+            None => Err(Renderer3DError::UnknownCameraTarget(id)),
+        }
+    }
+
+    pub fn camera_target_image_view(&self, id: CameraTargetId) -> Option<vk::ImageView> {
+        self.target_pass(CameraTarget::Offscreen(id))
+            .map(|geometry_pass| geometry_pass.color_image_view)
+    }
+
+    pub fn camera_target_readback_source(&self, id: CameraTargetId) -> Option<ReadbackSource> {
+        self.target_pass(CameraTarget::Offscreen(id))
+            .map(GeometryPass::color_readback_source)
+    }
+
+    pub fn settings(&self) -> &Renderer3DSettings {
+        &self.settings
+    }
+
+    // Takes effect from the next recorded frame. The shadow map size and cascade count are fixed
+    // at creation.
+    pub fn set_shadow_filter(&mut self, filter: ShadowFilter) {
+        self.settings.shadows.filter = filter;
+        self.geometry_pass.set_shadow_filter(filter);
+        for geometry_pass in self.camera_targets.iter_mut().flatten() {
+            geometry_pass.set_shadow_filter(filter);
+        }
+    }
+
+    pub fn set_shadow_bias(&mut self, depth_bias: f32, slope_bias: f32) {
+        self.settings.shadows.depth_bias = depth_bias;
+        self.settings.shadows.slope_bias = slope_bias;
+        self.shadow_pass.set_bias(depth_bias, slope_bias);
+    }
+
+    pub fn set_cascade_fitting(&mut self, split_lambda: f32, stabilize: bool) {
+        self.settings.shadows.split_lambda = split_lambda;
+        self.settings.shadows.stabilize = stabilize;
+    }
+
+    // Rewritten every frame from the current cameras, into the slots of `frame_index` only, so
+    // frames still in flight keep the values they were recorded with.
+    fn upload_scene_uniforms(&self, frame_index: usize) -> Result<(), Renderer3DError> {
+        // Travels from (4, 5, 2) towards the origin
+        let light_dir = -glam::Vec3::new(4.0, 5.0, 2.0).normalize();
+
+        let shadow_camera = &self.cameras[0];
+        let cascades = ShadowCascades::fit(
+            shadow_camera,
+            shadow_camera.aspect(self.target_extent(shadow_camera.target)),
+            light_dir,
+            &self.settings.shadows,
+        );
+
+        self.shadow_pass.upload_light_vp_buffer(
+            frame_index,
+            &LightVP {
+                light_view_proj: cascades.view_proj,
+            },
+        )?;
+
+        for (index, camera) in self.cameras.iter().enumerate() {
+            let Some(geometry_pass) = self.target_pass(camera.target) else {
+                continue;
+            };
+            // Slots are per target, in the same order as `target_viewports`
+            let camera_index = self.cameras[..index]
+                .iter()
+                .filter(|other| other.target == camera.target)
+                .count();
+
+            let aspect = camera.aspect(self.target_extent(camera.target));
+            let proj = camera.projection_matrix(aspect, self.settings.reverse_z);
+
+            geometry_pass.upload_camera_buffer(
+                frame_index,
+                camera_index,
+                &CameraUBO {
+                    view_proj: proj * camera.view_matrix(),
+                    shadow_view: shadow_camera.view_matrix(),
+                    cascade_view_proj: cascades.view_proj,
+                    cascade_splits: cascades.split_depths,
+                    light_dir,
+                    cascade_count: cascades.count as u32,
+                },
+            )?;
+        }
+
+        Ok(())
+    }
+
+    fn target_pass(&self, target: CameraTarget) -> Option<&GeometryPass> {
+        match target {
+            CameraTarget::Main => Some(&self.geometry_pass),
+            CameraTarget::Offscreen(id) => self.camera_targets.get(id.0).and_then(Option::as_ref),
+        }
+    }
+
+    fn target_extent(&self, target: CameraTarget) -> vk::Extent2D {
+        match target {
+            CameraTarget::Main => self.image_extent,
+            CameraTarget::Offscreen(_) => self
+                .target_pass(target)
+                .map_or(self.image_extent, GeometryPass::image_extent),
+        }
+    }
+
+    fn target_viewports(&self, target: CameraTarget) -> Vec<vk::Rect2D> {
+        let extent = self.target_extent(target);
+
+        self.cameras
+            .iter()
+            .filter(|camera| camera.target == target)
+            .map(|camera| camera.viewport.to_rect(extent))
+            .collect()
+    }
+
+    // Depth of the last geometry pass, for passes like SSAO or fog. Near is 1.0 with reverse Z.
+    pub fn depth_image_view(&self) -> vk::ImageView {
+        self.geometry_pass.depth_image_view
+    }
+
+    pub fn geometry_readback_source(&self) -> ReadbackSource {
+        self.geometry_pass.color_readback_source()
+    }
+}
+
 impl Renderer<RenderItem> for Renderer3D {
-    fn render(&self, frame_context: &FrameContext, render_items: &[RenderItem]) {
+    fn device_requirements() -> DeviceRequirements {
+        DeviceRequirements::default()
+            .optional_feature(DeviceFeature::DynamicRendering)
+            .optional_feature(DeviceFeature::Synchronization2)
+    }
+
+    fn render(
+        &self,
+        frame_context: &FrameContext,
+        render_items: &[RenderItem],
+    ) -> Result<(), RendererError> {
+        // Without this frame's uniforms nothing is drawn, but the passes still clear their targets
+        // and leave every image in the layout the next user expects.
+        let upload_result = self.upload_scene_uniforms(frame_context.frame_index);
+        let render_items = if upload_result.is_ok() {
+            render_items
+        } else {
+            &[]
+        };
+
         self.shadow_pass.record(frame_context, render_items);
-        self.geometry_pass.record(frame_context, render_items);
+
+        // Targets no camera looks through keep their last image
+        for (index, geometry_pass) in self.camera_targets.iter().enumerate() {
+            let Some(geometry_pass) = geometry_pass else {
+                continue;
+            };
+
+            let viewports = self.target_viewports(CameraTarget::Offscreen(CameraTargetId(index)));
+            if !viewports.is_empty() {
+                geometry_pass.record(frame_context, render_items, &viewports);
+            }
+        }
+
+        self.geometry_pass.record(
+            frame_context,
+            render_items,
+            &self.target_viewports(CameraTarget::Main),
+        );
         self.final_pass.record(frame_context);
+
+        upload_result.map_err(|e| RendererError::RenderFailed {
+            vk_result: e.vk_result(),
+            source: e.into(),
+        })
+    }
+
+    fn on_swapchain_recreated(
+        &mut self,
+        resize_context: &ResizeContext,
+    ) -> Result<(), RendererError> {
+        self.resize(resize_context)
+            .map_err(|e| RendererError::ResizeFailed {
+                vk_result: e.vk_result(),
+                source: e.into(),
+            })
+    }
+}
+
+fn geometry_pass_settings(settings: &Renderer3DSettings) -> GeometryPassSettings {
+    GeometryPassSettings {
+        reverse_z: settings.reverse_z,
+        shadow_filter: settings.shadows.filter,
     }
 }
@@ -0,0 +1,129 @@
+use glam::{Mat4, Vec3, Vec4};
+
+use crate::{
+    passes::shadow_pass::{MAX_CASCADES, ShadowSettings},
+    render::camera::Camera,
+};
+
+#[derive(Debug, Clone, Copy)]
+pub struct ShadowCascades {
+    pub view_proj: [Mat4; MAX_CASCADES],
+    // View space distance from the camera where each cascade ends
+    pub split_depths: [f32; MAX_CASCADES],
+    pub count: usize,
+}
+
+impl ShadowCascades {
+    // `light_dir` is the direction the light travels in.
+    pub fn fit(camera: &Camera, aspect: f32, light_dir: Vec3, settings: &ShadowSettings) -> Self {
+        let count = (settings.cascade_count as usize).clamp(1, MAX_CASCADES);
+        let light_dir = light_dir.normalize();
+
+        let mut cascades = Self {
+            view_proj: [Mat4::IDENTITY; MAX_CASCADES],
+            split_depths: [camera.far; MAX_CASCADES],
+            count,
+        };
+
+        let mut split_near = camera.near;
+        for index in 0..count {
+            let split_far = split_depth(camera, settings.split_lambda, index + 1, count);
+
+            let corners = frustum_corners(camera, aspect, split_near, split_far);
+            cascades.view_proj[index] = if settings.stabilize {
+                stable_view_proj(&corners, light_dir, settings.map_size)
+            } else {
+                tight_view_proj(&corners, light_dir)
+            };
+            cascades.split_depths[index] = split_far;
+
+            split_near = split_far;
+        }
+
+        cascades
+    }
+}
+
+// Blend of logarithmic and uniform splits; `lambda` 1.0 is fully logarithmic.
+fn split_depth(camera: &Camera, lambda: f32, index: usize, count: usize) -> f32 {
+    let lambda = lambda.clamp(0.0, 1.0);
+    let fraction = index as f32 / count as f32;
+    let logarithmic = camera.near * (camera.far / camera.near).powf(fraction);
+    let uniform = camera.near + (camera.far - camera.near) * fraction;
+
+    lambda * logarithmic + (1.0 - lambda) * uniform
+}
+
+fn frustum_corners(camera: &Camera, aspect: f32, near: f32, far: f32) -> [Vec3; 8] {
+    let mut corners = [Vec3::ZERO; 8];
+
+    for (index, depth) in [near, far].into_iter().enumerate() {
+        let half_extents = camera.half_extents_at(depth, aspect);
+
+        for (corner, (x, y)) in [(-1.0, -1.0), (1.0, -1.0), (1.0, 1.0), (-1.0, 1.0)]
+            .into_iter()
+            .enumerate()
+        {
+            corners[index * 4 + corner] = camera.transform.transform_point3(Vec3::new(
+                x * half_extents.x,
+                y * half_extents.y,
+                -depth,
+            ));
+        }
+    }
+
+    corners
+}
+
+fn light_view(center: Vec3, light_dir: Vec3, distance: f32) -> Mat4 {
+    let up = if light_dir.abs().y > 0.99 {
+        Vec3::Z
+    } else {
+        Vec3::Y
+    };
+
+    Mat4::look_at_rh(center - light_dir * distance, center, up)
+}
+
+// Bounding sphere of the slice, so the projection only changes size when the split does, and
+// snapped to whole shadow map texels, so camera movement doesn't make edges shimmer.
+fn stable_view_proj(corners: &[Vec3; 8], light_dir: Vec3, map_size: u32) -> Mat4 {
+    let center = corners.iter().copied().sum::<Vec3>() / corners.len() as f32;
+    let radius = corners
+        .iter()
+        .map(|corner| corner.distance(center))
+        .fold(0.0, f32::max);
+    let radius = (radius * 16.0).ceil() / 16.0;
+
+    // Casters up to one radius in front of the slice still land in the map
+    let view = light_view(center, light_dir, 2.0 * radius);
+    let mut proj = Mat4::orthographic_rh(-radius, radius, -radius, radius, 0.0, 3.0 * radius);
+
+    let texels_per_unit = map_size as f32 * 0.5;
+    let origin = (proj * view * Vec4::W) * texels_per_unit;
+    let offset = (origin.round() - origin) / texels_per_unit;
+    proj.w_axis.x += offset.x;
+    proj.w_axis.y += offset.y;
+
+    proj * view
+}
+
+// Smallest box around the slice; sharper than `stable_view_proj`, but it swims with the camera.
+fn tight_view_proj(corners: &[Vec3; 8], light_dir: Vec3) -> Mat4 {
+    let center = corners.iter().copied().sum::<Vec3>() / corners.len() as f32;
+    let view = light_view(center, light_dir, 1.0);
+
+    let (min, max) = corners.iter().fold(
+        (Vec3::splat(f32::MAX), Vec3::splat(f32::MIN)),
+        |(min, max), corner| {
+            let corner = view.transform_point3(*corner);
+            (min.min(corner), max.max(corner))
+        },
+    );
+
+    // Light space looks down -z; extend towards the light by the slice's depth for casters
+    let depth = max.z - min.z;
+    let proj = Mat4::orthographic_rh(min.x, max.x, min.y, max.y, -max.z - depth, -min.z);
+
+    proj * view
+}
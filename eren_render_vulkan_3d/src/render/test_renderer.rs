@@ -1,5 +1,13 @@
 use ash::vk;
-use eren_render_vulkan_core::renderer::{FrameContext, Renderer};
+use eren_render_vulkan_core::{
+    renderer::{FrameContext, RenderTargetInfo, Renderer, RendererError, ResizeContext},
+    vulkan::{
+        allocator::MemoryAllocator,
+        destruction::DestructionQueue,
+        device::DeviceManager,
+        features::{DeviceFeature, DeviceRequirements},
+    },
+};
 use thiserror::Error;
 
 use crate::{
@@ -19,6 +27,15 @@ pub enum TestRendererError {
     FinalPassCreationFailed(#[from] FinalPassError),
 }
 
+impl TestRendererError {
+    pub fn vk_result(&self) -> Option<vk::Result> {
+        match self {
+            Self::TestPassCreationFailed(e) => e.vk_result(),
+            Self::FinalPassCreationFailed(e) => e.vk_result(),
+        }
+    }
+}
+
 pub struct TestRenderer {
     test_pass: TestPass,
     final_pass: FinalPass,
@@ -26,20 +43,27 @@ pub struct TestRenderer {
 
 impl TestRenderer {
     pub fn new(
-        instance: &ash::Instance,
-        physical_device: vk::PhysicalDevice,
-        device: ash::Device,
-        swapchain_image_views: &Vec<vk::ImageView>,
-        surface_format: vk::Format,
-        image_extent: vk::Extent2D,
+        device_manager: &DeviceManager,
+        allocator: &MemoryAllocator,
+        destruction_queue: &DestructionQueue,
+        pipeline_cache: vk::PipelineCache,
+        render_target: &RenderTargetInfo,
     ) -> Result<Self, TestRendererError> {
-        let test_pass = TestPass::new(instance, physical_device, device.clone(), image_extent)?;
+        let image_extent = render_target.extent;
 
-        let final_pass = FinalPass::new(
-            device.clone(),
-            swapchain_image_views,
-            surface_format,
+        let test_pass = TestPass::new(
+            device_manager,
+            allocator,
+            destruction_queue,
+            pipeline_cache,
             image_extent,
+        )?;
+
+        let final_pass = FinalPass::new(
+            device_manager,
+            destruction_queue,
+            pipeline_cache,
+            render_target,
             test_pass.color_image_view,
         )?;
 
@@ -48,11 +72,45 @@ impl TestRenderer {
             final_pass,
         })
     }
+
+    pub fn resize(&mut self, resize_context: &ResizeContext) -> Result<(), TestRendererError> {
+        let render_target = resize_context.render_target;
+
+        self.test_pass
+            .resize(resize_context.allocator, render_target.extent)?;
+        self.final_pass
+            .resize(render_target, self.test_pass.color_image_view)?;
+
+        Ok(())
+    }
 }
 
 impl Renderer<RenderItem> for TestRenderer {
-    fn render(&self, frame_context: &FrameContext, _render_items: &[RenderItem]) {
+    fn device_requirements() -> DeviceRequirements {
+        DeviceRequirements::default()
+            .optional_feature(DeviceFeature::DynamicRendering)
+            .optional_feature(DeviceFeature::Synchronization2)
+    }
+
+    fn render(
+        &self,
+        frame_context: &FrameContext,
+        _render_items: &[RenderItem],
+    ) -> Result<(), RendererError> {
         self.test_pass.record(frame_context);
         self.final_pass.record(frame_context);
+
+        Ok(())
+    }
+
+    fn on_swapchain_recreated(
+        &mut self,
+        resize_context: &ResizeContext,
+    ) -> Result<(), RendererError> {
+        self.resize(resize_context)
+            .map_err(|e| RendererError::ResizeFailed {
+                vk_result: e.vk_result(),
+                source: e.into(),
+            })
     }
 }
@@ -0,0 +1,72 @@
+use ash::vk;
+use bytemuck::{Pod, Zeroable};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct VertexAttribute {
+    pub location: u32,
+    pub format: vk::Format,
+    pub offset: u32,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct VertexLayout {
+    pub stride: u32,
+    pub attributes: &'static [VertexAttribute],
+}
+
+impl VertexLayout {
+    pub fn binding_description(&self) -> vk::VertexInputBindingDescription {
+        vk::VertexInputBindingDescription::default()
+            .binding(0)
+            .stride(self.stride)
+            .input_rate(vk::VertexInputRate::VERTEX)
+    }
+
+    pub fn attribute_descriptions(&self) -> Vec<vk::VertexInputAttributeDescription> {
+        self.attributes
+            .iter()
+            .map(|attribute| {
+                vk::VertexInputAttributeDescription::default()
+                    .location(attribute.location)
+                    .binding(0)
+                    .format(attribute.format)
+                    .offset(attribute.offset)
+            })
+            .collect()
+    }
+}
+
+pub trait Vertex: Pod {
+    const LAYOUT: VertexLayout;
+}
+
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Pod, Zeroable)]
+pub struct Vertex3D {
+    pub position: [f32; 3],
+    pub normal: [f32; 3],
+    pub uv: [f32; 2],
+}
+
+impl Vertex for Vertex3D {
+    const LAYOUT: VertexLayout = VertexLayout {
+        stride: std::mem::size_of::<Vertex3D>() as u32,
+        attributes: &[
+            VertexAttribute {
+                location: 0,
+                format: vk::Format::R32G32B32_SFLOAT,
+                offset: std::mem::offset_of!(Vertex3D, position) as u32,
+            },
+            VertexAttribute {
+                location: 1,
+                format: vk::Format::R32G32B32_SFLOAT,
+                offset: std::mem::offset_of!(Vertex3D, normal) as u32,
+            },
+            VertexAttribute {
+                location: 2,
+                format: vk::Format::R32G32_SFLOAT,
+                offset: std::mem::offset_of!(Vertex3D, uv) as u32,
+            },
+        ],
+    };
+}
@@ -5,8 +5,44 @@ layout(location = 0) out vec4 outColor;
 
 layout(set = 0, binding = 0) uniform sampler2D inputColor;
 
+// 0: gamma 2.2 (SDR), 1: ST 2084 / PQ (HDR10), 2: linear (scRGB)
+layout(constant_id = 0) const uint OUTPUT_TRANSFER = 0;
+// Brightness of SDR white on HDR outputs
+layout(constant_id = 1) const float PAPER_WHITE_NITS = 203.0;
+
+const uint OUTPUT_TRANSFER_GAMMA = 0;
+const uint OUTPUT_TRANSFER_PQ = 1;
+const uint OUTPUT_TRANSFER_LINEAR = 2;
+
+// Linear BT.709 to linear BT.2020 (column-major)
+const mat3 BT709_TO_BT2020 = mat3(
+  0.6274, 0.0691, 0.0164,
+  0.3293, 0.9195, 0.0880,
+  0.0433, 0.0114, 0.8956
+);
+
+vec3 pqEncode(vec3 nits) {
+  const float m1 = 0.1593017578125;
+  const float m2 = 78.84375;
+  const float c1 = 0.8359375;
+  const float c2 = 18.8515625;
+  const float c3 = 18.6875;
+
+  vec3 y = pow(clamp(nits / 10000.0, 0.0, 1.0), vec3(m1));
+  return pow((c1 + c2 * y) / (1.0 + c3 * y), vec3(m2));
+}
+
 void main() {
   vec3 color = texture(inputColor, vUV).rgb;
-  color = pow(color, vec3(1.0 / 2.2));
+
+  if (OUTPUT_TRANSFER == OUTPUT_TRANSFER_PQ) {
+    color = pqEncode(BT709_TO_BT2020 * color * PAPER_WHITE_NITS);
+  } else if (OUTPUT_TRANSFER == OUTPUT_TRANSFER_LINEAR) {
+    // scRGB puts 1.0 at 80 nits
+    color = color * (PAPER_WHITE_NITS / 80.0);
+  } else {
+    color = pow(color, vec3(1.0 / 2.2));
+  }
+
   outColor = vec4(color, 1.0);
 }
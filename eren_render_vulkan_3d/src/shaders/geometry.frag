@@ -2,27 +2,130 @@
 
 layout(location = 0) in vec3 vNormal;
 layout(location = 1) in vec3 vWorldPos;
-layout(location = 2) in vec4 vShadowPos;
+layout(location = 2) in float vViewDepth;
 
 layout(location = 0) out vec4 outColor;
 
-layout(set = 1, binding = 0) uniform sampler2DShadow uShadow;
+// Same image twice: depth comparisons for filtering, raw depths for the PCSS blocker search
+// One layer per cascade
+layout(set = 1, binding = 0) uniform sampler2DArrayShadow uShadow;
+layout(set = 1, binding = 1) uniform sampler2DArray uShadowDepth;
 
 layout(set = 0, binding = 0) uniform CameraUBO {
   mat4 viewProj;
-  mat4 lightViewProj;
+  mat4 shadowView;
+  mat4 cascadeViewProj[4];
+  // View space distance where each cascade ends
+  vec4 cascadeSplits;
   vec3 lightDir;
+  uint cascadeCount;
 } uCam;
 
+const uint SHADOW_FILTER_PCF = 0;
+const uint SHADOW_FILTER_PCSS = 1;
+
+layout(push_constant) uniform ShadowParams {
+  layout(offset = 64) uint filterMode;
+  // Texels on each side of the center sample
+  uint pcfRadius;
+  // Penumbra width in shadow map UV per unit of light space depth between blocker and receiver
+  float lightSize;
+} uShadowParams;
+
+const int POISSON_SAMPLES = 16;
+const vec2 POISSON_DISK[POISSON_SAMPLES] = vec2[](
+  vec2(-0.94201624, -0.39906216), vec2(0.94558609, -0.76890725),
+  vec2(-0.09418410, -0.92938870), vec2(0.34495938, 0.29387760),
+  vec2(-0.91588581, 0.45771432), vec2(-0.81544232, -0.87912464),
+  vec2(-0.38277543, 0.27676845), vec2(0.97484398, 0.75648379),
+  vec2(0.44323325, -0.97511554), vec2(0.53742981, -0.47373420),
+  vec2(-0.26496911, -0.41893023), vec2(0.79197514, 0.19090188),
+  vec2(-0.24188840, 0.99706507), vec2(-0.81409955, 0.91437590),
+  vec2(0.19984126, 0.78641367), vec2(0.14383161, -0.14100790)
+);
+
+float shadowPcf(vec3 coords, float cascade) {
+  int radius = int(uShadowParams.pcfRadius);
+  vec2 texelSize = 1.0 / vec2(textureSize(uShadow, 0).xy);
+
+  float sum = 0.0;
+  for (int y = -radius; y <= radius; ++y) {
+    for (int x = -radius; x <= radius; ++x) {
+      sum += texture(uShadow, vec4(coords.xy + vec2(x, y) * texelSize, cascade, coords.z));
+    }
+  }
+
+  float width = float(2 * radius + 1);
+  return sum / (width * width);
+}
+
+float shadowPcss(vec3 coords, float cascade) {
+  vec2 texelSize = 1.0 / vec2(textureSize(uShadowDepth, 0).xy);
+
+  // Widest penumbra a blocker in front of the receiver can cast
+  float searchRadius = max(uShadowParams.lightSize * coords.z, texelSize.x);
+
+  float blockerDepth = 0.0;
+  int blockers = 0;
+  for (int i = 0; i < POISSON_SAMPLES; ++i) {
+    vec2 uv = coords.xy + POISSON_DISK[i] * searchRadius;
+    float depth = texture(uShadowDepth, vec3(uv, cascade)).r;
+    if (depth < coords.z) {
+      blockerDepth += depth;
+      blockers++;
+    }
+  }
+
+  if (blockers == 0) {
+    return 1.0;
+  }
+  blockerDepth /= float(blockers);
+
+  float filterRadius = max((coords.z - blockerDepth) * uShadowParams.lightSize, texelSize.x);
+
+  float sum = 0.0;
+  for (int i = 0; i < POISSON_SAMPLES; ++i) {
+    vec2 uv = coords.xy + POISSON_DISK[i] * filterRadius;
+    sum += texture(uShadow, vec4(uv, cascade, coords.z));
+  }
+
+  return sum / float(POISSON_SAMPLES);
+}
+
+float shadowVisibility() {
+  // First cascade that reaches this far; past the last one there is no shadow
+  uint cascade = 0;
+  while (cascade < uCam.cascadeCount && vViewDepth > uCam.cascadeSplits[cascade]) {
+    cascade++;
+  }
+  if (cascade == uCam.cascadeCount) {
+    return 1.0;
+  }
+
+  vec4 shadowPos = uCam.cascadeViewProj[cascade] * vec4(vWorldPos, 1.0);
+
+  // Vulkan clip space depth is already in [0, 1]; only x and y need remapping to UV.
+  vec3 coords = shadowPos.xyz / shadowPos.w;
+  coords.xy = coords.xy * 0.5 + 0.5;
+
+  // Beyond the far plane of the light; nothing there was rendered into the map
+  if (coords.z > 1.0) {
+    return 1.0;
+  }
+
+  if (uShadowParams.filterMode == SHADOW_FILTER_PCSS) {
+    return shadowPcss(coords, float(cascade));
+  }
+
+  return shadowPcf(coords, float(cascade));
+}
+
 void main() {
   vec3 N = normalize(vNormal);
   vec3 L = normalize(-uCam.lightDir);
   float diff = max(dot(N, L), 0.0);
 
-  vec3 proj = vShadowPos.xyz / vShadowPos.w;
-  proj = proj * 0.5 + 0.5;
-  //float visibility = texture(uShadow, proj);
-  float visibility = 1.0;
+  float visibility = diff > 0.0 ? shadowVisibility() : 1.0;
   float lightTerm = diff * visibility;
 
   vec3 albedo = vec3(0.7);
@@ -6,12 +6,16 @@ layout(location = 2) in vec2 inUV;
 
 layout(location = 0) out vec3 vNormal;
 layout(location = 1) out vec3 vWorldPos;
-layout(location = 2) out vec4 vShadowPos;
+// Depth in the view the shadow cascades were fitted to
+layout(location = 2) out float vViewDepth;
 
 layout(set = 0, binding = 0) uniform CameraUBO {
   mat4 viewProj;
-  mat4 lightViewProj;
+  mat4 shadowView;
+  mat4 cascadeViewProj[4];
+  vec4 cascadeSplits;
   vec3 lightDir;
+  uint cascadeCount;
 } uCam;
 
 layout(push_constant) uniform Push {
@@ -24,7 +28,7 @@ void main() {
   vec4 worldPos = modelMatrix * vec4(inPos, 1.0);
   vWorldPos     = worldPos.xyz;
   vNormal       = mat3(modelMatrix) * inNormal;
-  vShadowPos    = uCam.lightViewProj * worldPos;
+  vViewDepth    = -(uCam.shadowView * worldPos).z;
 
   gl_Position = uCam.viewProj * worldPos;
 }
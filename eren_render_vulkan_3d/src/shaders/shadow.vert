@@ -3,13 +3,14 @@
 layout(location = 0) in vec3 inPos;
 
 layout(set = 0, binding = 0) uniform LightVP {
-  mat4 lightViewProj;
+  mat4 lightViewProj[4];
 } uLight;
 
 layout(push_constant) uniform Push {
   mat4 modelMatrix;
+  uint cascadeIndex;
 } uPush;
 
 void main() {
-  gl_Position = uLight.lightViewProj * uPush.modelMatrix * vec4(inPos, 1.0);
+  gl_Position = uLight.lightViewProj[uPush.cascadeIndex] * uPush.modelMatrix * vec4(inPos, 1.0);
 }
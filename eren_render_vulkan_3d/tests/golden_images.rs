@@ -0,0 +1,519 @@
+use std::{
+    fs::File,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+use ash::vk;
+use eren_render_vulkan_3d::{
+    passes::shadow_pass::ShadowFilter,
+    render::{
+        camera::{Camera, CameraTarget},
+        mesh::Mesh,
+        render_item::{Material, RenderItem},
+        renderer_3d::{Renderer3D, Renderer3DSettings},
+        test_renderer::TestRenderer,
+        vertex::Vertex3D,
+    },
+};
+use eren_render_vulkan_core::{
+    context::{GraphicsContext, GraphicsContextConfig},
+    renderer::Renderer,
+    vulkan::{
+        instance::ValidationConfig, physical_device::PhysicalDeviceManagerError,
+        readback::CapturedFrame,
+    },
+};
+
+const WIDTH: u32 = 256;
+const HEIGHT: u32 = 256;
+
+// Frames rendered before the capture so every pass has settled.
+const WARMUP_FRAMES: usize = 3;
+
+// Per-channel difference that still counts as a match, to absorb driver rounding.
+const CHANNEL_TOLERANCE: u8 = 2;
+// Fraction of pixels allowed to exceed the channel tolerance.
+const MAX_MISMATCH_RATIO: f64 = 0.001;
+
+// Set to regenerate the reference images instead of comparing against them.
+const UPDATE_ENV: &str = "EREN_UPDATE_GOLDEN";
+// Set to fail instead of skip when no Vulkan device is available (e.g. CI with lavapipe).
+const REQUIRE_GPU_ENV: &str = "EREN_REQUIRE_GPU_TESTS";
+
+const PLANE_VERTS: [Vertex3D; 4] = [
+    Vertex3D {
+        position: [-1.0, 0.0, -1.0],
+        normal: [0.0, 1.0, 0.0],
+        uv: [0.0, 0.0],
+    },
+    Vertex3D {
+        position: [1.0, 0.0, -1.0],
+        normal: [0.0, 1.0, 0.0],
+        uv: [1.0, 0.0],
+    },
+    Vertex3D {
+        position: [1.0, 0.0, 1.0],
+        normal: [0.0, 1.0, 0.0],
+        uv: [1.0, 1.0],
+    },
+    Vertex3D {
+        position: [-1.0, 0.0, 1.0],
+        normal: [0.0, 1.0, 0.0],
+        uv: [0.0, 1.0],
+    },
+];
+const PLANE_IDXS: [u32; 6] = [0, 1, 2, 2, 3, 0];
+
+#[test]
+fn test_renderer_matches_golden() {
+    let Some(mut graphics_context) = create_headless_context() else {
+        return;
+    };
+
+    let mut renderer = {
+        let device_manager = graphics_context.device_manager.as_ref().unwrap();
+        let memory_allocator = graphics_context.memory_allocator.as_ref().unwrap();
+        let destruction_queue = graphics_context.destruction_queue.as_ref().unwrap();
+
+        TestRenderer::new(
+            device_manager,
+            memory_allocator,
+            destruction_queue,
+            graphics_context.pipeline_cache(),
+            &graphics_context.render_target_info().unwrap(),
+        )
+        .expect("Failed to create test renderer")
+    };
+
+    let frame = render_and_capture(&mut graphics_context, &mut renderer, &[]);
+    drop(renderer);
+
+    assert_matches_golden("test_renderer", &frame);
+}
+
+#[test]
+fn renderer_3d_empty_scene_matches_golden() {
+    let Some(mut graphics_context) = create_headless_context() else {
+        return;
+    };
+
+    let mut renderer = create_renderer_3d(&graphics_context);
+
+    let frame = render_and_capture(&mut graphics_context, &mut renderer, &[]);
+    drop(renderer);
+
+    assert_matches_golden("renderer_3d_empty", &frame);
+}
+
+#[test]
+fn renderer_3d_sphere_scene_matches_golden() {
+    let Some(mut graphics_context) = create_headless_context() else {
+        return;
+    };
+
+    let mut renderer = create_renderer_3d(&graphics_context);
+    let render_items = create_sphere_scene(&mut graphics_context);
+
+    let frame = render_and_capture(&mut graphics_context, &mut renderer, &render_items);
+    let geometry_frame = graphics_context
+        .capture_image(&renderer.geometry_readback_source())
+        .expect("Failed to capture geometry pass color target");
+
+    drop(renderer);
+    drop(render_items);
+
+    assert_matches_golden("renderer_3d_sphere", &frame);
+    assert_matches_golden("renderer_3d_sphere_geometry", &geometry_frame);
+}
+
+#[test]
+fn renderer_3d_sphere_scene_pcss_matches_golden() {
+    let Some(mut graphics_context) = create_headless_context() else {
+        return;
+    };
+
+    let mut renderer = create_renderer_3d(&graphics_context);
+    renderer.set_shadow_filter(ShadowFilter::Pcss { light_size: 0.1 });
+    let render_items = create_sphere_scene(&mut graphics_context);
+
+    let frame = render_and_capture(&mut graphics_context, &mut renderer, &render_items);
+
+    drop(renderer);
+    drop(render_items);
+
+    assert_matches_golden("renderer_3d_sphere_pcss", &frame);
+}
+
+#[test]
+fn renderer_3d_sphere_scene_reverse_z_matches_golden() {
+    let Some(mut graphics_context) = create_headless_context() else {
+        return;
+    };
+
+    let mut renderer = create_renderer_3d_with_settings(
+        &graphics_context,
+        &Renderer3DSettings {
+            reverse_z: true,
+            ..Default::default()
+        },
+    );
+    let render_items = create_sphere_scene(&mut graphics_context);
+
+    let frame = render_and_capture(&mut graphics_context, &mut renderer, &render_items);
+
+    drop(renderer);
+    drop(render_items);
+
+    // Same scene as `renderer_3d_sphere`, so it must match that reference too
+    assert_matches_golden("renderer_3d_sphere", &frame);
+}
+
+#[test]
+fn renderer_3d_camera_target_matches_golden() {
+    let Some(mut graphics_context) = create_headless_context() else {
+        return;
+    };
+
+    let mut renderer = create_renderer_3d(&graphics_context);
+    let camera_target = {
+        let device_manager = graphics_context.device_manager.as_ref().unwrap();
+        let memory_allocator = graphics_context.memory_allocator.as_ref().unwrap();
+        let destruction_queue = graphics_context.destruction_queue.as_ref().unwrap();
+
+        renderer
+            .create_camera_target(
+                device_manager,
+                memory_allocator,
+                destruction_queue,
+                graphics_context.pipeline_cache(),
+                vk::Extent2D {
+                    width: WIDTH,
+                    height: HEIGHT,
+                },
+            )
+            .expect("Failed to create camera target")
+    };
+    renderer
+        .set_camera(Camera::default().target(CameraTarget::Offscreen(camera_target)))
+        .expect("Failed to set camera");
+    let render_items = create_sphere_scene(&mut graphics_context);
+
+    render_and_capture(&mut graphics_context, &mut renderer, &render_items);
+    let target_frame = graphics_context
+        .capture_image(
+            &renderer
+                .camera_target_readback_source(camera_target)
+                .unwrap(),
+        )
+        .expect("Failed to capture camera target");
+
+    drop(renderer);
+    drop(render_items);
+
+    // The default camera drawn offscreen sees what the main geometry pass does
+    assert_matches_golden("renderer_3d_sphere_geometry", &target_frame);
+}
+
+fn create_headless_context() -> Option<GraphicsContext> {
+    let require_gpu = std::env::var_os(REQUIRE_GPU_ENV).is_some();
+
+    // Validation is skipped on machines without the layer, but any error it reports fails the test.
+    let config = GraphicsContextConfig {
+        validation: ValidationConfig {
+            enabled: true,
+            panic_on_error: true,
+            ..Default::default()
+        },
+        device_requirements: Renderer3D::device_requirements()
+            .merge(&TestRenderer::device_requirements()),
+        ..Default::default()
+    };
+
+    let mut graphics_context = match GraphicsContext::with_config(config) {
+        Ok(graphics_context) => graphics_context,
+        Err(e) if !require_gpu => {
+            eprintln!("Skipping golden image test, Vulkan is unavailable: {}", e);
+            return None;
+        }
+        Err(e) => panic!("Failed to create graphics context: {}", e),
+    };
+
+    match graphics_context.init_headless(WIDTH, HEIGHT) {
+        Ok(_) => Some(graphics_context),
+        Err(
+            eren_render_vulkan_core::context::GraphicsContextError::CreatePhysicalDeviceFailed(
+                PhysicalDeviceManagerError::NoSuitablePhysicalDevice,
+            ),
+        ) if !require_gpu => {
+            eprintln!("Skipping golden image test, no suitable Vulkan device");
+            None
+        }
+        Err(e) => panic!("Failed to initialize headless graphics context: {}", e),
+    }
+}
+
+fn create_renderer_3d(graphics_context: &GraphicsContext) -> Renderer3D {
+    create_renderer_3d_with_settings(graphics_context, &Renderer3DSettings::default())
+}
+
+fn create_renderer_3d_with_settings(
+    graphics_context: &GraphicsContext,
+    settings: &Renderer3DSettings,
+) -> Renderer3D {
+    let device_manager = graphics_context.device_manager.as_ref().unwrap();
+    let memory_allocator = graphics_context.memory_allocator.as_ref().unwrap();
+    let destruction_queue = graphics_context.destruction_queue.as_ref().unwrap();
+
+    Renderer3D::with_settings(
+        device_manager,
+        memory_allocator,
+        destruction_queue,
+        graphics_context.pipeline_cache(),
+        &graphics_context.render_target_info().unwrap(),
+        settings,
+    )
+    .expect("Failed to create 3D renderer")
+}
+
+fn render_and_capture<R: Renderer<RI>, RI>(
+    graphics_context: &mut GraphicsContext,
+    renderer: &mut R,
+    render_items: &[RI],
+) -> CapturedFrame {
+    for _ in 0..WARMUP_FRAMES {
+        graphics_context
+            .redraw(renderer, render_items)
+            .expect("Failed to render warmup frame");
+    }
+
+    graphics_context.request_frame_capture();
+    graphics_context
+        .redraw(renderer, render_items)
+        .expect("Failed to render captured frame");
+
+    graphics_context
+        .take_captured_frame()
+        .expect("Frame capture was requested but nothing was captured")
+}
+
+fn assert_matches_golden(name: &str, actual: &CapturedFrame) {
+    let reference_path = golden_dir().join(format!("{}.png", name));
+
+    if std::env::var_os(UPDATE_ENV).is_some() {
+        std::fs::create_dir_all(golden_dir()).unwrap();
+        actual
+            .save_png(&reference_path)
+            .expect("Failed to write reference image");
+        eprintln!("Updated reference image {}", reference_path.display());
+        return;
+    }
+
+    let Some(expected) = load_png(&reference_path) else {
+        let actual_path = write_artifact(name, "actual", actual);
+        panic!(
+            "Missing reference image {} (output written to {}); run with {}=1 to create it",
+            reference_path.display(),
+            actual_path.display(),
+            UPDATE_ENV
+        );
+    };
+
+    if (expected.width, expected.height) != (actual.width, actual.height) {
+        let actual_path = write_artifact(name, "actual", actual);
+        panic!(
+            "{}: size mismatch, expected {}x{} but rendered {}x{} (output written to {})",
+            name,
+            expected.width,
+            expected.height,
+            actual.width,
+            actual.height,
+            actual_path.display()
+        );
+    }
+
+    let (mismatched_pixels, diff) = diff_frames(&expected, actual);
+    let total_pixels = (actual.width * actual.height) as usize;
+    let mismatch_ratio = mismatched_pixels as f64 / total_pixels as f64;
+
+    if mismatch_ratio > MAX_MISMATCH_RATIO {
+        let actual_path = write_artifact(name, "actual", actual);
+        let diff_path = write_artifact(name, "diff", &diff);
+        panic!(
+            "{}: {} of {} pixels differ by more than {} ({:.3}% > {:.3}%); actual: {}, diff: {}",
+            name,
+            mismatched_pixels,
+            total_pixels,
+            CHANNEL_TOLERANCE,
+            mismatch_ratio * 100.0,
+            MAX_MISMATCH_RATIO * 100.0,
+            actual_path.display(),
+            diff_path.display()
+        );
+    }
+}
+
+// Mismatched pixels are painted red over a dimmed grayscale copy of the expected image.
+fn diff_frames(expected: &CapturedFrame, actual: &CapturedFrame) -> (usize, CapturedFrame) {
+    let mut mismatched_pixels = 0;
+    let mut pixels = Vec::with_capacity(actual.pixels.len());
+
+    for (expected_pixel, actual_pixel) in expected
+        .pixels
+        .chunks_exact(4)
+        .zip(actual.pixels.chunks_exact(4))
+    {
+        let max_difference = expected_pixel
+            .iter()
+            .zip(actual_pixel)
+            .map(|(&e, &a)| e.abs_diff(a))
+            .max()
+            .unwrap_or(0);
+
+        if max_difference > CHANNEL_TOLERANCE {
+            mismatched_pixels += 1;
+            pixels.extend_from_slice(&[255, 0, 0, 255]);
+        } else {
+            let luma = (expected_pixel[0] as u32 * 299
+                + expected_pixel[1] as u32 * 587
+                + expected_pixel[2] as u32 * 114)
+                / 1000;
+            let dimmed = (luma / 3) as u8;
+            pixels.extend_from_slice(&[dimmed, dimmed, dimmed, 255]);
+        }
+    }
+
+    (
+        mismatched_pixels,
+        CapturedFrame {
+            width: actual.width,
+            height: actual.height,
+            pixels,
+        },
+    )
+}
+
+fn golden_dir() -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("tests")
+        .join("golden")
+}
+
+fn write_artifact(name: &str, kind: &str, frame: &CapturedFrame) -> PathBuf {
+    let artifact_dir = Path::new(env!("CARGO_TARGET_TMPDIR")).join("golden");
+    std::fs::create_dir_all(&artifact_dir).unwrap();
+
+    let path = artifact_dir.join(format!("{}.{}.png", name, kind));
+    frame
+        .save_png(&path)
+        .expect("Failed to write golden test artifact");
+    path
+}
+
+fn load_png(path: &Path) -> Option<CapturedFrame> {
+    let file = File::open(path).ok()?;
+
+    let mut decoder = png::Decoder::new(file);
+    decoder.set_transformations(png::Transformations::EXPAND | png::Transformations::ALPHA);
+
+    let mut reader = decoder.read_info().expect("Failed to read reference PNG");
+    let mut pixels = vec![0; reader.output_buffer_size()];
+    let info = reader
+        .next_frame(&mut pixels)
+        .expect("Failed to decode reference PNG");
+
+    assert_eq!(
+        (info.color_type, info.bit_depth),
+        (png::ColorType::Rgba, png::BitDepth::Eight),
+        "Reference images must be 8-bit RGBA"
+    );
+
+    pixels.truncate(info.buffer_size());
+
+    Some(CapturedFrame {
+        width: info.width,
+        height: info.height,
+        pixels,
+    })
+}
+
+// Same plane + UV sphere scene as the `test_sphere` example.
+fn create_sphere_scene(graphics_context: &mut GraphicsContext) -> Vec<RenderItem> {
+    let (sphere_verts, sphere_idxs) = generate_uv_sphere(1.0, 32, 16);
+
+    let plane_mesh = create_mesh(graphics_context, &PLANE_VERTS, &PLANE_IDXS);
+    let sphere_mesh = create_mesh(graphics_context, &sphere_verts, &sphere_idxs);
+    let material = Arc::new(
+        Material::new(
+            &graphics_context.device_manager.as_ref().unwrap().device,
+            graphics_context.destruction_queue.as_ref().unwrap(),
+            &[],
+        )
+        .expect("Failed to create material"),
+    );
+
+    vec![
+        RenderItem {
+            mesh: plane_mesh,
+            material: material.clone(),
+            transform: glam::Mat4::IDENTITY,
+        },
+        RenderItem {
+            mesh: sphere_mesh,
+            material,
+            transform: glam::Mat4::IDENTITY,
+        },
+    ]
+}
+
+fn create_mesh(
+    graphics_context: &mut GraphicsContext,
+    vertices: &[Vertex3D],
+    indices: &[u32],
+) -> Arc<Mesh> {
+    let upload_manager = graphics_context.upload_manager.as_mut().unwrap();
+    let destruction_queue = graphics_context.destruction_queue.as_ref().unwrap();
+
+    let mesh = Mesh::builder(vertices)
+        .indices(indices)
+        .build(upload_manager, destruction_queue)
+        .expect("Failed to create mesh");
+    mesh.wait_for_upload(upload_manager)
+        .expect("Failed to upload mesh");
+
+    Arc::new(mesh)
+}
+
+fn generate_uv_sphere(radius: f32, lon: u32, lat: u32) -> (Vec<Vertex3D>, Vec<u32>) {
+    let mut verts = Vec::new();
+    let mut idxs = Vec::new();
+
+    for y in 0..=lat {
+        let v = y as f32 / lat as f32;
+        let theta = v * std::f32::consts::PI;
+        for x in 0..=lon {
+            let u = x as f32 / lon as f32;
+            let phi = u * std::f32::consts::TAU;
+            let pos = [
+                radius * phi.sin() * theta.sin(),
+                radius * theta.cos(),
+                radius * phi.cos() * theta.sin(),
+            ];
+            verts.push(Vertex3D {
+                position: pos,
+                normal: [pos[0] / radius, pos[1] / radius, pos[2] / radius],
+                uv: [u, v],
+            });
+        }
+    }
+
+    for y in 0..lat {
+        for x in 0..lon {
+            let i0 = y * (lon + 1) + x;
+            let i1 = i0 + lon + 1;
+            idxs.extend_from_slice(&[i0, i1, i0 + 1, i0 + 1, i1, i1 + 1]);
+        }
+    }
+
+    (verts, idxs)
+}
@@ -1,8 +1,8 @@
 use std::sync::Arc;
 
 use eren_render_vulkan_core::{
-    context::GraphicsContext,
-    renderer::{FrameContext, Renderer},
+    context::{GraphicsContext, GraphicsContextError},
+    renderer::{FrameContext, Renderer, RendererError},
 };
 use eren_window::window::{WindowConfig, WindowEventHandler, WindowLifecycleManager, WindowSize};
 use winit::window::Window;
@@ -25,7 +25,13 @@ struct EmptyRenderItem;
 struct EmptyRenderer;
 
 impl Renderer<EmptyRenderItem> for EmptyRenderer {
-    fn render(&self, _frame_context: &FrameContext, _render_items: &[EmptyRenderItem]) {}
+    fn render(
+        &self,
+        _frame_context: &FrameContext,
+        _render_items: &[EmptyRenderItem],
+    ) -> Result<(), RendererError> {
+        Ok(())
+    }
 }
 
 struct TestWindowEventHandler {
@@ -39,6 +45,15 @@ impl TestWindowEventHandler {
 
         self.renderer = Some(renderer);
     }
+    fn recover_device_lost(&mut self) {
+        self.renderer = None;
+
+        if let Err(e) = self.graphics_context.recover_device_lost() {
+            show_error_popup_and_panic(e, "Failed to recover from device loss");
+        }
+
+        self.recreate_renderer();
+    }
 }
 
 impl WindowEventHandler for TestWindowEventHandler {
@@ -71,13 +86,11 @@ impl WindowEventHandler for TestWindowEventHandler {
     }
 
     fn redraw(&mut self) {
-        if let Some(renderer) = &self.renderer {
+        if let Some(renderer) = &mut self.renderer {
+            // Swapchain changes are handled by the renderer itself
             match self.graphics_context.redraw(renderer, &[]) {
-                Ok(renderer_needs_recreation) => {
-                    if renderer_needs_recreation {
-                        self.recreate_renderer();
-                    }
-                }
+                Ok(_) => {}
+                Err(GraphicsContextError::DeviceLost) => self.recover_device_lost(),
                 Err(e) => show_error_popup_and_panic(e, "Failed to redraw graphics context"),
             }
         }
@@ -90,6 +103,8 @@ impl WindowEventHandler for TestWindowEventHandler {
 }
 
 fn main() {
+    env_logger::init();
+
     match WindowLifecycleManager::new(
         WindowConfig {
             width: 800,
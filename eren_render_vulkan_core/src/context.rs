@@ -1,4 +1,7 @@
-use std::sync::Arc;
+use std::{
+    path::PathBuf,
+    sync::{Arc, Mutex, Weak},
+};
 
 use ash::vk;
 use eren_window::window::WindowSize;
@@ -6,14 +9,26 @@ use thiserror::Error;
 use winit::window::Window;
 
 use crate::{
-    constants::MAX_FRAMES_IN_FLIGHT,
-    renderer::{FrameContext, Renderer},
+    renderer::{
+        DeviceResource, FrameContext, RenderTargetInfo, Renderer, RendererError, ResizeContext,
+        RestoreContext,
+    },
     vulkan::{
+        allocator::MemoryAllocator,
+        destruction::{DestructionQueue, ReleasedObject},
         device::{DeviceManager, DeviceManagerError},
-        instance::{VulkanInstanceManager, VulkanInstanceManagerError},
-        physical_device::{PhysicalDeviceManager, PhysicalDeviceManagerError},
+        features::DeviceRequirements,
+        instance::{ValidationConfig, VulkanInstanceManager, VulkanInstanceManagerError},
+        offscreen::{OffscreenManager, OffscreenManagerError},
+        physical_device::{
+            AdapterInfo, GpuPreference, PhysicalDeviceManager, PhysicalDeviceManagerError,
+        },
+        pipeline_cache::{PipelineCacheManager, PipelineCacheManagerError},
+        profiler::{GpuProfiler, GpuProfilerError},
+        readback::{CapturedFrame, ReadbackBuffer, ReadbackError, ReadbackSource},
         surface::{SurfaceManager, SurfaceManagerError},
-        swapchain::{SwapchainManager, SwapchainManagerError},
+        swapchain::{PresentConfig, SwapchainManager, SwapchainManagerError},
+        upload::{UploadManager, UploadManagerError},
     },
 };
 
@@ -37,60 +52,119 @@ pub enum GraphicsContextError {
     #[error("Failed to create swapchain: {0}")]
     CreateSwapchainFailed(#[from] SwapchainManagerError),
 
+    #[error("Failed to create pipeline cache: {0}")]
+    CreatePipelineCacheFailed(#[from] PipelineCacheManagerError),
+
+    #[error("Failed to create GPU profiler: {0}")]
+    CreateGpuProfilerFailed(#[from] GpuProfilerError),
+
+    #[error("Failed to create upload manager: {0}")]
+    CreateUploadManagerFailed(#[from] UploadManagerError),
+
+    #[error("Failed to create offscreen images: {0}")]
+    CreateOffscreenImagesFailed(#[from] OffscreenManagerError),
+
     #[error("Failed to create semaphores: {0}")]
-    CreateSemaphoresFailed(String),
+    CreateSemaphoresFailed(vk::Result),
 
     #[error("Failed to create fences: {0}")]
-    CreateFencesFailed(String),
+    CreateFencesFailed(vk::Result),
 
     #[error("Failed to create swapchain image views: {0}")]
-    CreateSwapchainImageViewsFailed(String),
+    CreateSwapchainImageViewsFailed(vk::Result),
 
     #[error("Failed to create command pool: {0}")]
-    CreateCommandPoolFailed(String),
+    CreateCommandPoolFailed(vk::Result),
 
     #[error("Failed to create command buffers: {0}")]
-    CreateCommandBuffersFailed(String),
+    CreateCommandBuffersFailed(vk::Result),
 
     #[error("Failed to acquire next image: {0}")]
-    AcquireNextImageFailed(String),
+    AcquireNextImageFailed(vk::Result),
 
     #[error("Failed to wait for fences: {0}")]
-    WaitForFencesFailed(String),
+    WaitForFencesFailed(vk::Result),
 
     #[error("Failed to reset command buffer: {0}")]
-    ResetCommandBufferFailed(String),
+    ResetCommandBufferFailed(vk::Result),
 
     #[error("Failed to begin command buffer: {0}")]
-    BeginCommandBufferFailed(String),
+    BeginCommandBufferFailed(vk::Result),
 
     #[error("Failed to end command buffer: {0}")]
-    EndCommandBufferFailed(String),
+    EndCommandBufferFailed(vk::Result),
 
     #[error("Failed to reset fences: {0}")]
-    ResetFencesFailed(String),
+    ResetFencesFailed(vk::Result),
 
     #[error("Failed to queue submit: {0}")]
-    QueueSubmitFailed(String),
+    QueueSubmitFailed(vk::Result),
 
     #[error("Failed to queue present: {0}")]
-    QueuePresentFailed(String),
+    QueuePresentFailed(vk::Result),
 
     #[error("Failed to wait for device idle: {0}")]
-    DeviceWaitIdleFailed(String),
+    DeviceWaitIdleFailed(vk::Result),
+
+    #[error("Failed to capture frame: {0}")]
+    FrameCaptureFailed(#[from] ReadbackError),
+
+    #[error("The current surface does not support frame capture")]
+    FrameCaptureUnsupported,
+
+    #[error("Failed to resize renderer: {0}")]
+    RendererResizeFailed(#[from] RendererError),
+
+    #[error("Failed to restore device resource: {0}")]
+    RestoreDeviceResourceFailed(RendererError),
+
+    #[error("Renderer failed to render frame: {0}")]
+    RenderFailed(RendererError),
+
+    // Recoverable with `GraphicsContext::recover_device_lost`
+    #[error("The device was lost")]
+    DeviceLost,
+
+    #[error("The surface was lost")]
+    SurfaceLost,
+
+    #[error("Graphics context is not initialized")]
+    NotInitialized,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct GraphicsContextConfig {
+    pub validation: ValidationConfig,
+    pub gpu_preference: GpuPreference,
+    // Merged with the engine's own needs, usually from `Renderer::device_requirements`.
+    pub device_requirements: DeviceRequirements,
+    // Can be changed later with `GraphicsContext::set_present_config`.
+    pub present: PresentConfig,
+    // Where the pipeline cache is persisted between runs; None keeps it in memory only.
+    pub pipeline_cache_dir: Option<PathBuf>,
+    // Times renderer scopes with timestamp queries; see `GraphicsContext::gpu_profiler`.
+    pub gpu_profiling: bool,
 }
 
 pub struct GraphicsContext {
     entry: ash::Entry,
+    config: GraphicsContextConfig,
 
     window: Option<Arc<Window>>,
     pub instance_manager: Option<VulkanInstanceManager>,
     surface_manager: Option<SurfaceManager>,
     pub physical_device_manager: Option<PhysicalDeviceManager>,
     pub device_manager: Option<DeviceManager>,
+    pub memory_allocator: Option<MemoryAllocator>,
+    pub pipeline_cache_manager: Option<PipelineCacheManager>,
+    pub upload_manager: Option<UploadManager>,
+    pub destruction_queue: Option<DestructionQueue>,
+    gpu_profiler: Option<GpuProfiler>,
 
     pub swapchain_manager: Option<SwapchainManager>,
+    pub offscreen_manager: Option<OffscreenManager>,
     pub swapchain_image_views: Vec<vk::ImageView>,
+    headless_extent: Option<vk::Extent2D>,
 
     command_pool: Option<vk::CommandPool>,
     command_buffers: Vec<vk::CommandBuffer>,
@@ -102,22 +176,39 @@ pub struct GraphicsContext {
 
     current_frame: usize,
     swapchain_needs_recreation: bool,
+
+    frame_capture_requested: bool,
+    captured_frame: Option<CapturedFrame>,
+
+    device_resources: Vec<Weak<Mutex<dyn DeviceResource>>>,
 }
 
 impl GraphicsContext {
     pub fn new() -> Result<Self, GraphicsContextError> {
+        Self::with_config(GraphicsContextConfig::default())
+    }
+
+    pub fn with_config(config: GraphicsContextConfig) -> Result<Self, GraphicsContextError> {
         let entry = unsafe { ash::Entry::load()? };
         Ok(Self {
             entry,
+            config,
 
             window: None,
             instance_manager: None,
             surface_manager: None,
             physical_device_manager: None,
             device_manager: None,
+            memory_allocator: None,
+            pipeline_cache_manager: None,
+            upload_manager: None,
+            destruction_queue: None,
+            gpu_profiler: None,
             swapchain_manager: None,
+            offscreen_manager: None,
 
             swapchain_image_views: Vec::new(),
+            headless_extent: None,
             command_pool: None,
             command_buffers: Vec::new(),
 
@@ -128,23 +219,135 @@ impl GraphicsContext {
 
             current_frame: 0,
             swapchain_needs_recreation: false,
+
+            frame_capture_requested: false,
+            captured_frame: None,
+            device_resources: Vec::new(),
         })
     }
 
     pub fn init(&mut self, window: Arc<Window>) -> Result<(), GraphicsContextError> {
-        let instance_manager = VulkanInstanceManager::new(&self.entry, window.clone())?;
+        let instance_manager =
+            VulkanInstanceManager::new(&self.entry, window.clone(), &self.config.validation)?;
         let surface_manager =
             SurfaceManager::new(&self.entry, &instance_manager.instance, window.clone())?;
+
+        self.window = Some(window);
+        self.init_with_instance(instance_manager, Some(surface_manager))
+    }
+
+    pub fn init_headless(&mut self, width: u32, height: u32) -> Result<(), GraphicsContextError> {
+        let instance_manager =
+            VulkanInstanceManager::new_headless(&self.entry, &self.config.validation)?;
+
+        self.headless_extent = Some(vk::Extent2D { width, height });
+        self.init_with_instance(instance_manager, None)
+    }
+
+    // Before `init` this uses a throwaway instance, so suitability is judged without a surface.
+    pub fn enumerate_adapters(&self) -> Result<Vec<AdapterInfo>, GraphicsContextError> {
+        if let Some(instance_manager) = &self.instance_manager {
+            return Ok(PhysicalDeviceManager::enumerate_adapters(
+                &instance_manager.instance,
+                self.surface_manager.as_ref(),
+                &self.config.device_requirements,
+            )?);
+        }
+
+        let instance_manager = VulkanInstanceManager::new_headless(
+            &self.entry,
+            &ValidationConfig {
+                enabled: false,
+                ..Default::default()
+            },
+        )?;
+
+        Ok(PhysicalDeviceManager::enumerate_adapters(
+            &instance_manager.instance,
+            None,
+            &self.config.device_requirements,
+        )?)
+    }
+
+    // Null before `init`, which pipeline creation treats as no cache.
+    pub fn pipeline_cache(&self) -> vk::PipelineCache {
+        self.pipeline_cache_manager
+            .as_ref()
+            .map_or(vk::PipelineCache::null(), |pipeline_cache_manager| {
+                pipeline_cache_manager.pipeline_cache
+            })
+    }
+
+    // Per-scope GPU timings, if `GraphicsContextConfig::gpu_profiling` is set and supported.
+    pub fn gpu_profiler(&self) -> Option<&GpuProfiler> {
+        self.gpu_profiler.as_ref()
+    }
+
+    pub fn is_headless(&self) -> bool {
+        self.headless_extent.is_some()
+    }
+
+    fn init_with_instance(
+        &mut self,
+        instance_manager: VulkanInstanceManager,
+        surface_manager: Option<SurfaceManager>,
+    ) -> Result<(), GraphicsContextError> {
         let physical_device_manager = PhysicalDeviceManager::new(
             &instance_manager.instance,
-            &surface_manager.surface_loader,
-            surface_manager.surface,
+            surface_manager.as_ref(),
+            &self.config.gpu_preference,
+            &self.config.device_requirements,
         )?;
         let device_manager = DeviceManager::new(
             &instance_manager.instance,
             physical_device_manager.physical_device,
             &physical_device_manager.queue_family_indices,
+            &physical_device_manager.enabled_features,
+            instance_manager.debug_utils_enabled(),
+        )?;
+        let memory_allocator = MemoryAllocator::new(
+            &instance_manager.instance,
+            physical_device_manager.physical_device,
+            &device_manager.device,
+        );
+        let pipeline_cache_manager = PipelineCacheManager::new(
+            &instance_manager.instance,
+            physical_device_manager.physical_device,
+            &device_manager.device,
+            self.config.pipeline_cache_dir.as_deref(),
+        )?;
+        let upload_manager = UploadManager::new(
+            &device_manager,
+            &memory_allocator,
+            &physical_device_manager.queue_family_indices,
         )?;
+        let destruction_queue = DestructionQueue::new(
+            &device_manager.device,
+            self.config.present.frames_in_flight(),
+        );
+        let gpu_profiler = if self.config.gpu_profiling {
+            let graphics_queue_family_index = physical_device_manager
+                .queue_family_indices
+                .graphics_queue_family_index
+                .expect("Graphics queue family index not found");
+
+            match GpuProfiler::new(
+                &instance_manager.instance,
+                physical_device_manager.physical_device,
+                &device_manager.device,
+                graphics_queue_family_index,
+                self.config.present.frames_in_flight(),
+            ) {
+                Ok(gpu_profiler) => Some(gpu_profiler),
+                Err(GpuProfilerError::TimestampsUnsupported) => {
+                    log::warn!("GPU profiling disabled, the graphics queue has no timestamps");
+                    None
+                }
+                Err(e) => return Err(e.into()),
+            }
+        } else {
+            None
+        };
 
         let command_pool = unsafe {
             device_manager
@@ -160,9 +363,38 @@ impl GraphicsContext {
                         .flags(vk::CommandPoolCreateFlags::RESET_COMMAND_BUFFER), // Or TRANSIENT if re-recorded often
                     None,
                 )
-                .map_err(|e| GraphicsContextError::CreateCommandPoolFailed(e.to_string()))?
+                .map_err(GraphicsContextError::CreateCommandPoolFailed)?
         };
 
+        self.instance_manager = Some(instance_manager);
+        self.surface_manager = surface_manager;
+        self.physical_device_manager = Some(physical_device_manager);
+        self.device_manager = Some(device_manager);
+        self.memory_allocator = Some(memory_allocator);
+        self.pipeline_cache_manager = Some(pipeline_cache_manager);
+        self.upload_manager = Some(upload_manager);
+        self.destruction_queue = Some(destruction_queue);
+        self.gpu_profiler = gpu_profiler;
+        self.command_pool = Some(command_pool);
+
+        self.create_frame_objects()?;
+        self.create_swapchain()?;
+        self.create_image_objects()?;
+
+        self.check_validation_errors();
+
+        Ok(())
+    }
+
+    // Command buffers and synchronization objects for each frame in flight.
+    fn create_frame_objects(&mut self) -> Result<(), GraphicsContextError> {
+        let (Some(device_manager), Some(command_pool)) = (&self.device_manager, self.command_pool)
+        else {
+            return Err(GraphicsContextError::NotInitialized);
+        };
+
+        let frames_in_flight = self.config.present.frames_in_flight();
+
         self.command_buffers = unsafe {
             device_manager
                 .device
@@ -170,19 +402,17 @@ impl GraphicsContext {
                     &vk::CommandBufferAllocateInfo::default()
                         .command_pool(command_pool)
                         .level(vk::CommandBufferLevel::PRIMARY)
-                        .command_buffer_count(MAX_FRAMES_IN_FLIGHT as u32),
+                        .command_buffer_count(frames_in_flight as u32),
                 )
-                .map_err(|e| GraphicsContextError::CreateCommandBuffersFailed(e.to_string()))?
+                .map_err(GraphicsContextError::CreateCommandBuffersFailed)?
         };
 
-        let semaphore_info = vk::SemaphoreCreateInfo::default();
-
-        for _ in 0..MAX_FRAMES_IN_FLIGHT {
+        for _ in 0..frames_in_flight {
             self.image_available_semaphores.push(unsafe {
                 device_manager
                     .device
-                    .create_semaphore(&semaphore_info, None)
-                    .map_err(|e| GraphicsContextError::CreateSemaphoresFailed(e.to_string()))?
+                    .create_semaphore(&vk::SemaphoreCreateInfo::default(), None)
+                    .map_err(GraphicsContextError::CreateSemaphoresFailed)?
             });
 
             self.frame_completion_fences.push(unsafe {
@@ -192,36 +422,150 @@ impl GraphicsContext {
                         &vk::FenceCreateInfo::default().flags(vk::FenceCreateFlags::SIGNALED),
                         None,
                     )
-                    .map_err(|e| GraphicsContextError::CreateFencesFailed(e.to_string()))?
+                    .map_err(GraphicsContextError::CreateFencesFailed)?
             });
         }
 
-        self.window = Some(window);
-        self.instance_manager = Some(instance_manager);
-        self.surface_manager = Some(surface_manager);
-        self.physical_device_manager = Some(physical_device_manager);
-        self.device_manager = Some(device_manager);
-        self.command_pool = Some(command_pool);
+        self.current_frame = 0;
 
-        self.create_swapchain()?;
+        Ok(())
+    }
+
+    // Must only be called while the device is idle.
+    fn destroy_frame_objects(&mut self) {
+        let Some(device_manager) = &self.device_manager else {
+            return;
+        };
+
+        unsafe {
+            if let Some(command_pool) = self.command_pool {
+                device_manager
+                    .device
+                    .free_command_buffers(command_pool, &self.command_buffers);
+            }
+
+            for semaphore in self.image_available_semaphores.drain(..) {
+                device_manager.device.destroy_semaphore(semaphore, None);
+            }
 
-        for _ in 0..self.swapchain_manager.as_ref().unwrap().amount_of_images {
+            for fence in self.frame_completion_fences.drain(..) {
+                device_manager.device.destroy_fence(fence, None);
+            }
+        }
+
+        self.command_buffers.clear();
+        // They point at the fences destroyed above.
+        self.image_in_flight_fences.fill(vk::Fence::null());
+    }
+
+    // Objects indexed by swapchain image, whose count can change whenever the swapchain is rebuilt.
+    fn create_image_objects(&mut self) -> Result<(), GraphicsContextError> {
+        let amount_of_images = self.amount_of_images();
+
+        let Some(device_manager) = &self.device_manager else {
+            return Err(GraphicsContextError::NotInitialized);
+        };
+
+        // Surplus semaphores are kept rather than destroyed, since a pending present may still use them.
+        while self.render_finished_semaphores.len() < amount_of_images {
             self.render_finished_semaphores.push(unsafe {
-                self.device_manager
-                    .as_ref()
-                    .unwrap()
+                device_manager
                     .device
-                    .create_semaphore(&semaphore_info, None)
-                    .map_err(|e| GraphicsContextError::CreateSemaphoresFailed(e.to_string()))?
+                    .create_semaphore(&vk::SemaphoreCreateInfo::default(), None)
+                    .map_err(GraphicsContextError::CreateSemaphoresFailed)?
             });
+        }
+
+        self.image_in_flight_fences
+            .resize(amount_of_images, vk::Fence::null());
 
-            self.image_in_flight_fences.push(vk::Fence::null());
+        Ok(())
+    }
+
+    pub fn present_config(&self) -> &PresentConfig {
+        &self.config.present
+    }
+
+    // Takes effect in place: the swapchain is rebuilt on the next redraw, which also resizes the
+    // renderer. Changing the frames in flight waits for the device to go idle.
+    pub fn set_present_config(
+        &mut self,
+        present_config: PresentConfig,
+    ) -> Result<(), GraphicsContextError> {
+        if present_config == self.config.present {
+            return Ok(());
         }
 
+        let frames_in_flight_changed =
+            present_config.frames_in_flight() != self.config.present.frames_in_flight();
+        self.config.present = present_config;
+
+        let Some(device_manager) = &self.device_manager else {
+            return Ok(());
+        };
+
+        if frames_in_flight_changed {
+            unsafe {
+                device_manager
+                    .device
+                    .device_wait_idle()
+                    .map_err(GraphicsContextError::DeviceWaitIdleFailed)?;
+            }
+
+            if let Some(destruction_queue) = &self.destruction_queue {
+                destruction_queue.set_frames_in_flight(self.config.present.frames_in_flight());
+            }
+
+            if let Some(gpu_profiler) = &self.gpu_profiler {
+                gpu_profiler.set_frames_in_flight(self.config.present.frames_in_flight())?;
+            }
+
+            self.destroy_frame_objects();
+            self.create_frame_objects()?;
+        }
+
+        self.swapchain_needs_recreation = true;
+
         Ok(())
     }
 
+    // Present modes the current surface supports, for settings menus.
+    pub fn available_present_modes(&self) -> Vec<vk::PresentModeKHR> {
+        let (Some(surface_manager), Some(physical_device_manager)) =
+            (&self.surface_manager, &self.physical_device_manager)
+        else {
+            return Vec::new();
+        };
+
+        unsafe {
+            surface_manager
+                .surface_loader
+                .get_physical_device_surface_present_modes(
+                    physical_device_manager.physical_device,
+                    surface_manager.surface,
+                )
+                .unwrap_or_default()
+        }
+    }
+
+    fn check_validation_errors(&self) {
+        if let Some(instance_manager) = &self.instance_manager {
+            instance_manager.check_validation_errors();
+        }
+    }
+
     fn create_swapchain(&mut self) -> Result<(), GraphicsContextError> {
+        self.create_swapchain_with_old(vk::SwapchainKHR::null())
+    }
+
+    fn create_swapchain_with_old(
+        &mut self,
+        old_swapchain: vk::SwapchainKHR,
+    ) -> Result<(), GraphicsContextError> {
+        if let Some(image_extent) = self.headless_extent {
+            return self.create_offscreen_images(image_extent);
+        }
+
         if let (
             Some(window),
             Some(instance_manager),
@@ -238,38 +582,18 @@ impl GraphicsContext {
             let swapchain_manager = SwapchainManager::new(
                 window,
                 &instance_manager.instance,
-                &surface_manager.surface_loader,
-                surface_manager.surface,
-                physical_device_manager.physical_device,
-                &physical_device_manager.queue_family_indices,
+                surface_manager,
+                physical_device_manager,
                 &device_manager.device,
+                &self.config.present,
+                old_swapchain,
             )?;
 
-            for &image in &swapchain_manager.swapchain_images {
-                let create_info = vk::ImageViewCreateInfo::default()
-                    .image(image)
-                    .view_type(vk::ImageViewType::TYPE_2D)
-                    .format(swapchain_manager.preferred_surface_format)
-                    .components(vk::ComponentMapping::default())
-                    .subresource_range(vk::ImageSubresourceRange {
-                        aspect_mask: vk::ImageAspectFlags::COLOR,
-                        base_mip_level: 0,
-                        level_count: 1,
-                        base_array_layer: 0,
-                        layer_count: 1,
-                    });
-
-                let image_view = unsafe {
-                    device_manager
-                        .device
-                        .create_image_view(&create_info, None)
-                        .map_err(|e| {
-                            GraphicsContextError::CreateSwapchainImageViewsFailed(e.to_string())
-                        })?
-                };
-
-                self.swapchain_image_views.push(image_view);
-            }
+            self.swapchain_image_views = create_image_views(
+                &device_manager.device,
+                &swapchain_manager.swapchain_images,
+                swapchain_manager.preferred_surface_format,
+            )?;
 
             self.swapchain_manager = Some(swapchain_manager);
         }
@@ -277,34 +601,257 @@ impl GraphicsContext {
         Ok(())
     }
 
-    pub fn resize(&mut self, _window_size: WindowSize) {
+    fn create_offscreen_images(
+        &mut self,
+        image_extent: vk::Extent2D,
+    ) -> Result<(), GraphicsContextError> {
+        if let (Some(device_manager), Some(memory_allocator)) =
+            (&self.device_manager, &self.memory_allocator)
+        {
+            let offscreen_manager = OffscreenManager::new(
+                &device_manager.device,
+                memory_allocator,
+                image_extent,
+                self.config.present.frames_in_flight(),
+            )?;
+
+            self.swapchain_image_views = create_image_views(
+                &device_manager.device,
+                &offscreen_manager.images,
+                offscreen_manager.format,
+            )?;
+
+            self.offscreen_manager = Some(offscreen_manager);
+        }
+
+        Ok(())
+    }
+
+    fn amount_of_images(&self) -> usize {
+        if let Some(swapchain_manager) = &self.swapchain_manager {
+            swapchain_manager.amount_of_images
+        } else if let Some(offscreen_manager) = &self.offscreen_manager {
+            offscreen_manager.amount_of_images
+        } else {
+            0
+        }
+    }
+
+    pub fn render_target_info(&self) -> Option<RenderTargetInfo> {
+        if let Some(swapchain_manager) = &self.swapchain_manager {
+            Some(RenderTargetInfo {
+                images: swapchain_manager.swapchain_images.clone(),
+                image_views: self.swapchain_image_views.clone(),
+                format: swapchain_manager.preferred_surface_format,
+                color_space: swapchain_manager.color_space,
+                extent: swapchain_manager.image_extent,
+                final_layout: vk::ImageLayout::PRESENT_SRC_KHR,
+            })
+        } else {
+            self.offscreen_manager
+                .as_ref()
+                .map(|offscreen_manager| RenderTargetInfo {
+                    images: offscreen_manager.images.clone(),
+                    image_views: self.swapchain_image_views.clone(),
+                    format: offscreen_manager.format,
+                    color_space: vk::ColorSpaceKHR::SRGB_NONLINEAR,
+                    extent: offscreen_manager.image_extent,
+                    // Offscreen frames are only ever read back, never presented.
+                    final_layout: vk::ImageLayout::TRANSFER_SRC_OPTIMAL,
+                })
+        }
+    }
+
+    fn frame_readback_source(
+        &self,
+        image_index: usize,
+    ) -> Result<ReadbackSource, GraphicsContextError> {
+        if let Some(swapchain_manager) = &self.swapchain_manager {
+            if !swapchain_manager
+                .image_usage
+                .contains(vk::ImageUsageFlags::TRANSFER_SRC)
+            {
+                return Err(GraphicsContextError::FrameCaptureUnsupported);
+            }
+
+            Ok(ReadbackSource {
+                image: swapchain_manager.swapchain_images[image_index],
+                format: swapchain_manager.preferred_surface_format,
+                color_space: swapchain_manager.color_space,
+                extent: swapchain_manager.image_extent,
+                layout: vk::ImageLayout::PRESENT_SRC_KHR,
+            })
+        } else if let Some(offscreen_manager) = &self.offscreen_manager {
+            Ok(ReadbackSource {
+                image: offscreen_manager.images[image_index],
+                format: offscreen_manager.format,
+                color_space: vk::ColorSpaceKHR::SRGB_NONLINEAR,
+                extent: offscreen_manager.image_extent,
+                layout: vk::ImageLayout::TRANSFER_SRC_OPTIMAL,
+            })
+        } else {
+            Err(GraphicsContextError::NotInitialized)
+        }
+    }
+
+    fn record_frame_capture(
+        &self,
+        command_buffer: vk::CommandBuffer,
+        image_index: usize,
+    ) -> Result<ReadbackBuffer, GraphicsContextError> {
+        let source = self.frame_readback_source(image_index)?;
+        let readback_buffer = self.create_readback_buffer(&source)?;
+        readback_buffer.record_copy(command_buffer, &source);
+
+        Ok(readback_buffer)
+    }
+
+    fn create_readback_buffer(
+        &self,
+        source: &ReadbackSource,
+    ) -> Result<ReadbackBuffer, GraphicsContextError> {
+        let (Some(device_manager), Some(memory_allocator)) =
+            (&self.device_manager, &self.memory_allocator)
+        else {
+            return Err(GraphicsContextError::NotInitialized);
+        };
+
+        Ok(ReadbackBuffer::new(
+            &device_manager.device,
+            memory_allocator,
+            source,
+        )?)
+    }
+
+    // The next redraw copies its final image out before presenting; see `take_captured_frame`.
+    pub fn request_frame_capture(&mut self) {
+        self.frame_capture_requested = true;
+    }
+
+    pub fn take_captured_frame(&mut self) -> Option<CapturedFrame> {
+        self.captured_frame.take()
+    }
+
+    // Copies an arbitrary image (e.g. an intermediate pass target) once all submitted frames are done.
+    pub fn capture_image(
+        &self,
+        source: &ReadbackSource,
+    ) -> Result<CapturedFrame, GraphicsContextError> {
+        let (Some(device_manager), Some(command_pool)) = (&self.device_manager, self.command_pool)
+        else {
+            return Err(GraphicsContextError::NotInitialized);
+        };
+
+        let device = &device_manager.device;
+        let readback_buffer = self.create_readback_buffer(source)?;
+
+        unsafe {
+            device
+                .wait_for_fences(&self.frame_completion_fences, true, u64::MAX)
+                .map_err(GraphicsContextError::WaitForFencesFailed)?;
+
+            let command_buffer = device
+                .allocate_command_buffers(
+                    &vk::CommandBufferAllocateInfo::default()
+                        .command_pool(command_pool)
+                        .level(vk::CommandBufferLevel::PRIMARY)
+                        .command_buffer_count(1),
+                )
+                .map_err(GraphicsContextError::CreateCommandBuffersFailed)?[0];
+
+            let fence = match device.create_fence(&vk::FenceCreateInfo::default(), None) {
+                Ok(fence) => fence,
+                Err(e) => {
+                    device.free_command_buffers(command_pool, &[command_buffer]);
+                    return Err(GraphicsContextError::CreateFencesFailed(e));
+                }
+            };
+
+            let submit_result = device
+                .begin_command_buffer(
+                    command_buffer,
+                    &vk::CommandBufferBeginInfo::default()
+                        .flags(vk::CommandBufferUsageFlags::ONE_TIME_SUBMIT),
+                )
+                .map_err(GraphicsContextError::BeginCommandBufferFailed)
+                .and_then(|_| {
+                    readback_buffer.record_copy(command_buffer, source);
+
+                    device
+                        .end_command_buffer(command_buffer)
+                        .map_err(GraphicsContextError::EndCommandBufferFailed)
+                })
+                .and_then(|_| {
+                    device
+                        .queue_submit(
+                            device_manager.graphics_queue,
+                            &[vk::SubmitInfo::default()
+                                .command_buffers(std::slice::from_ref(&command_buffer))],
+                            fence,
+                        )
+                        .map_err(GraphicsContextError::QueueSubmitFailed)
+                })
+                .and_then(|_| {
+                    device
+                        .wait_for_fences(&[fence], true, u64::MAX)
+                        .map_err(GraphicsContextError::WaitForFencesFailed)
+                });
+
+            device.destroy_fence(fence, None);
+            device.free_command_buffers(command_pool, &[command_buffer]);
+
+            submit_result?;
+        }
+
+        self.check_validation_errors();
+
+        Ok(readback_buffer.read()?)
+    }
+
+    pub fn resize(&mut self, window_size: WindowSize) {
+        if self.headless_extent.is_some() {
+            self.headless_extent = Some(vk::Extent2D {
+                width: window_size.width,
+                height: window_size.height,
+            });
+        }
+
         self.swapchain_needs_recreation = true;
     }
 
     pub fn destroy(&mut self) {
+        self.destroy_device_objects();
+
+        self.headless_extent = None;
+        self.surface_manager = None;
+        self.instance_manager = None;
+    }
+
+    // Everything created from the logical device, leaving the instance and surface alive.
+    fn destroy_device_objects(&mut self) {
+        if let Some(device_manager) = &self.device_manager {
+            // A lost device reports an error here, but is idle as far as destruction is concerned.
+            if let Err(e) = unsafe { device_manager.device.device_wait_idle() } {
+                log::warn!("Failed to wait for device idle: {}", e);
+            }
+
+            if let Some(destruction_queue) = &self.destruction_queue {
+                destruction_queue.flush();
+            }
+        }
+
+        self.destroy_frame_objects();
+
         unsafe {
             if let Some(device_manager) = &self.device_manager {
-                device_manager
-                    .device
-                    .device_wait_idle()
-                    .expect("Failed to wait for device idle");
-
                 for &image_view in &self.swapchain_image_views {
                     device_manager.device.destroy_image_view(image_view, None);
                 }
 
-                for &semaphore in self.image_available_semaphores.iter() {
-                    device_manager.device.destroy_semaphore(semaphore, None);
-                }
-
-                for &semaphore in self.render_finished_semaphores.iter() {
+                for semaphore in self.render_finished_semaphores.drain(..) {
                     device_manager.device.destroy_semaphore(semaphore, None);
                 }
 
-                for &fence in self.frame_completion_fences.iter() {
-                    device_manager.device.destroy_fence(fence, None);
-                }
-
                 // image_in_flight_fences contains copies of frame_completion_fences, no need to double destroy.
 
                 if let Some(command_pool) = self.command_pool {
@@ -315,142 +862,387 @@ impl GraphicsContext {
             }
         }
 
+        self.swapchain_image_views.clear();
+        self.image_in_flight_fences.clear();
+        self.command_pool = None;
+        // Saved to disk when dropped
+        self.pipeline_cache_manager = None;
+        self.gpu_profiler = None;
+        self.upload_manager = None;
         self.swapchain_manager = None;
+        self.offscreen_manager = None;
+        self.destruction_queue = None;
+        self.memory_allocator = None;
         self.device_manager = None;
         self.physical_device_manager = None;
+    }
+
+    // Restored after every `recover_device_lost` until the resource is dropped.
+    pub fn register_device_resource<R: DeviceResource + 'static>(
+        &mut self,
+        resource: &Arc<Mutex<R>>,
+    ) {
+        let resource: Weak<Mutex<dyn DeviceResource>> = Arc::downgrade(resource) as _;
+        self.device_resources.push(resource);
+    }
+
+    // Rebuilds the device and everything created from it after `GraphicsContextError::DeviceLost`.
+    // Renderers and other Vulkan objects of the old device must be dropped first, except registered
+    // device resources, which are released and restored here.
+    pub fn recover_device_lost(&mut self) -> Result<(), GraphicsContextError> {
+        self.device_resources
+            .retain(|resource| resource.strong_count() > 0);
+        let device_resources: Vec<Arc<Mutex<dyn DeviceResource>>> = self
+            .device_resources
+            .iter()
+            .filter_map(Weak::upgrade)
+            .collect();
+
+        for resource in &device_resources {
+            resource.lock().unwrap_or_else(|e| e.into_inner()).release();
+        }
+
+        self.destroy_device_objects();
+
+        let Some(instance_manager) = self.instance_manager.take() else {
+            return Err(GraphicsContextError::NotInitialized);
+        };
+        let surface_manager = self.surface_manager.take();
+
+        log::warn!("Device lost, recreating it");
+        self.init_with_instance(instance_manager, surface_manager)?;
+
+        let pipeline_cache = self.pipeline_cache();
+        let (
+            Some(device_manager),
+            Some(memory_allocator),
+            Some(upload_manager),
+            Some(destruction_queue),
+        ) = (
+            &self.device_manager,
+            &self.memory_allocator,
+            &mut self.upload_manager,
+            &self.destruction_queue,
+        )
+        else {
+            return Err(GraphicsContextError::NotInitialized);
+        };
+
+        let mut restore_context = RestoreContext {
+            device: &device_manager.device,
+            allocator: memory_allocator,
+            upload_manager,
+            destruction_queue,
+            debug_utils: &device_manager.debug_utils,
+            pipeline_cache,
+        };
+
+        for resource in &device_resources {
+            resource
+                .lock()
+                .unwrap_or_else(|e| e.into_inner())
+                .restore(&mut restore_context)
+                .map_err(GraphicsContextError::RestoreDeviceResourceFailed)?;
+        }
+
+        Ok(())
+    }
+
+    // A lost surface takes its swapchain with it, so both are created again from the window.
+    fn recreate_surface(&mut self) -> Result<(), GraphicsContextError> {
+        let (Some(window), Some(device_manager)) = (self.window.clone(), &self.device_manager)
+        else {
+            return Err(GraphicsContextError::NotInitialized);
+        };
+
+        unsafe {
+            device_manager
+                .device
+                .device_wait_idle()
+                .map_err(|e| frame_error(e, GraphicsContextError::DeviceWaitIdleFailed))?;
+        }
+
+        // The device is idle, so nothing has to wait for frames in flight.
+        if let Some(destruction_queue) = &self.destruction_queue {
+            destruction_queue.release_all(
+                self.swapchain_image_views
+                    .drain(..)
+                    .map(ReleasedObject::ImageView),
+            );
+            destruction_queue.flush();
+        }
+
+        self.swapchain_manager = None;
         self.surface_manager = None;
-        self.instance_manager = None;
+
+        let Some(instance_manager) = &self.instance_manager else {
+            return Err(GraphicsContextError::NotInitialized);
+        };
+        self.surface_manager = Some(SurfaceManager::new(
+            &self.entry,
+            &instance_manager.instance,
+            window,
+        )?);
+
+        log::warn!("Surface lost, recreating it");
+        self.create_swapchain()?;
+        self.image_in_flight_fences.fill(vk::Fence::null());
+        self.create_image_objects()
     }
 
     fn recreate_swapchain(&mut self) -> Result<(), GraphicsContextError> {
-        if let Some(device_manager) = &self.device_manager {
-            unsafe {
-                device_manager
-                    .device
-                    .device_wait_idle()
-                    .map_err(|e| GraphicsContextError::DeviceWaitIdleFailed(e.to_string()))?;
+        let Some(destruction_queue) = &self.destruction_queue else {
+            return Ok(());
+        };
 
-                for &image_view in &self.swapchain_image_views {
-                    device_manager.device.destroy_image_view(image_view, None);
-                }
-            };
+        // Frames still in flight may reference the old images, so they are retired through the
+        // destruction queue. That is only safe once the current slot has been submitted, or once
+        // every other frame has finished when it is skipped.
+        let old_swapchain_manager = self.swapchain_manager.take();
+        let old_swapchain = old_swapchain_manager
+            .as_ref()
+            .map_or(vk::SwapchainKHR::null(), |swapchain_manager| {
+                swapchain_manager.swapchain
+            });
 
-            self.swapchain_image_views.clear();
-            self.swapchain_manager = None;
+        destruction_queue.release_all(
+            self.swapchain_image_views
+                .drain(..)
+                .map(ReleasedObject::ImageView),
+        );
 
-            self.create_swapchain()?;
+        if let Some(offscreen_manager) = self.offscreen_manager.take() {
+            destruction_queue.release(ReleasedObject::Owned(Box::new(offscreen_manager)));
         }
-        Ok(())
+
+        // Released only after the new swapchain is created, since it is passed as the old swapchain.
+        let result = self.create_swapchain_with_old(old_swapchain);
+
+        if let (Some(destruction_queue), Some(old_swapchain_manager)) =
+            (&self.destruction_queue, old_swapchain_manager)
+        {
+            destruction_queue.release(ReleasedObject::Owned(Box::new(old_swapchain_manager)));
+        }
+
+        // Images of the new swapchain have not been used by any frame yet.
+        self.image_in_flight_fences.fill(vk::Fence::null());
+
+        result?;
+        self.create_image_objects()
     }
 
+    // Returns whether the swapchain was rebuilt, after `renderer` has been told about it.
     pub fn redraw<R: Renderer<RI>, RI>(
         &mut self,
-        renderer: &R,
+        renderer: &mut R,
         render_items: &[RI],
     ) -> Result<bool, GraphicsContextError> {
-        let mut renderer_needs_recreation = false;
+        let mut swapchain_recreated = false;
+        let mut surface_lost = false;
 
-        if let (Some(device_manager), Some(swapchain_manager)) =
-            (&self.device_manager, &self.swapchain_manager)
-        {
+        let Some(device_manager) = &self.device_manager else {
+            return Ok(swapchain_recreated);
+        };
+
+        if self.swapchain_manager.is_none() && self.offscreen_manager.is_none() {
+            return Ok(swapchain_recreated);
+        }
+
+        unsafe {
+            device_manager
+                .device
+                .wait_for_fences(
+                    &[self.frame_completion_fences[self.current_frame]],
+                    true,
+                    std::u64::MAX,
+                )
+                .map_err(|e| frame_error(e, GraphicsContextError::WaitForFencesFailed))?
+        };
+
+        // Everything released since this slot was last used is now safe to destroy.
+        if let Some(destruction_queue) = &self.destruction_queue {
+            destruction_queue.begin_frame(self.current_frame);
+        }
+
+        let image_index = match &self.swapchain_manager {
+            Some(swapchain_manager) => {
+                let acquire_result = unsafe {
+                    swapchain_manager.swapchain_loader.acquire_next_image(
+                        swapchain_manager.swapchain,
+                        u64::MAX,
+                        self.image_available_semaphores[self.current_frame],
+                        vk::Fence::null(), // Not using a fence here
+                    )
+                };
+
+                match acquire_result {
+                    Ok((image_index, is_suboptimal)) => {
+                        // Still presentable: draw this frame, rebuild the swapchain after it
+                        if is_suboptimal {
+                            self.swapchain_needs_recreation = true;
+                        }
+                        image_index
+                    }
+                    // Nothing was acquired, so the frame is skipped after rebuilding. The slot is
+                    // not submitted and its bin would be destroyed on the next redraw, so the
+                    // other frames in flight have to finish before anything goes into it.
+                    Err(vk::Result::ERROR_OUT_OF_DATE_KHR) => {
+                        self.swapchain_needs_recreation = false;
+                        unsafe {
+                            device_manager
+                                .device
+                                .wait_for_fences(&self.frame_completion_fences, true, u64::MAX)
+                                .map_err(|e| {
+                                    frame_error(e, GraphicsContextError::WaitForFencesFailed)
+                                })?;
+                        }
+                        self.recreate_swapchain()?;
+                        self.notify_swapchain_recreated(renderer)?;
+                        return Ok(true);
+                    }
+                    Err(vk::Result::ERROR_SURFACE_LOST_KHR) => {
+                        self.swapchain_needs_recreation = false;
+                        self.recreate_surface()?;
+                        self.notify_swapchain_recreated(renderer)?;
+                        return Ok(true);
+                    }
+                    Err(e) => {
+                        return Err(frame_error(e, GraphicsContextError::AcquireNextImageFailed));
+                    }
+                }
+            }
+            // Offscreen images are never held by a presentation engine, so they just rotate
+            None => (self.current_frame % self.amount_of_images()) as u32,
+        };
+
+        // Check if a previous frame is using this image (i.e. there is its fence to wait on)
+        if self.image_in_flight_fences[image_index as usize] != vk::Fence::null() {
             unsafe {
                 device_manager
                     .device
                     .wait_for_fences(
-                        &[self.frame_completion_fences[self.current_frame]],
+                        &[self.image_in_flight_fences[image_index as usize]],
                         true,
                         std::u64::MAX,
                     )
-                    .map_err(|e| GraphicsContextError::WaitForFencesFailed(e.to_string()))?
+                    .map_err(|e| frame_error(e, GraphicsContextError::WaitForFencesFailed))?
             };
+        }
 
-            let (image_index, _) = unsafe {
-                swapchain_manager
-                    .swapchain_loader
-                    .acquire_next_image(
-                        swapchain_manager.swapchain,
-                        u64::MAX,
-                        self.image_available_semaphores[self.current_frame],
-                        vk::Fence::null(), // Not using a fence here
-                    )
-                    .map_err(|e| GraphicsContextError::AcquireNextImageFailed(e.to_string()))?
-            };
+        // Mark the image as now being in use by this frame
+        self.image_in_flight_fences[image_index as usize] =
+            self.frame_completion_fences[self.current_frame];
 
-            // Check if a previous frame is using this image (i.e. there is its fence to wait on)
-            if self.image_in_flight_fences[image_index as usize] != vk::Fence::null() {
-                unsafe {
-                    device_manager
-                        .device
-                        .wait_for_fences(
-                            &[self.image_in_flight_fences[image_index as usize]],
-                            true,
-                            std::u64::MAX,
-                        )
-                        .map_err(|e| GraphicsContextError::WaitForFencesFailed(e.to_string()))?
-                };
-            }
+        let command_buffer = self.command_buffers[self.current_frame];
 
-            // Mark the image as now being in use by this frame
-            self.image_in_flight_fences[image_index as usize] =
-                self.frame_completion_fences[self.current_frame];
+        unsafe {
+            device_manager
+                .device
+                .reset_command_buffer(command_buffer, vk::CommandBufferResetFlags::empty())
+                .map_err(GraphicsContextError::ResetCommandBufferFailed)?;
+        }
 
-            let command_buffer = self.command_buffers[self.current_frame];
+        unsafe {
+            device_manager
+                .device
+                .begin_command_buffer(command_buffer, &vk::CommandBufferBeginInfo::default())
+                .map_err(GraphicsContextError::BeginCommandBufferFailed)?;
+        }
 
-            unsafe {
-                device_manager
-                    .device
-                    .reset_command_buffer(command_buffer, vk::CommandBufferResetFlags::empty())
-                    .map_err(|e| GraphicsContextError::ResetCommandBufferFailed(e.to_string()))?;
-            }
+        // Resolves the timings this slot recorded last time around, then resets its queries.
+        if let Some(gpu_profiler) = &self.gpu_profiler {
+            gpu_profiler.begin_frame(self.current_frame, command_buffer);
+        }
 
-            unsafe {
-                device_manager
-                    .device
-                    .begin_command_buffer(command_buffer, &vk::CommandBufferBeginInfo::default())
-                    .map_err(|e| GraphicsContextError::BeginCommandBufferFailed(e.to_string()))?;
-            }
+        let render_result = {
+            let frame_context = FrameContext {
+                command_buffer,
+                image_index: image_index as usize,
+                frame_index: self.current_frame,
+                gpu_profiler: self.gpu_profiler.as_ref(),
+                debug_utils: &device_manager.debug_utils,
+            };
+            let _frame_scope = frame_context.gpu_scope("Frame");
 
-            renderer.render(
-                &FrameContext {
-                    command_buffer,
-                    image_index: image_index as usize,
-                },
-                render_items,
-            );
+            renderer.render(&frame_context, render_items)
+        };
 
-            unsafe {
-                device_manager
-                    .device
-                    .end_command_buffer(command_buffer)
-                    .map_err(|e| GraphicsContextError::EndCommandBufferFailed(e.to_string()))?;
+        // Render and capture failures are only reported once the frame went through: the acquired
+        // image and its semaphore must still be submitted and presented.
+        let mut deferred_error = render_result.err().map(GraphicsContextError::RenderFailed);
+        let frame_readback_buffer = if self.frame_capture_requested {
+            self.frame_capture_requested = false;
+
+            match self.record_frame_capture(command_buffer, image_index as usize) {
+                Ok(readback_buffer) => Some(readback_buffer),
+                Err(e) => {
+                    deferred_error.get_or_insert(e);
+                    None
+                }
             }
+        } else {
+            None
+        };
+
+        unsafe {
+            device_manager
+                .device
+                .end_command_buffer(command_buffer)
+                .map_err(GraphicsContextError::EndCommandBufferFailed)?;
+        }
+
+        let wait_semaphore = self.image_available_semaphores[self.current_frame];
+        let signal_semaphore = self.render_finished_semaphores[image_index as usize];
+        let wait_dst_stage_mask = vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT;
 
-            let wait_semaphore = self.image_available_semaphores[self.current_frame];
-            let signal_semaphore = self.render_finished_semaphores[image_index as usize];
-            let wait_dst_stage_mask = vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT;
+        let mut submit_info =
+            vk::SubmitInfo::default().command_buffers(std::slice::from_ref(&command_buffer));
 
-            let submit_info = vk::SubmitInfo::default()
+        if self.swapchain_manager.is_some() {
+            submit_info = submit_info
                 .wait_semaphores(std::slice::from_ref(&wait_semaphore))
                 .wait_dst_stage_mask(std::slice::from_ref(&wait_dst_stage_mask))
-                .command_buffers(std::slice::from_ref(&command_buffer))
                 .signal_semaphores(std::slice::from_ref(&signal_semaphore));
+        }
 
-            unsafe {
-                device_manager
-                    .device
-                    .reset_fences(&[self.frame_completion_fences[self.current_frame]])
-                    .map_err(|e| GraphicsContextError::ResetFencesFailed(e.to_string()))?;
+        unsafe {
+            device_manager
+                .device
+                .reset_fences(&[self.frame_completion_fences[self.current_frame]])
+                .map_err(GraphicsContextError::ResetFencesFailed)?;
+
+            device_manager
+                .device
+                .queue_submit(
+                    device_manager.graphics_queue,
+                    std::slice::from_ref(&submit_info),
+                    self.frame_completion_fences[self.current_frame],
+                )
+                .map_err(|e| frame_error(e, GraphicsContextError::QueueSubmitFailed))?;
+        }
 
+        if let Some(readback_buffer) = frame_readback_buffer {
+            // Capturing stalls this one frame so the pixels can be handed back right away
+            unsafe {
                 device_manager
                     .device
-                    .queue_submit(
-                        device_manager.graphics_queue,
-                        std::slice::from_ref(&submit_info),
-                        self.frame_completion_fences[self.current_frame],
+                    .wait_for_fences(
+                        &[self.frame_completion_fences[self.current_frame]],
+                        true,
+                        u64::MAX,
                     )
-                    .map_err(|e| GraphicsContextError::QueueSubmitFailed(e.to_string()))?;
+                    .map_err(GraphicsContextError::WaitForFencesFailed)?;
             }
 
+            match readback_buffer.read() {
+                Ok(captured_frame) => self.captured_frame = Some(captured_frame),
+                Err(e) => {
+                    deferred_error.get_or_insert(e.into());
+                }
+            }
+        }
+
+        if let Some(swapchain_manager) = &self.swapchain_manager {
             let swapchain = swapchain_manager.swapchain;
             let present_info = vk::PresentInfoKHR::default()
                 .wait_semaphores(std::slice::from_ref(&signal_semaphore))
@@ -466,21 +1258,99 @@ impl GraphicsContext {
             match present_result {
                 Ok(is_suboptimal) if is_suboptimal => self.swapchain_needs_recreation = true,
                 Err(vk::Result::ERROR_OUT_OF_DATE_KHR) => self.swapchain_needs_recreation = true,
-                Err(e) => return Err(GraphicsContextError::QueuePresentFailed(e.to_string())),
+                Err(vk::Result::ERROR_SURFACE_LOST_KHR) => surface_lost = true,
+                Err(e) => return Err(frame_error(e, GraphicsContextError::QueuePresentFailed)),
                 _ => {}
             }
+        }
 
-            if self.swapchain_needs_recreation {
-                self.swapchain_needs_recreation = false;
-                self.recreate_swapchain()?;
-                renderer_needs_recreation = true;
-            }
+        if surface_lost {
+            self.swapchain_needs_recreation = false;
+            self.recreate_surface()?;
+            self.notify_swapchain_recreated(renderer)?;
+            swapchain_recreated = true;
+        } else if self.swapchain_needs_recreation {
+            self.swapchain_needs_recreation = false;
+            self.recreate_swapchain()?;
+            self.notify_swapchain_recreated(renderer)?;
+            swapchain_recreated = true;
+        }
 
-            self.current_frame = (self.current_frame + 1) % MAX_FRAMES_IN_FLIGHT;
+        self.current_frame = (self.current_frame + 1) % self.frame_completion_fences.len();
+
+        self.check_validation_errors();
+
+        match deferred_error {
+            Some(e) => Err(e),
+            None => Ok(swapchain_recreated),
         }
+    }
+
+    fn notify_swapchain_recreated<R: Renderer<RI>, RI>(
+        &self,
+        renderer: &mut R,
+    ) -> Result<(), GraphicsContextError> {
+        let (Some(memory_allocator), Some(destruction_queue), Some(render_target)) = (
+            &self.memory_allocator,
+            &self.destruction_queue,
+            self.render_target_info(),
+        ) else {
+            return Err(GraphicsContextError::NotInitialized);
+        };
+
+        renderer.on_swapchain_recreated(&ResizeContext {
+            allocator: memory_allocator,
+            destruction_queue,
+            render_target: &render_target,
+        })?;
 
-        Ok(renderer_needs_recreation)
+        Ok(())
+    }
+}
+
+// Device and surface loss get variants of their own, since callers can recover from them.
+fn frame_error(
+    result: vk::Result,
+    error: impl FnOnce(vk::Result) -> GraphicsContextError,
+) -> GraphicsContextError {
+    match result {
+        vk::Result::ERROR_DEVICE_LOST => GraphicsContextError::DeviceLost,
+        vk::Result::ERROR_SURFACE_LOST_KHR => GraphicsContextError::SurfaceLost,
+        result => error(result),
+    }
+}
+
+fn create_image_views(
+    device: &ash::Device,
+    images: &[vk::Image],
+    format: vk::Format,
+) -> Result<Vec<vk::ImageView>, GraphicsContextError> {
+    let mut image_views = Vec::with_capacity(images.len());
+
+    for &image in images {
+        let create_info = vk::ImageViewCreateInfo::default()
+            .image(image)
+            .view_type(vk::ImageViewType::TYPE_2D)
+            .format(format)
+            .components(vk::ComponentMapping::default())
+            .subresource_range(vk::ImageSubresourceRange {
+                aspect_mask: vk::ImageAspectFlags::COLOR,
+                base_mip_level: 0,
+                level_count: 1,
+                base_array_layer: 0,
+                layer_count: 1,
+            });
+
+        let image_view = unsafe {
+            device
+                .create_image_view(&create_info, None)
+                .map_err(GraphicsContextError::CreateSwapchainImageViewsFailed)?
+        };
+
+        image_views.push(image_view);
     }
+
+    Ok(image_views)
 }
 
 impl Drop for GraphicsContext {
@@ -1,11 +1,136 @@
 use ash::vk;
+use thiserror::Error;
 
-#[derive(Debug)]
-pub struct FrameContext {
+use crate::vulkan::{
+    allocator::MemoryAllocator,
+    debug::{DebugLabel, DebugUtils},
+    destruction::DestructionQueue,
+    features::DeviceRequirements,
+    profiler::{GpuProfiler, GpuScope},
+    upload::UploadManager,
+};
+
+// `source` is the renderer's own error, for downcasting; `vk_result` is the Vulkan result behind it,
+// if any, so callers can tell e.g. running out of memory apart from device loss without knowing
+// the renderer's error type.
+#[derive(Debug, Error)]
+pub enum RendererError {
+    #[error("Failed to resize renderer: {source}")]
+    ResizeFailed {
+        source: Box<dyn std::error::Error + Send + Sync>,
+        vk_result: Option<vk::Result>,
+    },
+
+    #[error("Failed to restore device resource: {source}")]
+    RestoreFailed {
+        source: Box<dyn std::error::Error + Send + Sync>,
+        vk_result: Option<vk::Result>,
+    },
+
+    #[error("Failed to render frame: {source}")]
+    RenderFailed {
+        source: Box<dyn std::error::Error + Send + Sync>,
+        vk_result: Option<vk::Result>,
+    },
+}
+
+impl RendererError {
+    pub fn vk_result(&self) -> Option<vk::Result> {
+        match self {
+            Self::ResizeFailed { vk_result, .. }
+            | Self::RestoreFailed { vk_result, .. }
+            | Self::RenderFailed { vk_result, .. } => *vk_result,
+        }
+    }
+
+    pub fn is_out_of_memory(&self) -> bool {
+        matches!(
+            self.vk_result(),
+            Some(vk::Result::ERROR_OUT_OF_DEVICE_MEMORY | vk::Result::ERROR_OUT_OF_HOST_MEMORY)
+        )
+    }
+}
+
+pub struct FrameContext<'a> {
     pub command_buffer: vk::CommandBuffer,
     pub image_index: usize,
+    // Slot of this frame among those in flight, always below `MAX_FRAMES_IN_FLIGHT`. Resources the
+    // CPU rewrites every frame keep one copy per slot, indexed by this.
+    pub frame_index: usize,
+    // None unless `GraphicsContextConfig::gpu_profiling` is set and timestamps are supported
+    pub gpu_profiler: Option<&'a GpuProfiler>,
+    pub debug_utils: &'a DebugUtils,
+}
+
+impl FrameContext<'_> {
+    // Times the commands recorded until the returned scope is dropped.
+    pub fn gpu_scope(&self, name: &'static str) -> GpuScope<'_> {
+        GpuScope::new(self.gpu_profiler, self.command_buffer, name)
+    }
+
+    // Groups the commands recorded until the returned label is dropped, in captures and messages.
+    pub fn debug_label(&self, name: &str) -> DebugLabel<'_> {
+        DebugLabel::new(self.debug_utils, self.command_buffer, name)
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct RenderTargetInfo {
+    // Same order as `image_views`; needed for the layout transitions of dynamic rendering.
+    pub images: Vec<vk::Image>,
+    pub image_views: Vec<vk::ImageView>,
+    pub format: vk::Format,
+    // Decides the transfer function the final pass encodes with, e.g. PQ for HDR10.
+    pub color_space: vk::ColorSpaceKHR,
+    pub extent: vk::Extent2D,
+    // Layout the final pass must leave the target image in at the end of a frame.
+    pub final_layout: vk::ImageLayout,
+}
+
+pub struct ResizeContext<'a> {
+    pub allocator: &'a MemoryAllocator,
+    pub destruction_queue: &'a DestructionQueue,
+    pub render_target: &'a RenderTargetInfo,
+}
+
+pub struct RestoreContext<'a> {
+    pub device: &'a ash::Device,
+    pub allocator: &'a MemoryAllocator,
+    pub upload_manager: &'a mut UploadManager,
+    pub destruction_queue: &'a DestructionQueue,
+    pub debug_utils: &'a DebugUtils,
+    pub pipeline_cache: vk::PipelineCache,
+}
+
+// Something that survives `GraphicsContext::recover_device_lost` by re-creating its Vulkan objects
+// from data it keeps on the CPU. See `GraphicsContext::register_device_resource`.
+pub trait DeviceResource {
+    // Every Vulkan object must be released here, before the lost device is destroyed.
+    fn release(&mut self);
+
+    fn restore(&mut self, restore_context: &mut RestoreContext) -> Result<(), RendererError>;
 }
 
 pub trait Renderer<R> {
-    fn render(&self, frame_context: &FrameContext, render_items: &[R]);
+    // Passed to `GraphicsContextConfig::device_requirements` before the device is created.
+    fn device_requirements() -> DeviceRequirements
+    where
+        Self: Sized,
+    {
+        DeviceRequirements::default()
+    }
+
+    // An error is returned from `GraphicsContext::redraw` once the frame has been submitted anyway,
+    // so the target must still end up in `RenderTargetInfo::final_layout`.
+    fn render(&self, frame_context: &FrameContext, render_items: &[R])
+    -> Result<(), RendererError>;
+
+    // Called by `GraphicsContext::redraw` after the swapchain was rebuilt. Only what depends on the
+    // render target needs recreating; the old objects should go through the destruction queue.
+    fn on_swapchain_recreated(
+        &mut self,
+        _resize_context: &ResizeContext,
+    ) -> Result<(), RendererError> {
+        Ok(())
+    }
 }
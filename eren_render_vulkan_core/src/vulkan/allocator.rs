@@ -0,0 +1,707 @@
+use std::sync::{Arc, Mutex, MutexGuard};
+
+use ash::vk;
+
+use crate::vulkan::memory::MemoryError;
+
+const DEFAULT_BLOCK_SIZE: vk::DeviceSize = 64 * 1024 * 1024;
+// Heaps up to this size (e.g. host-visible BAR windows) get blocks of an eighth of the heap instead.
+const SMALL_HEAP_MAX_SIZE: vk::DeviceSize = 1024 * 1024 * 1024;
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct AllocatorStats {
+    // vkDeviceMemory objects backing the sub-allocated pools
+    pub block_count: usize,
+    pub block_bytes: vk::DeviceSize,
+    // Resources that own their vkDeviceMemory
+    pub dedicated_allocation_count: usize,
+    pub dedicated_bytes: vk::DeviceSize,
+    // Live allocations of both kinds and the bytes they requested
+    pub allocation_count: usize,
+    pub allocated_bytes: vk::DeviceSize,
+}
+
+impl AllocatorStats {
+    pub fn device_memory_count(&self) -> usize {
+        self.block_count + self.dedicated_allocation_count
+    }
+}
+
+#[derive(Clone)]
+pub struct MemoryAllocator {
+    inner: Arc<Mutex<AllocatorInner>>,
+}
+
+impl MemoryAllocator {
+    pub fn new(
+        instance: &ash::Instance,
+        physical_device: vk::PhysicalDevice,
+        device: &ash::Device,
+    ) -> Self {
+        let memory_properties =
+            unsafe { instance.get_physical_device_memory_properties(physical_device) };
+
+        // One pool per memory type for buffers and linear images, one for optimal images,
+        // so neighbouring resources never violate bufferImageGranularity.
+        let pools = (0..memory_properties.memory_type_count as usize * 2)
+            .map(|_| MemoryPool { blocks: Vec::new() })
+            .collect();
+
+        Self {
+            inner: Arc::new(Mutex::new(AllocatorInner {
+                device: device.clone(),
+                memory_properties,
+                pools,
+                next_block_id: 0,
+                stats: AllocatorStats::default(),
+            })),
+        }
+    }
+
+    pub fn create_buffer(
+        &self,
+        size: vk::DeviceSize,
+        usage: vk::BufferUsageFlags,
+        memory_flags: vk::MemoryPropertyFlags,
+    ) -> Result<(vk::Buffer, Allocation), MemoryError> {
+        let mut inner = self.lock();
+        let device = inner.device.clone();
+
+        let buffer_info = vk::BufferCreateInfo::default()
+            .size(size)
+            .usage(usage)
+            .sharing_mode(vk::SharingMode::EXCLUSIVE);
+
+        let buffer = unsafe { device.create_buffer(&buffer_info, None) }
+            .map_err(MemoryError::CreateBufferFailed)?;
+
+        let mut dedicated_requirements = vk::MemoryDedicatedRequirements::default();
+        let mut requirements2 =
+            vk::MemoryRequirements2::default().push_next(&mut dedicated_requirements);
+
+        unsafe {
+            device.get_buffer_memory_requirements2(
+                &vk::BufferMemoryRequirementsInfo2::default().buffer(buffer),
+                &mut requirements2,
+            );
+        }
+
+        let requirements = requirements2.memory_requirements;
+
+        let request = AllocationRequest {
+            requirements,
+            memory_flags,
+            linear: true,
+            prefers_dedicated: dedicated_requirements.prefers_dedicated_allocation == vk::TRUE
+                || dedicated_requirements.requires_dedicated_allocation == vk::TRUE,
+            dedicated_resource: DedicatedResource::Buffer(buffer),
+        };
+
+        let allocation = match inner.allocate(&request) {
+            Ok(allocation) => allocation,
+            Err(e) => {
+                unsafe { device.destroy_buffer(buffer, None) };
+                return Err(e);
+            }
+        };
+
+        if let Err(e) =
+            unsafe { device.bind_buffer_memory(buffer, allocation.memory, allocation.offset) }
+        {
+            inner.free(&allocation);
+            unsafe { device.destroy_buffer(buffer, None) };
+            return Err(MemoryError::BindMemoryToBufferFailed(e));
+        }
+
+        drop(inner);
+
+        Ok((buffer, self.wrap(allocation)))
+    }
+
+    pub fn create_image(
+        &self,
+        image_info: &vk::ImageCreateInfo,
+        memory_flags: vk::MemoryPropertyFlags,
+    ) -> Result<(vk::Image, Allocation), MemoryError> {
+        let mut inner = self.lock();
+        let device = inner.device.clone();
+
+        let image = unsafe { device.create_image(image_info, None) }
+            .map_err(MemoryError::CreateImageFailed)?;
+
+        let mut dedicated_requirements = vk::MemoryDedicatedRequirements::default();
+        let mut requirements2 =
+            vk::MemoryRequirements2::default().push_next(&mut dedicated_requirements);
+
+        unsafe {
+            device.get_image_memory_requirements2(
+                &vk::ImageMemoryRequirementsInfo2::default().image(image),
+                &mut requirements2,
+            );
+        }
+
+        let requirements = requirements2.memory_requirements;
+
+        let request = AllocationRequest {
+            requirements,
+            memory_flags,
+            linear: image_info.tiling == vk::ImageTiling::LINEAR,
+            prefers_dedicated: dedicated_requirements.prefers_dedicated_allocation == vk::TRUE
+                || dedicated_requirements.requires_dedicated_allocation == vk::TRUE,
+            dedicated_resource: DedicatedResource::Image(image),
+        };
+
+        let allocation = match inner.allocate(&request) {
+            Ok(allocation) => allocation,
+            Err(e) => {
+                unsafe { device.destroy_image(image, None) };
+                return Err(e);
+            }
+        };
+
+        if let Err(e) =
+            unsafe { device.bind_image_memory(image, allocation.memory, allocation.offset) }
+        {
+            inner.free(&allocation);
+            unsafe { device.destroy_image(image, None) };
+            return Err(MemoryError::BindMemoryToImageFailed(e));
+        }
+
+        drop(inner);
+
+        Ok((image, self.wrap(allocation)))
+    }
+
+    pub fn stats(&self) -> AllocatorStats {
+        self.lock().stats
+    }
+
+    fn lock(&self) -> MutexGuard<'_, AllocatorInner> {
+        lock_inner(&self.inner)
+    }
+
+    fn wrap(&self, raw: RawAllocation) -> Allocation {
+        Allocation {
+            allocator: self.inner.clone(),
+            raw,
+        }
+    }
+}
+
+// Frees its memory range when dropped; the resource bound to it must be destroyed first.
+pub struct Allocation {
+    allocator: Arc<Mutex<AllocatorInner>>,
+    raw: RawAllocation,
+}
+
+// The mapped pointer is only dereferenced through `write`/`read_bytes`, bounded by the allocation.
+unsafe impl Send for Allocation {}
+unsafe impl Sync for Allocation {}
+
+impl Allocation {
+    pub fn memory(&self) -> vk::DeviceMemory {
+        self.raw.memory
+    }
+
+    pub fn offset(&self) -> vk::DeviceSize {
+        self.raw.offset
+    }
+
+    pub fn size(&self) -> vk::DeviceSize {
+        self.raw.size
+    }
+
+    pub fn is_dedicated(&self) -> bool {
+        matches!(self.raw.kind, AllocationKind::Dedicated)
+    }
+
+    // Host-visible memory stays mapped for its whole lifetime, so callers never map it themselves.
+    pub fn mapped_ptr(&self) -> Option<*mut u8> {
+        if self.raw.mapped_ptr.is_null() {
+            None
+        } else {
+            Some(self.raw.mapped_ptr)
+        }
+    }
+
+    pub fn write<T: Copy>(&self, offset: vk::DeviceSize, data: &[T]) -> Result<(), MemoryError> {
+        let len = std::mem::size_of_val(data) as vk::DeviceSize;
+        let ptr = self.host_range(offset, len)?;
+
+        unsafe {
+            std::ptr::copy_nonoverlapping(data.as_ptr() as *const u8, ptr, len as usize);
+        }
+
+        Ok(())
+    }
+
+    pub fn read_bytes(&self, offset: vk::DeviceSize, len: usize) -> Result<Vec<u8>, MemoryError> {
+        let ptr = self.host_range(offset, len as vk::DeviceSize)?;
+
+        Ok(unsafe { std::slice::from_raw_parts(ptr as *const u8, len) }.to_vec())
+    }
+
+    fn host_range(
+        &self,
+        offset: vk::DeviceSize,
+        len: vk::DeviceSize,
+    ) -> Result<*mut u8, MemoryError> {
+        let ptr = self.mapped_ptr().ok_or(MemoryError::MemoryNotHostVisible)?;
+
+        if offset
+            .checked_add(len)
+            .is_none_or(|end| end > self.raw.size)
+        {
+            return Err(MemoryError::AccessOutOfBounds {
+                offset,
+                len,
+                size: self.raw.size,
+            });
+        }
+
+        Ok(unsafe { ptr.add(offset as usize) })
+    }
+}
+
+impl Drop for Allocation {
+    fn drop(&mut self) {
+        lock_inner(&self.allocator).free(&self.raw);
+    }
+}
+
+fn lock_inner(inner: &Mutex<AllocatorInner>) -> MutexGuard<'_, AllocatorInner> {
+    // A panic while holding the lock cannot leave the free lists half-updated, so keep going.
+    inner.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+struct AllocationRequest {
+    requirements: vk::MemoryRequirements,
+    memory_flags: vk::MemoryPropertyFlags,
+    linear: bool,
+    prefers_dedicated: bool,
+    dedicated_resource: DedicatedResource,
+}
+
+#[derive(Clone, Copy)]
+enum DedicatedResource {
+    Buffer(vk::Buffer),
+    Image(vk::Image),
+}
+
+struct RawAllocation {
+    memory: vk::DeviceMemory,
+    offset: vk::DeviceSize,
+    size: vk::DeviceSize,
+    mapped_ptr: *mut u8,
+    kind: AllocationKind,
+}
+
+enum AllocationKind {
+    Block {
+        pool_index: usize,
+        block_id: u64,
+        // Range reserved in the block, including the padding in front of `offset`
+        range: MemoryRange,
+    },
+    Dedicated,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct MemoryRange {
+    offset: vk::DeviceSize,
+    size: vk::DeviceSize,
+}
+
+struct MemoryPool {
+    blocks: Vec<MemoryBlock>,
+}
+
+struct MemoryBlock {
+    id: u64,
+    memory: vk::DeviceMemory,
+    size: vk::DeviceSize,
+    mapped_ptr: *mut u8,
+    // Sorted by offset, adjacent ranges are always merged
+    free_ranges: Vec<MemoryRange>,
+    allocation_count: usize,
+}
+
+unsafe impl Send for MemoryBlock {}
+
+impl MemoryBlock {
+    fn try_allocate(
+        &mut self,
+        size: vk::DeviceSize,
+        alignment: vk::DeviceSize,
+    ) -> Option<(MemoryRange, vk::DeviceSize)> {
+        let alignment = alignment.max(1);
+
+        for i in 0..self.free_ranges.len() {
+            let free_range = self.free_ranges[i];
+            let aligned_offset = free_range.offset.next_multiple_of(alignment);
+            let reserved_size = aligned_offset - free_range.offset + size;
+
+            if reserved_size > free_range.size {
+                continue;
+            }
+
+            if reserved_size == free_range.size {
+                self.free_ranges.remove(i);
+            } else {
+                self.free_ranges[i] = MemoryRange {
+                    offset: free_range.offset + reserved_size,
+                    size: free_range.size - reserved_size,
+                };
+            }
+
+            self.allocation_count += 1;
+
+            return Some((
+                MemoryRange {
+                    offset: free_range.offset,
+                    size: reserved_size,
+                },
+                aligned_offset,
+            ));
+        }
+
+        None
+    }
+
+    fn release(&mut self, range: MemoryRange) {
+        let index = self
+            .free_ranges
+            .partition_point(|free_range| free_range.offset < range.offset);
+
+        self.free_ranges.insert(index, range);
+
+        if index + 1 < self.free_ranges.len() {
+            let current = self.free_ranges[index];
+            let next = self.free_ranges[index + 1];
+
+            if current.offset + current.size == next.offset {
+                self.free_ranges[index].size += next.size;
+                self.free_ranges.remove(index + 1);
+            }
+        }
+
+        if index > 0 {
+            let previous = self.free_ranges[index - 1];
+            let current = self.free_ranges[index];
+
+            if previous.offset + previous.size == current.offset {
+                self.free_ranges[index - 1].size += current.size;
+                self.free_ranges.remove(index);
+            }
+        }
+
+        self.allocation_count -= 1;
+    }
+}
+
+struct AllocatorInner {
+    device: ash::Device,
+    memory_properties: vk::PhysicalDeviceMemoryProperties,
+    pools: Vec<MemoryPool>,
+    next_block_id: u64,
+    stats: AllocatorStats,
+}
+
+impl AllocatorInner {
+    fn find_memory_type(
+        &self,
+        type_filter: u32,
+        properties: vk::MemoryPropertyFlags,
+    ) -> Result<u32, MemoryError> {
+        (0..self.memory_properties.memory_type_count)
+            .find(|&i| {
+                (type_filter & (1 << i)) != 0
+                    && self.memory_properties.memory_types[i as usize]
+                        .property_flags
+                        .contains(properties)
+            })
+            .ok_or(MemoryError::FindSuitableMemoryTypeFailed)
+    }
+
+    fn block_size(&self, memory_type_index: u32) -> vk::DeviceSize {
+        let heap_index = self.memory_properties.memory_types[memory_type_index as usize].heap_index;
+        let heap_size = self.memory_properties.memory_heaps[heap_index as usize].size;
+
+        if heap_size <= SMALL_HEAP_MAX_SIZE {
+            (heap_size / 8).max(1)
+        } else {
+            DEFAULT_BLOCK_SIZE
+        }
+    }
+
+    fn allocate(&mut self, request: &AllocationRequest) -> Result<RawAllocation, MemoryError> {
+        let requirements = request.requirements;
+        let memory_type_index =
+            self.find_memory_type(requirements.memory_type_bits, request.memory_flags)?;
+
+        let block_size = self.block_size(memory_type_index);
+
+        // Large resources (typically render targets) would waste most of a shared block.
+        if request.prefers_dedicated || requirements.size > block_size / 2 {
+            return self.allocate_dedicated(request, memory_type_index);
+        }
+
+        let pool_index = memory_type_index as usize * 2 + usize::from(!request.linear);
+
+        let mut found = None;
+        for block in self.pools[pool_index].blocks.iter_mut() {
+            if let Some((range, offset)) =
+                block.try_allocate(requirements.size, requirements.alignment)
+            {
+                found = Some((block.id, block.memory, block.mapped_ptr, range, offset));
+                break;
+            }
+        }
+
+        let (block_id, memory, block_mapped_ptr, range, offset) = match found {
+            Some(found) => found,
+            None => {
+                let (memory, mapped_ptr) =
+                    self.allocate_device_memory(block_size, memory_type_index, None)?;
+
+                let mut block = MemoryBlock {
+                    id: self.next_block_id,
+                    memory,
+                    size: block_size,
+                    mapped_ptr,
+                    free_ranges: vec![MemoryRange {
+                        offset: 0,
+                        size: block_size,
+                    }],
+                    allocation_count: 0,
+                };
+                self.next_block_id += 1;
+
+                let (range, offset) = block
+                    .try_allocate(requirements.size, requirements.alignment)
+                    .expect("Fresh memory block is too small for allocation");
+
+                let found = (block.id, block.memory, block.mapped_ptr, range, offset);
+
+                self.stats.block_count += 1;
+                self.stats.block_bytes += block_size;
+                self.pools[pool_index].blocks.push(block);
+
+                found
+            }
+        };
+
+        self.stats.allocation_count += 1;
+        self.stats.allocated_bytes += requirements.size;
+
+        Ok(RawAllocation {
+            memory,
+            offset,
+            size: requirements.size,
+            mapped_ptr: if block_mapped_ptr.is_null() {
+                block_mapped_ptr
+            } else {
+                unsafe { block_mapped_ptr.add(offset as usize) }
+            },
+            kind: AllocationKind::Block {
+                pool_index,
+                block_id,
+                range,
+            },
+        })
+    }
+
+    fn allocate_dedicated(
+        &mut self,
+        request: &AllocationRequest,
+        memory_type_index: u32,
+    ) -> Result<RawAllocation, MemoryError> {
+        let size = request.requirements.size;
+        let (memory, mapped_ptr) =
+            self.allocate_device_memory(size, memory_type_index, Some(request.dedicated_resource))?;
+
+        self.stats.dedicated_allocation_count += 1;
+        self.stats.dedicated_bytes += size;
+        self.stats.allocation_count += 1;
+        self.stats.allocated_bytes += size;
+
+        Ok(RawAllocation {
+            memory,
+            offset: 0,
+            size,
+            mapped_ptr,
+            kind: AllocationKind::Dedicated,
+        })
+    }
+
+    fn allocate_device_memory(
+        &self,
+        size: vk::DeviceSize,
+        memory_type_index: u32,
+        dedicated_resource: Option<DedicatedResource>,
+    ) -> Result<(vk::DeviceMemory, *mut u8), MemoryError> {
+        let mut dedicated_info = match dedicated_resource {
+            Some(DedicatedResource::Buffer(buffer)) => {
+                vk::MemoryDedicatedAllocateInfo::default().buffer(buffer)
+            }
+            Some(DedicatedResource::Image(image)) => {
+                vk::MemoryDedicatedAllocateInfo::default().image(image)
+            }
+            None => vk::MemoryDedicatedAllocateInfo::default(),
+        };
+
+        let mut alloc_info = vk::MemoryAllocateInfo::default()
+            .allocation_size(size)
+            .memory_type_index(memory_type_index);
+
+        if dedicated_resource.is_some() {
+            alloc_info = alloc_info.push_next(&mut dedicated_info);
+        }
+
+        let memory = unsafe { self.device.allocate_memory(&alloc_info, None) }
+            .map_err(MemoryError::AllocateMemoryFailed)?;
+
+        let host_visible = self.memory_properties.memory_types[memory_type_index as usize]
+            .property_flags
+            .contains(vk::MemoryPropertyFlags::HOST_VISIBLE);
+
+        if !host_visible {
+            return Ok((memory, std::ptr::null_mut()));
+        }
+
+        match unsafe {
+            self.device
+                .map_memory(memory, 0, vk::WHOLE_SIZE, vk::MemoryMapFlags::empty())
+        } {
+            Ok(ptr) => Ok((memory, ptr as *mut u8)),
+            Err(e) => {
+                unsafe { self.device.free_memory(memory, None) };
+                Err(MemoryError::MapMemoryFailed(e))
+            }
+        }
+    }
+
+    fn free(&mut self, allocation: &RawAllocation) {
+        self.stats.allocation_count -= 1;
+        self.stats.allocated_bytes -= allocation.size;
+
+        match allocation.kind {
+            AllocationKind::Dedicated => {
+                unsafe { self.device.free_memory(allocation.memory, None) };
+
+                self.stats.dedicated_allocation_count -= 1;
+                self.stats.dedicated_bytes -= allocation.size;
+            }
+            AllocationKind::Block {
+                pool_index,
+                block_id,
+                range,
+            } => {
+                let blocks = &mut self.pools[pool_index].blocks;
+                let Some(block_index) = blocks.iter().position(|block| block.id == block_id) else {
+                    return;
+                };
+
+                blocks[block_index].release(range);
+
+                // Keep one empty block per pool around so alternating create/destroy doesn't thrash.
+                if blocks[block_index].allocation_count == 0 && blocks.len() > 1 {
+                    let block = blocks.remove(block_index);
+
+                    unsafe { self.device.free_memory(block.memory, None) };
+
+                    self.stats.block_count -= 1;
+                    self.stats.block_bytes -= block.size;
+                }
+            }
+        }
+    }
+}
+
+impl Drop for AllocatorInner {
+    fn drop(&mut self) {
+        unsafe {
+            for pool in self.pools.iter() {
+                for block in pool.blocks.iter() {
+                    self.device.free_memory(block.memory, None);
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn block(size: vk::DeviceSize) -> MemoryBlock {
+        MemoryBlock {
+            id: 0,
+            memory: vk::DeviceMemory::null(),
+            size,
+            mapped_ptr: std::ptr::null_mut(),
+            free_ranges: vec![MemoryRange { offset: 0, size }],
+            allocation_count: 0,
+        }
+    }
+
+    fn free_ranges(block: &MemoryBlock) -> Vec<(vk::DeviceSize, vk::DeviceSize)> {
+        block
+            .free_ranges
+            .iter()
+            .map(|range| (range.offset, range.size))
+            .collect()
+    }
+
+    #[test]
+    fn try_allocate_aligns_offset_and_reserves_padding() {
+        let mut block = block(1024);
+
+        let (first, first_offset) = block.try_allocate(10, 1).unwrap();
+        assert_eq!((first.offset, first.size, first_offset), (0, 10, 0));
+
+        let (second, second_offset) = block.try_allocate(100, 256).unwrap();
+        assert_eq!(second_offset, 256);
+        // The padding in front of the aligned offset belongs to the allocation
+        assert_eq!((second.offset, second.size), (10, 346));
+
+        assert_eq!(free_ranges(&block), vec![(356, 668)]);
+        assert_eq!(block.allocation_count, 2);
+    }
+
+    #[test]
+    fn release_merges_with_both_neighbours() {
+        let mut block = block(300);
+
+        let (first, _) = block.try_allocate(100, 1).unwrap();
+        let (second, _) = block.try_allocate(100, 1).unwrap();
+        let (third, _) = block.try_allocate(100, 1).unwrap();
+        assert!(block.free_ranges.is_empty());
+
+        block.release(first);
+        block.release(third);
+        assert_eq!(free_ranges(&block), vec![(0, 100), (200, 100)]);
+
+        block.release(second);
+        assert_eq!(free_ranges(&block), vec![(0, 300)]);
+        assert_eq!(block.allocation_count, 0);
+    }
+
+    #[test]
+    fn try_allocate_fails_when_no_free_range_is_large_enough() {
+        let mut block = block(400);
+
+        let allocations: Vec<MemoryRange> = (0..4)
+            .map(|_| block.try_allocate(100, 1).unwrap().0)
+            .collect();
+        block.release(allocations[1]);
+        block.release(allocations[3]);
+
+        // 200 bytes are free in total, but split into two ranges of 100
+        assert!(block.try_allocate(150, 1).is_none());
+        // Alignment padding can push an allocation past a range that would otherwise fit
+        assert!(block.try_allocate(100, 128).is_none());
+        assert_eq!(free_ranges(&block), vec![(100, 100), (300, 100)]);
+        assert_eq!(block.allocation_count, 2);
+    }
+}
@@ -0,0 +1,184 @@
+use ash::vk;
+
+#[derive(Debug, Clone, Copy)]
+pub enum BarrierResource {
+    Buffer {
+        buffer: vk::Buffer,
+        offset: vk::DeviceSize,
+        size: vk::DeviceSize,
+    },
+    Image {
+        image: vk::Image,
+        subresource_range: vk::ImageSubresourceRange,
+    },
+}
+
+// A buffer or image memory barrier, optionally transitioning layout and/or moving the resource
+// between queue families.
+//
+// Moving ownership takes two halves with the same parameters: `record_release` on the source
+// queue, then `record_acquire` on the destination queue in a submission that waits on a semaphore
+// signaled by the release submission.
+#[derive(Debug, Clone, Copy)]
+pub struct ResourceBarrier {
+    pub resource: BarrierResource,
+    pub src_queue_family_index: u32,
+    pub dst_queue_family_index: u32,
+    // Ignored for buffers
+    pub old_layout: vk::ImageLayout,
+    pub new_layout: vk::ImageLayout,
+}
+
+impl ResourceBarrier {
+    pub fn buffer(buffer: vk::Buffer) -> Self {
+        Self::new(BarrierResource::Buffer {
+            buffer,
+            offset: 0,
+            size: vk::WHOLE_SIZE,
+        })
+    }
+
+    pub fn image(image: vk::Image, subresource_range: vk::ImageSubresourceRange) -> Self {
+        Self::new(BarrierResource::Image {
+            image,
+            subresource_range,
+        })
+    }
+
+    fn new(resource: BarrierResource) -> Self {
+        Self {
+            resource,
+            src_queue_family_index: vk::QUEUE_FAMILY_IGNORED,
+            dst_queue_family_index: vk::QUEUE_FAMILY_IGNORED,
+            old_layout: vk::ImageLayout::UNDEFINED,
+            new_layout: vk::ImageLayout::UNDEFINED,
+        }
+    }
+
+    pub fn layout_transition(
+        mut self,
+        old_layout: vk::ImageLayout,
+        new_layout: vk::ImageLayout,
+    ) -> Self {
+        self.old_layout = old_layout;
+        self.new_layout = new_layout;
+        self
+    }
+
+    // Same families make this an ordinary barrier, so callers needn't special-case shared queues.
+    pub fn queue_family_transfer(
+        mut self,
+        src_queue_family_index: u32,
+        dst_queue_family_index: u32,
+    ) -> Self {
+        if src_queue_family_index != dst_queue_family_index {
+            self.src_queue_family_index = src_queue_family_index;
+            self.dst_queue_family_index = dst_queue_family_index;
+        }
+        self
+    }
+
+    pub fn is_queue_family_transfer(&self) -> bool {
+        self.src_queue_family_index != self.dst_queue_family_index
+    }
+
+    // Source half of an ownership transfer; the destination access is ignored by Vulkan.
+    pub fn record_release(
+        &self,
+        device: &ash::Device,
+        command_buffer: vk::CommandBuffer,
+        src_stage: vk::PipelineStageFlags,
+        src_access: vk::AccessFlags,
+    ) {
+        self.record(
+            device,
+            command_buffer,
+            src_stage,
+            vk::PipelineStageFlags::BOTTOM_OF_PIPE,
+            src_access,
+            vk::AccessFlags::empty(),
+        );
+    }
+
+    // Destination half of an ownership transfer; the source access is ignored by Vulkan.
+    pub fn record_acquire(
+        &self,
+        device: &ash::Device,
+        command_buffer: vk::CommandBuffer,
+        dst_stage: vk::PipelineStageFlags,
+        dst_access: vk::AccessFlags,
+    ) {
+        // Chains with the semaphore wait, which should use `dst_stage` as its wait stage.
+        self.record(
+            device,
+            command_buffer,
+            dst_stage,
+            dst_stage,
+            vk::AccessFlags::empty(),
+            dst_access,
+        );
+    }
+
+    pub fn record(
+        &self,
+        device: &ash::Device,
+        command_buffer: vk::CommandBuffer,
+        src_stage: vk::PipelineStageFlags,
+        dst_stage: vk::PipelineStageFlags,
+        src_access: vk::AccessFlags,
+        dst_access: vk::AccessFlags,
+    ) {
+        unsafe {
+            match self.resource {
+                BarrierResource::Buffer {
+                    buffer,
+                    offset,
+                    size,
+                } => {
+                    let buffer_barrier = vk::BufferMemoryBarrier::default()
+                        .src_access_mask(src_access)
+                        .dst_access_mask(dst_access)
+                        .src_queue_family_index(self.src_queue_family_index)
+                        .dst_queue_family_index(self.dst_queue_family_index)
+                        .buffer(buffer)
+                        .offset(offset)
+                        .size(size);
+
+                    device.cmd_pipeline_barrier(
+                        command_buffer,
+                        src_stage,
+                        dst_stage,
+                        vk::DependencyFlags::empty(),
+                        &[],
+                        &[buffer_barrier],
+                        &[],
+                    );
+                }
+                BarrierResource::Image {
+                    image,
+                    subresource_range,
+                } => {
+                    let image_barrier = vk::ImageMemoryBarrier::default()
+                        .src_access_mask(src_access)
+                        .dst_access_mask(dst_access)
+                        .old_layout(self.old_layout)
+                        .new_layout(self.new_layout)
+                        .src_queue_family_index(self.src_queue_family_index)
+                        .dst_queue_family_index(self.dst_queue_family_index)
+                        .image(image)
+                        .subresource_range(subresource_range);
+
+                    device.cmd_pipeline_barrier(
+                        command_buffer,
+                        src_stage,
+                        dst_stage,
+                        vk::DependencyFlags::empty(),
+                        &[],
+                        &[],
+                        &[image_barrier],
+                    );
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,90 @@
+use std::ffi::CString;
+
+use ash::vk;
+
+// Object names and command buffer labels for captures and validation messages. Every call is a
+// no-op, without even building the name string, unless the instance enabled `VK_EXT_debug_utils`.
+#[derive(Clone)]
+pub struct DebugUtils {
+    debug_utils_device: Option<ash::ext::debug_utils::Device>,
+}
+
+impl DebugUtils {
+    pub fn new(instance: &ash::Instance, device: &ash::Device, enabled: bool) -> Self {
+        Self {
+            debug_utils_device: enabled
+                .then(|| ash::ext::debug_utils::Device::new(instance, device)),
+        }
+    }
+
+    pub fn disabled() -> Self {
+        Self {
+            debug_utils_device: None,
+        }
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.debug_utils_device.is_some()
+    }
+
+    pub fn set_object_name<H: vk::Handle>(&self, object: H, name: &str) {
+        let Some(debug_utils_device) = &self.debug_utils_device else {
+            return;
+        };
+
+        let name = to_c_string(name);
+        let name_info = vk::DebugUtilsObjectNameInfoEXT::default()
+            .object_handle(object)
+            .object_name(&name);
+
+        // Names only help debugging, so failing to set one is not worth surfacing.
+        if let Err(e) = unsafe { debug_utils_device.set_debug_utils_object_name(&name_info) } {
+            log::debug!("Failed to name {:?}: {}", name, e);
+        }
+    }
+
+    pub fn begin_label(&self, command_buffer: vk::CommandBuffer, name: &str) {
+        let Some(debug_utils_device) = &self.debug_utils_device else {
+            return;
+        };
+
+        let name = to_c_string(name);
+        let label = vk::DebugUtilsLabelEXT::default().label_name(&name);
+
+        unsafe { debug_utils_device.cmd_begin_debug_utils_label(command_buffer, &label) };
+    }
+
+    pub fn end_label(&self, command_buffer: vk::CommandBuffer) {
+        if let Some(debug_utils_device) = &self.debug_utils_device {
+            unsafe { debug_utils_device.cmd_end_debug_utils_label(command_buffer) };
+        }
+    }
+}
+
+// Ends its label when dropped, so a pass can wrap its whole `record` with one line.
+pub struct DebugLabel<'a> {
+    debug_utils: &'a DebugUtils,
+    command_buffer: vk::CommandBuffer,
+}
+
+impl<'a> DebugLabel<'a> {
+    pub fn new(debug_utils: &'a DebugUtils, command_buffer: vk::CommandBuffer, name: &str) -> Self {
+        debug_utils.begin_label(command_buffer, name);
+
+        Self {
+            debug_utils,
+            command_buffer,
+        }
+    }
+}
+
+impl Drop for DebugLabel<'_> {
+    fn drop(&mut self) {
+        self.debug_utils.end_label(self.command_buffer);
+    }
+}
+
+// Interior NULs would truncate the name anyway, so they are dropped instead of failing.
+fn to_c_string(name: &str) -> CString {
+    CString::new(name.replace('\0', "")).unwrap_or_default()
+}
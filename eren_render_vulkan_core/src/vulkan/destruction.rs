@@ -0,0 +1,149 @@
+use std::sync::{Arc, Mutex, MutexGuard};
+
+use ash::vk;
+
+use crate::vulkan::allocator::Allocation;
+
+pub enum ReleasedObject {
+    Buffer(vk::Buffer),
+    Image(vk::Image),
+    ImageView(vk::ImageView),
+    Sampler(vk::Sampler),
+    Framebuffer(vk::Framebuffer),
+    RenderPass(vk::RenderPass),
+    Pipeline(vk::Pipeline),
+    PipelineLayout(vk::PipelineLayout),
+    DescriptorSetLayout(vk::DescriptorSetLayout),
+    DescriptorPool(vk::DescriptorPool),
+    Allocation(Allocation),
+    // Anything that destroys its own Vulkan objects when dropped, e.g. a retired swapchain.
+    Owned(Box<dyn Send>),
+}
+
+// Holds released objects until the GPU can no longer be using them. Objects released while
+// frame slot N is current are destroyed the next time that slot's completion fence is waited on.
+#[derive(Clone)]
+pub struct DestructionQueue {
+    inner: Arc<Mutex<DestructionQueueInner>>,
+}
+
+impl DestructionQueue {
+    pub fn new(device: &ash::Device, frames_in_flight: usize) -> Self {
+        Self {
+            inner: Arc::new(Mutex::new(DestructionQueueInner {
+                device: device.clone(),
+                frames: (0..frames_in_flight).map(|_| Vec::new()).collect(),
+                current_frame: 0,
+            })),
+        }
+    }
+
+    pub fn release(&self, object: ReleasedObject) {
+        let mut inner = self.lock();
+        let current_frame = inner.current_frame;
+
+        inner.frames[current_frame].push(object);
+    }
+
+    pub fn release_all(&self, objects: impl IntoIterator<Item = ReleasedObject>) {
+        let mut inner = self.lock();
+        let current_frame = inner.current_frame;
+
+        inner.frames[current_frame].extend(objects);
+    }
+
+    pub fn pending_count(&self) -> usize {
+        self.lock().frames.iter().map(|objects| objects.len()).sum()
+    }
+
+    // Must only be called once the fence of `frame_index`'s previous submission has signaled.
+    pub(crate) fn begin_frame(&self, frame_index: usize) {
+        let (device, objects) = {
+            let mut inner = self.lock();
+            inner.current_frame = frame_index;
+
+            (
+                inner.device.clone(),
+                std::mem::take(&mut inner.frames[frame_index]),
+            )
+        };
+
+        // Destroyed outside the lock, since dropping an owned object may release more objects.
+        destroy_objects(&device, objects);
+    }
+
+    // Must only be called while the device is idle.
+    pub(crate) fn flush(&self) {
+        let (device, objects) = {
+            let mut inner = self.lock();
+            let objects: Vec<ReleasedObject> =
+                inner.frames.iter_mut().flat_map(std::mem::take).collect();
+
+            (inner.device.clone(), objects)
+        };
+
+        destroy_objects(&device, objects);
+    }
+
+    // Must only be called while the device is idle, since every pending object is destroyed.
+    pub(crate) fn set_frames_in_flight(&self, frames_in_flight: usize) {
+        self.flush();
+
+        let mut inner = self.lock();
+        inner.frames = (0..frames_in_flight).map(|_| Vec::new()).collect();
+        inner.current_frame = 0;
+    }
+
+    fn lock(&self) -> MutexGuard<'_, DestructionQueueInner> {
+        // The bins are plain vectors, so a panic while holding the lock cannot corrupt them.
+        self.inner.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+struct DestructionQueueInner {
+    device: ash::Device,
+    frames: Vec<Vec<ReleasedObject>>,
+    current_frame: usize,
+}
+
+impl Drop for DestructionQueueInner {
+    fn drop(&mut self) {
+        for objects in self.frames.iter_mut() {
+            destroy_objects(&self.device, std::mem::take(objects));
+        }
+    }
+}
+
+fn destroy_objects(device: &ash::Device, objects: Vec<ReleasedObject>) {
+    for object in objects {
+        unsafe {
+            match object {
+                ReleasedObject::Buffer(buffer) => device.destroy_buffer(buffer, None),
+                ReleasedObject::Image(image) => device.destroy_image(image, None),
+                ReleasedObject::ImageView(image_view) => {
+                    device.destroy_image_view(image_view, None)
+                }
+                ReleasedObject::Sampler(sampler) => device.destroy_sampler(sampler, None),
+                ReleasedObject::Framebuffer(framebuffer) => {
+                    device.destroy_framebuffer(framebuffer, None)
+                }
+                ReleasedObject::RenderPass(render_pass) => {
+                    device.destroy_render_pass(render_pass, None)
+                }
+                ReleasedObject::Pipeline(pipeline) => device.destroy_pipeline(pipeline, None),
+                ReleasedObject::PipelineLayout(pipeline_layout) => {
+                    device.destroy_pipeline_layout(pipeline_layout, None)
+                }
+                ReleasedObject::DescriptorSetLayout(descriptor_set_layout) => {
+                    device.destroy_descriptor_set_layout(descriptor_set_layout, None)
+                }
+                ReleasedObject::DescriptorPool(descriptor_pool) => {
+                    device.destroy_descriptor_pool(descriptor_pool, None)
+                }
+                // Dropping frees the memory (or the owned objects).
+                ReleasedObject::Allocation(allocation) => drop(allocation),
+                ReleasedObject::Owned(object) => drop(object),
+            }
+        }
+    }
+}
@@ -2,20 +2,24 @@ use ash::vk;
 use thiserror::Error;
 
 use crate::vulkan::{
-    physical_device::{get_required_device_extensions, get_required_device_features},
-    queue::QueueFamilyIndices,
+    debug::DebugUtils, features::EnabledDeviceFeatures, queue::QueueFamilyIndices,
 };
 
 #[derive(Debug, Error)]
 pub enum DeviceManagerError {
     #[error("Failed to create device: {0}")]
-    CreateDeviceFailed(String),
+    CreateDeviceFailed(vk::Result),
 }
 
 pub struct DeviceManager {
     pub device: ash::Device,
     pub graphics_queue: vk::Queue,
     pub present_queue: vk::Queue,
+    pub transfer_queue: vk::Queue,
+    // The graphics queue when the device has no separate compute family
+    pub compute_queue: vk::Queue,
+    pub enabled_features: EnabledDeviceFeatures,
+    pub debug_utils: DebugUtils,
 }
 
 impl DeviceManager {
@@ -23,56 +27,72 @@ impl DeviceManager {
         instance: &ash::Instance,
         physical_device: vk::PhysicalDevice,
         queue_family_indices: &QueueFamilyIndices,
+        enabled_features: &EnabledDeviceFeatures,
+        debug_utils_enabled: bool,
     ) -> Result<Self, DeviceManagerError> {
         let graphics_index = queue_family_indices.graphics_queue_family_index.unwrap();
-        let present_index = queue_family_indices.present_queue_family_index.unwrap();
+        // Headless devices have no present family; the graphics queue stands in for it.
+        let present_index = queue_family_indices
+            .present_queue_family_index
+            .unwrap_or(graphics_index);
+        let transfer_index = queue_family_indices
+            .transfer_queue_family_index
+            .unwrap_or(graphics_index);
+        let compute_index = queue_family_indices
+            .compute_queue_family_index
+            .unwrap_or(graphics_index);
 
-        let mut queue_infos = Vec::new();
-        let queue_priority = [1.0f32];
+        let mut unique_indices = vec![graphics_index];
+        for index in [present_index, transfer_index, compute_index] {
+            if !unique_indices.contains(&index) {
+                unique_indices.push(index);
+            }
+        }
 
-        if graphics_index == present_index {
-            queue_infos.push(
-                vk::DeviceQueueCreateInfo::default()
-                    .queue_family_index(graphics_index)
-                    .queue_priorities(&queue_priority),
-            );
-        } else {
-            queue_infos.push(
-                vk::DeviceQueueCreateInfo::default()
-                    .queue_family_index(graphics_index)
-                    .queue_priorities(&queue_priority),
-            );
-            queue_infos.push(
+        let queue_priority = [1.0f32];
+        let queue_infos: Vec<vk::DeviceQueueCreateInfo> = unique_indices
+            .iter()
+            .map(|&index| {
                 vk::DeviceQueueCreateInfo::default()
-                    .queue_family_index(present_index)
-                    .queue_priorities(&queue_priority),
-            );
-        }
+                    .queue_family_index(index)
+                    .queue_priorities(&queue_priority)
+            })
+            .collect();
 
-        let required_device_features = get_required_device_features();
-        let raw_required_device_extensions: Vec<*const i8> = get_required_device_extensions()
+        let mut feature_set = enabled_features.to_feature_set();
+        let mut features2 = feature_set.features2();
+        let raw_enabled_extensions: Vec<*const i8> = enabled_features
+            .extensions
             .iter()
             .map(|s| s.as_ptr())
             .collect();
 
         let device_info = vk::DeviceCreateInfo::default()
+            .push_next(&mut features2)
             .queue_create_infos(&queue_infos)
-            .enabled_features(&required_device_features)
-            .enabled_extension_names(&raw_required_device_extensions);
+            .enabled_extension_names(&raw_enabled_extensions);
 
         let device = unsafe {
             instance
                 .create_device(physical_device, &device_info, None)
-                .map_err(|e| DeviceManagerError::CreateDeviceFailed(e.to_string()))?
+                .map_err(DeviceManagerError::CreateDeviceFailed)?
         };
 
         let graphics_queue = unsafe { device.get_device_queue(graphics_index, 0) };
         let present_queue = unsafe { device.get_device_queue(present_index, 0) };
+        let transfer_queue = unsafe { device.get_device_queue(transfer_index, 0) };
+        let compute_queue = unsafe { device.get_device_queue(compute_index, 0) };
+
+        let debug_utils = DebugUtils::new(instance, &device, debug_utils_enabled);
 
         Ok(Self {
             device,
             graphics_queue,
             present_queue,
+            transfer_queue,
+            compute_queue,
+            enabled_features: enabled_features.clone(),
+            debug_utils,
         })
     }
 }
@@ -0,0 +1,272 @@
+use std::ffi::CStr;
+
+use ash::vk;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum DeviceFeature {
+    ShaderClipDistance,
+    SamplerAnisotropy,
+    FillModeNonSolid,
+    // Bindless-style descriptor arrays (Vulkan 1.2)
+    DescriptorIndexing,
+    // Vulkan 1.2
+    TimelineSemaphore,
+    // Vulkan 1.2
+    BufferDeviceAddress,
+    // Vulkan 1.3
+    DynamicRendering,
+    // Vulkan 1.3
+    Synchronization2,
+}
+
+impl DeviceFeature {
+    fn is_supported(&self, supported: &FeatureSet) -> bool {
+        let core = &supported.core;
+        let vulkan12 = &supported.vulkan12;
+        let vulkan13 = &supported.vulkan13;
+
+        match self {
+            DeviceFeature::ShaderClipDistance => core.shader_clip_distance == vk::TRUE,
+            DeviceFeature::SamplerAnisotropy => core.sampler_anisotropy == vk::TRUE,
+            DeviceFeature::FillModeNonSolid => core.fill_mode_non_solid == vk::TRUE,
+            DeviceFeature::DescriptorIndexing => {
+                vulkan12.descriptor_indexing == vk::TRUE
+                    && vulkan12.runtime_descriptor_array == vk::TRUE
+                    && vulkan12.shader_sampled_image_array_non_uniform_indexing == vk::TRUE
+                    && vulkan12.descriptor_binding_partially_bound == vk::TRUE
+                    && vulkan12.descriptor_binding_variable_descriptor_count == vk::TRUE
+                    && vulkan12.descriptor_binding_sampled_image_update_after_bind == vk::TRUE
+            }
+            DeviceFeature::TimelineSemaphore => vulkan12.timeline_semaphore == vk::TRUE,
+            DeviceFeature::BufferDeviceAddress => vulkan12.buffer_device_address == vk::TRUE,
+            DeviceFeature::DynamicRendering => vulkan13.dynamic_rendering == vk::TRUE,
+            DeviceFeature::Synchronization2 => vulkan13.synchronization2 == vk::TRUE,
+        }
+    }
+
+    fn enable(&self, enabled: &mut FeatureSet) {
+        let core = &mut enabled.core;
+        let vulkan12 = &mut enabled.vulkan12;
+        let vulkan13 = &mut enabled.vulkan13;
+
+        match self {
+            DeviceFeature::ShaderClipDistance => core.shader_clip_distance = vk::TRUE,
+            DeviceFeature::SamplerAnisotropy => core.sampler_anisotropy = vk::TRUE,
+            DeviceFeature::FillModeNonSolid => core.fill_mode_non_solid = vk::TRUE,
+            DeviceFeature::DescriptorIndexing => {
+                vulkan12.descriptor_indexing = vk::TRUE;
+                vulkan12.runtime_descriptor_array = vk::TRUE;
+                vulkan12.shader_sampled_image_array_non_uniform_indexing = vk::TRUE;
+                vulkan12.descriptor_binding_partially_bound = vk::TRUE;
+                vulkan12.descriptor_binding_variable_descriptor_count = vk::TRUE;
+                vulkan12.descriptor_binding_sampled_image_update_after_bind = vk::TRUE;
+            }
+            DeviceFeature::TimelineSemaphore => vulkan12.timeline_semaphore = vk::TRUE,
+            DeviceFeature::BufferDeviceAddress => vulkan12.buffer_device_address = vk::TRUE,
+            DeviceFeature::DynamicRendering => vulkan13.dynamic_rendering = vk::TRUE,
+            DeviceFeature::Synchronization2 => vulkan13.synchronization2 = vk::TRUE,
+        }
+
+        match self {
+            DeviceFeature::DescriptorIndexing
+            | DeviceFeature::TimelineSemaphore
+            | DeviceFeature::BufferDeviceAddress => enabled.uses_vulkan12 = true,
+            DeviceFeature::DynamicRendering | DeviceFeature::Synchronization2 => {
+                enabled.uses_vulkan13 = true
+            }
+            _ => {}
+        }
+    }
+}
+
+// What a renderer needs from the device. Required entries make a device unsuitable when missing,
+// optional ones are enabled only where available.
+#[derive(Debug, Clone, Default)]
+pub struct DeviceRequirements {
+    pub required_features: Vec<DeviceFeature>,
+    pub optional_features: Vec<DeviceFeature>,
+    pub required_extensions: Vec<&'static CStr>,
+    pub optional_extensions: Vec<&'static CStr>,
+}
+
+impl DeviceRequirements {
+    pub fn require_feature(mut self, feature: DeviceFeature) -> Self {
+        push_unique(&mut self.required_features, feature);
+        self
+    }
+
+    pub fn optional_feature(mut self, feature: DeviceFeature) -> Self {
+        push_unique(&mut self.optional_features, feature);
+        self
+    }
+
+    pub fn require_extension(mut self, extension_name: &'static CStr) -> Self {
+        push_unique(&mut self.required_extensions, extension_name);
+        self
+    }
+
+    pub fn optional_extension(mut self, extension_name: &'static CStr) -> Self {
+        push_unique(&mut self.optional_extensions, extension_name);
+        self
+    }
+
+    // Combines the needs of several renderers; something required by either side stays required.
+    pub fn merge(mut self, other: &DeviceRequirements) -> Self {
+        for &feature in &other.required_features {
+            push_unique(&mut self.required_features, feature);
+        }
+        for &feature in &other.optional_features {
+            push_unique(&mut self.optional_features, feature);
+        }
+        for &extension_name in &other.required_extensions {
+            push_unique(&mut self.required_extensions, extension_name);
+        }
+        for &extension_name in &other.optional_extensions {
+            push_unique(&mut self.optional_extensions, extension_name);
+        }
+        self
+    }
+
+    // The enabled set for `physical_device`, or None when a required entry is missing.
+    pub(crate) fn negotiate(
+        &self,
+        instance: &ash::Instance,
+        physical_device: vk::PhysicalDevice,
+    ) -> Option<EnabledDeviceFeatures> {
+        let supported_features = FeatureSet::query(instance, physical_device);
+        let available_extensions = unsafe {
+            instance
+                .enumerate_device_extension_properties(physical_device)
+                .unwrap_or_else(|_| Vec::new())
+        };
+
+        let is_extension_available = |extension_name: &CStr| {
+            available_extensions
+                .iter()
+                .any(|extension| extension.extension_name_as_c_str() == Ok(extension_name))
+        };
+
+        if !self
+            .required_features
+            .iter()
+            .all(|feature| feature.is_supported(&supported_features))
+            || !self
+                .required_extensions
+                .iter()
+                .all(|extension_name| is_extension_available(extension_name))
+        {
+            return None;
+        }
+
+        let mut enabled = EnabledDeviceFeatures {
+            features: self.required_features.clone(),
+            extensions: self.required_extensions.clone(),
+        };
+
+        for &feature in &self.optional_features {
+            if feature.is_supported(&supported_features) {
+                push_unique(&mut enabled.features, feature);
+            }
+        }
+
+        for &extension_name in &self.optional_extensions {
+            if is_extension_available(extension_name) {
+                push_unique(&mut enabled.extensions, extension_name);
+            }
+        }
+
+        Some(enabled)
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct EnabledDeviceFeatures {
+    pub features: Vec<DeviceFeature>,
+    pub extensions: Vec<&'static CStr>,
+}
+
+impl EnabledDeviceFeatures {
+    pub fn has_feature(&self, feature: DeviceFeature) -> bool {
+        self.features.contains(&feature)
+    }
+
+    pub fn has_extension(&self, extension_name: &CStr) -> bool {
+        self.extensions.contains(&extension_name)
+    }
+
+    // Feature structs to chain into `vk::DeviceCreateInfo`, see `FeatureSet::features2`.
+    pub(crate) fn to_feature_set(&self) -> FeatureSet {
+        let mut enabled = FeatureSet::default();
+
+        for feature in &self.features {
+            feature.enable(&mut enabled);
+        }
+
+        enabled
+    }
+}
+
+#[derive(Default)]
+pub(crate) struct FeatureSet {
+    core: vk::PhysicalDeviceFeatures,
+    vulkan12: vk::PhysicalDeviceVulkan12Features<'static>,
+    vulkan13: vk::PhysicalDeviceVulkan13Features<'static>,
+    uses_vulkan12: bool,
+    uses_vulkan13: bool,
+}
+
+impl FeatureSet {
+    pub(crate) fn features2(&mut self) -> vk::PhysicalDeviceFeatures2<'_> {
+        let mut features2 = vk::PhysicalDeviceFeatures2::default().features(self.core);
+
+        // Only chained when needed, so devices below Vulkan 1.2/1.3 never see unknown structs.
+        if self.uses_vulkan12 {
+            features2 = features2.push_next(&mut self.vulkan12);
+        }
+        if self.uses_vulkan13 {
+            features2 = features2.push_next(&mut self.vulkan13);
+        }
+
+        features2
+    }
+
+    fn query(instance: &ash::Instance, physical_device: vk::PhysicalDevice) -> Self {
+        let api_version =
+            unsafe { instance.get_physical_device_properties(physical_device) }.api_version;
+
+        let mut vulkan12 = vk::PhysicalDeviceVulkan12Features::default();
+        let mut vulkan13 = vk::PhysicalDeviceVulkan13Features::default();
+
+        // Chaining a struct the device doesn't know about is invalid, so only ask for what its
+        // API version covers; the rest stays reported as unsupported.
+        let core = {
+            let mut features2 = vk::PhysicalDeviceFeatures2::default();
+            if api_version >= vk::API_VERSION_1_2 {
+                features2 = features2.push_next(&mut vulkan12);
+            }
+            if api_version >= vk::API_VERSION_1_3 {
+                features2 = features2.push_next(&mut vulkan13);
+            }
+
+            unsafe { instance.get_physical_device_features2(physical_device, &mut features2) };
+
+            features2.features
+        };
+
+        vulkan12.p_next = std::ptr::null_mut();
+        vulkan13.p_next = std::ptr::null_mut();
+
+        Self {
+            core,
+            vulkan12,
+            vulkan13,
+            ..Default::default()
+        }
+    }
+}
+
+fn push_unique<T: PartialEq>(values: &mut Vec<T>, value: T) {
+    if !values.contains(&value) {
+        values.push(value);
+    }
+}
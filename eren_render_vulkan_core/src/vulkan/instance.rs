@@ -1,4 +1,7 @@
-use std::sync::Arc;
+use std::{
+    ffi::CStr,
+    sync::{Arc, Mutex},
+};
 
 use ash::vk;
 use thiserror::Error;
@@ -7,27 +10,96 @@ use winit::{raw_window_handle::HasDisplayHandle, window::Window};
 #[derive(Debug, Error)]
 pub enum VulkanInstanceManagerError {
     #[error("Failed to enumerate required extensions: {0}")]
-    ExtensionEnumerationFailed(String),
+    ExtensionEnumerationFailed(vk::Result),
+
+    #[error("Failed to enumerate instance layers: {0}")]
+    LayerEnumerationFailed(vk::Result),
 
     #[error("Failed to create instance: {0}")]
-    CreateInstanceFailed(String),
+    CreateInstanceFailed(vk::Result),
 
     #[error("Failed to create debug utils messenger: {0}")]
-    CreateDebugUtilsMessengerFailed(String),
+    CreateDebugUtilsMessengerFailed(vk::Result),
 }
 
-pub struct VulkanInstanceManager {
-    pub instance: ash::Instance,
+const VALIDATION_LAYER_NAME: &CStr = c"VK_LAYER_KHRONOS_validation";
+
+#[derive(Debug, Clone, Copy)]
+pub struct ValidationConfig {
+    pub enabled: bool,
+    // Severities the debug messenger reports; they map onto `log` levels.
+    pub message_severity: vk::DebugUtilsMessageSeverityFlagsEXT,
+    // Validation errors make the next `GraphicsContext` call panic instead of only being logged.
+    pub panic_on_error: bool,
+}
+
+impl Default for ValidationConfig {
+    fn default() -> Self {
+        Self {
+            enabled: cfg!(debug_assertions),
+            message_severity: vk::DebugUtilsMessageSeverityFlagsEXT::WARNING
+                | vk::DebugUtilsMessageSeverityFlagsEXT::ERROR,
+            panic_on_error: false,
+        }
+    }
+}
+
+// Shared with the debug messenger callback through its user data pointer.
+struct DebugMessengerState {
+    panic_on_error: bool,
+    errors: Mutex<Vec<String>>,
+}
+
+struct DebugMessenger {
     debug_utils_loader: ash::ext::debug_utils::Instance,
     debug_utils_messenger: vk::DebugUtilsMessengerEXT,
 }
 
+pub struct VulkanInstanceManager {
+    pub instance: ash::Instance,
+    pub validation_enabled: bool,
+    debug_messenger: Option<DebugMessenger>,
+    debug_messenger_state: Box<DebugMessengerState>,
+}
+
 impl VulkanInstanceManager {
     pub fn new(
         entry: &ash::Entry,
         window: Arc<Window>,
+        validation_config: &ValidationConfig,
     ) -> Result<Self, VulkanInstanceManagerError> {
-        let app_name = std::ffi::CString::new(window.title()).unwrap();
+        let mut extension_name_pointers: Vec<*const i8> =
+            ash_window::enumerate_required_extensions(window.display_handle().unwrap().as_raw())
+                .map_err(VulkanInstanceManagerError::ExtensionEnumerationFailed)?
+                .to_vec();
+
+        // Exposes HDR and wide-gamut surface formats; SDR output works without it.
+        if is_extension_available(entry, ash::ext::swapchain_colorspace::NAME)? {
+            extension_name_pointers.push(ash::ext::swapchain_colorspace::NAME.as_ptr());
+        }
+
+        Self::create(
+            entry,
+            &window.title(),
+            extension_name_pointers,
+            validation_config,
+        )
+    }
+
+    pub fn new_headless(
+        entry: &ash::Entry,
+        validation_config: &ValidationConfig,
+    ) -> Result<Self, VulkanInstanceManagerError> {
+        Self::create(entry, "ErenEngine Headless", Vec::new(), validation_config)
+    }
+
+    fn create(
+        entry: &ash::Entry,
+        app_name: &str,
+        mut extension_name_pointers: Vec<*const i8>,
+        validation_config: &ValidationConfig,
+    ) -> Result<Self, VulkanInstanceManagerError> {
+        let app_name = std::ffi::CString::new(app_name).unwrap();
         let engine_name = std::ffi::CString::new("ErenEngine").unwrap();
 
         let app_info = vk::ApplicationInfo::default()
@@ -37,20 +109,31 @@ impl VulkanInstanceManager {
             .engine_name(&engine_name)
             .engine_version(vk::make_api_version(0, 1, 0, 0));
 
-        let layer_names: Vec<std::ffi::CString> =
-            vec![std::ffi::CString::new("VK_LAYER_KHRONOS_validation").unwrap()];
+        let mut layer_name_pointers: Vec<*const i8> = Vec::new();
 
-        let layer_name_pointers: Vec<*const i8> = layer_names
-            .iter()
-            .map(|layer_name| layer_name.as_ptr())
-            .collect();
+        // A missing layer or extension only disables validation, it never fails initialization.
+        let validation_enabled =
+            validation_config.enabled && is_layer_available(entry, VALIDATION_LAYER_NAME)?;
+        let debug_utils_enabled = validation_config.enabled
+            && is_extension_available(entry, ash::ext::debug_utils::NAME)?;
 
-        let mut extension_name_pointers: Vec<*const i8> =
-            ash_window::enumerate_required_extensions(window.display_handle().unwrap().as_raw())
-                .map_err(|e| VulkanInstanceManagerError::ExtensionEnumerationFailed(e.to_string()))?
-                .to_vec();
+        if validation_enabled {
+            layer_name_pointers.push(VALIDATION_LAYER_NAME.as_ptr());
+        } else if validation_config.enabled {
+            log::warn!(
+                "{} is not available, continuing without validation",
+                VALIDATION_LAYER_NAME.to_string_lossy()
+            );
+        }
 
-        extension_name_pointers.push(ash::ext::debug_utils::NAME.as_ptr());
+        if debug_utils_enabled {
+            extension_name_pointers.push(ash::ext::debug_utils::NAME.as_ptr());
+        } else if validation_config.enabled {
+            log::warn!(
+                "{} is not available, validation messages will not be reported",
+                ash::ext::debug_utils::NAME.to_string_lossy()
+            );
+        }
 
         let mut instance_create_flags = vk::InstanceCreateFlags::empty();
 
@@ -63,69 +146,172 @@ impl VulkanInstanceManager {
             instance_create_flags |= vk::InstanceCreateFlags::ENUMERATE_PORTABILITY_KHR;
         }
 
+        let mut debug_messenger_state = Box::new(DebugMessengerState {
+            panic_on_error: validation_config.panic_on_error,
+            errors: Mutex::new(Vec::new()),
+        });
+
         let mut debug_info = vk::DebugUtilsMessengerCreateInfoEXT::default()
-            .message_severity(
-                vk::DebugUtilsMessageSeverityFlagsEXT::WARNING
-                    | vk::DebugUtilsMessageSeverityFlagsEXT::VERBOSE
-                    //| vk::DebugUtilsMessageSeverityFlagsEXT::INFO
-                    | vk::DebugUtilsMessageSeverityFlagsEXT::ERROR,
-            )
+            .message_severity(validation_config.message_severity)
             .message_type(
                 vk::DebugUtilsMessageTypeFlagsEXT::GENERAL
                     | vk::DebugUtilsMessageTypeFlagsEXT::PERFORMANCE
                     | vk::DebugUtilsMessageTypeFlagsEXT::VALIDATION,
             )
-            .pfn_user_callback(Some(vulkan_debug_utils_callback));
+            .pfn_user_callback(Some(vulkan_debug_utils_callback))
+            .user_data(
+                (debug_messenger_state.as_mut() as *mut DebugMessengerState)
+                    .cast::<std::ffi::c_void>(),
+            );
 
-        let instance_info = vk::InstanceCreateInfo::default()
-            .push_next(&mut debug_info)
+        let mut instance_info = vk::InstanceCreateInfo::default()
             .application_info(&app_info)
             .enabled_layer_names(&layer_name_pointers)
             .enabled_extension_names(&extension_name_pointers)
             .flags(instance_create_flags);
 
+        // Also reports problems in vkCreateInstance/vkDestroyInstance themselves.
+        if debug_utils_enabled {
+            instance_info = instance_info.push_next(&mut debug_info);
+        }
+
         let instance = unsafe {
             entry
                 .create_instance(&instance_info, None)
-                .map_err(|e| VulkanInstanceManagerError::CreateInstanceFailed(e.to_string()))?
+                .map_err(VulkanInstanceManagerError::CreateInstanceFailed)?
         };
 
-        let debug_utils_loader = ash::ext::debug_utils::Instance::new(&entry, &instance);
-        let debug_utils_messenger = unsafe {
-            debug_utils_loader
-                .create_debug_utils_messenger(&debug_info, None)
-                .map_err(|e| {
-                    VulkanInstanceManagerError::CreateDebugUtilsMessengerFailed(e.to_string())
-                })?
+        let debug_messenger = if debug_utils_enabled {
+            let debug_utils_loader = ash::ext::debug_utils::Instance::new(entry, &instance);
+            let debug_utils_messenger =
+                unsafe { debug_utils_loader.create_debug_utils_messenger(&debug_info, None) };
+
+            match debug_utils_messenger {
+                Ok(debug_utils_messenger) => Some(DebugMessenger {
+                    debug_utils_loader,
+                    debug_utils_messenger,
+                }),
+                Err(e) => {
+                    unsafe { instance.destroy_instance(None) };
+                    return Err(VulkanInstanceManagerError::CreateDebugUtilsMessengerFailed(
+                        e,
+                    ));
+                }
+            }
+        } else {
+            None
         };
 
         Ok(Self {
             instance,
-            debug_utils_loader,
-            debug_utils_messenger,
+            validation_enabled,
+            debug_messenger,
+            debug_messenger_state,
         })
     }
+
+    // Object names and labels can only be set through the extension the messenger uses.
+    pub fn debug_utils_enabled(&self) -> bool {
+        self.debug_messenger.is_some()
+    }
+
+    // Panics with every validation error reported since the last call, if so configured.
+    pub fn check_validation_errors(&self) {
+        if !self.debug_messenger_state.panic_on_error {
+            return;
+        }
+
+        let errors = std::mem::take(
+            &mut *self
+                .debug_messenger_state
+                .errors
+                .lock()
+                .unwrap_or_else(|e| e.into_inner()),
+        );
+
+        if !errors.is_empty() {
+            panic!("Vulkan validation errors:\n{}", errors.join("\n"));
+        }
+    }
 }
 
 impl Drop for VulkanInstanceManager {
     fn drop(&mut self) {
         unsafe {
-            self.debug_utils_loader
-                .destroy_debug_utils_messenger(self.debug_utils_messenger, None);
+            if let Some(debug_messenger) = &self.debug_messenger {
+                debug_messenger
+                    .debug_utils_loader
+                    .destroy_debug_utils_messenger(debug_messenger.debug_utils_messenger, None);
+            }
+
             self.instance.destroy_instance(None);
         }
     }
 }
 
+fn is_layer_available(
+    entry: &ash::Entry,
+    layer_name: &CStr,
+) -> Result<bool, VulkanInstanceManagerError> {
+    let layers = unsafe {
+        entry
+            .enumerate_instance_layer_properties()
+            .map_err(VulkanInstanceManagerError::LayerEnumerationFailed)?
+    };
+
+    Ok(layers
+        .iter()
+        .any(|layer| layer.layer_name_as_c_str() == Ok(layer_name)))
+}
+
+fn is_extension_available(
+    entry: &ash::Entry,
+    extension_name: &CStr,
+) -> Result<bool, VulkanInstanceManagerError> {
+    let extensions = unsafe {
+        entry
+            .enumerate_instance_extension_properties(None)
+            .map_err(VulkanInstanceManagerError::ExtensionEnumerationFailed)?
+    };
+
+    Ok(extensions
+        .iter()
+        .any(|extension| extension.extension_name_as_c_str() == Ok(extension_name)))
+}
+
 unsafe extern "system" fn vulkan_debug_utils_callback(
     message_severity: vk::DebugUtilsMessageSeverityFlagsEXT,
     message_type: vk::DebugUtilsMessageTypeFlagsEXT,
     p_callback_data: *const vk::DebugUtilsMessengerCallbackDataEXT,
-    _p_user_data: *mut std::ffi::c_void,
+    p_user_data: *mut std::ffi::c_void,
 ) -> vk::Bool32 {
-    let message = unsafe { std::ffi::CStr::from_ptr((*p_callback_data).p_message) };
-    let severity = format!("{:?}", message_severity).to_lowercase();
+    let message = unsafe { CStr::from_ptr((*p_callback_data).p_message) }.to_string_lossy();
     let ty = format!("{:?}", message_type).to_lowercase();
-    println!("[Debug][{}][{}] {:?}", severity, ty, message);
+
+    let level = if message_severity.contains(vk::DebugUtilsMessageSeverityFlagsEXT::ERROR) {
+        log::Level::Error
+    } else if message_severity.contains(vk::DebugUtilsMessageSeverityFlagsEXT::WARNING) {
+        log::Level::Warn
+    } else if message_severity.contains(vk::DebugUtilsMessageSeverityFlagsEXT::INFO) {
+        log::Level::Info
+    } else {
+        log::Level::Trace
+    };
+
+    log::log!(target: "vulkan", level, "[{}] {}", ty, message);
+
+    // Unwinding out of this callback would abort, so errors are handed back to the caller instead.
+    if level == log::Level::Error && !p_user_data.is_null() {
+        let state = unsafe { &*p_user_data.cast::<DebugMessengerState>() };
+
+        if state.panic_on_error {
+            state
+                .errors
+                .lock()
+                .unwrap_or_else(|e| e.into_inner())
+                .push(format!("[{}] {}", ty, message));
+        }
+    }
+
     vk::FALSE
 }
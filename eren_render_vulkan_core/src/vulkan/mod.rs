@@ -1,7 +1,18 @@
+pub mod allocator;
+pub mod barrier;
+pub mod debug;
+pub mod destruction;
 pub mod device;
+pub mod features;
 pub mod instance;
+pub mod offscreen;
 pub mod physical_device;
+pub mod pipeline_cache;
+pub mod profiler;
 pub mod queue;
+pub mod readback;
+pub mod rendering;
 pub mod surface;
 pub mod swapchain;
+pub mod upload;
 pub mod memory;
@@ -0,0 +1,81 @@
+use ash::vk;
+use thiserror::Error;
+
+use crate::vulkan::{
+    allocator::{Allocation, MemoryAllocator},
+    memory::MemoryError,
+};
+
+// Matches the format preferred for swapchains so headless frames look like windowed ones.
+pub const OFFSCREEN_FORMAT: vk::Format = vk::Format::B8G8R8A8_SRGB;
+
+#[derive(Debug, Error)]
+pub enum OffscreenManagerError {
+    #[error("Failed to create offscreen image: {0}")]
+    CreateImageFailed(#[from] MemoryError),
+}
+
+pub struct OffscreenManager {
+    device: ash::Device,
+
+    pub images: Vec<vk::Image>,
+    image_allocations: Vec<Allocation>,
+    pub amount_of_images: usize,
+    pub format: vk::Format,
+    pub image_extent: vk::Extent2D,
+}
+
+impl OffscreenManager {
+    pub fn new(
+        device: &ash::Device,
+        allocator: &MemoryAllocator,
+        image_extent: vk::Extent2D,
+        amount_of_images: usize,
+    ) -> Result<Self, OffscreenManagerError> {
+        let image_info = vk::ImageCreateInfo::default()
+            .image_type(vk::ImageType::TYPE_2D)
+            .format(OFFSCREEN_FORMAT)
+            .extent(vk::Extent3D {
+                width: image_extent.width,
+                height: image_extent.height,
+                depth: 1,
+            })
+            .mip_levels(1)
+            .array_layers(1)
+            .samples(vk::SampleCountFlags::TYPE_1)
+            .tiling(vk::ImageTiling::OPTIMAL)
+            .usage(vk::ImageUsageFlags::COLOR_ATTACHMENT | vk::ImageUsageFlags::TRANSFER_SRC)
+            .sharing_mode(vk::SharingMode::EXCLUSIVE)
+            .initial_layout(vk::ImageLayout::UNDEFINED);
+
+        let mut manager = Self {
+            device: device.clone(),
+            images: Vec::with_capacity(amount_of_images),
+            image_allocations: Vec::with_capacity(amount_of_images),
+            amount_of_images,
+            format: OFFSCREEN_FORMAT,
+            image_extent,
+        };
+
+        for _ in 0..amount_of_images {
+            // Pushed one by one so that Drop cleans up whatever was created before a failure.
+            let (image, allocation) =
+                allocator.create_image(&image_info, vk::MemoryPropertyFlags::DEVICE_LOCAL)?;
+
+            manager.images.push(image);
+            manager.image_allocations.push(allocation);
+        }
+
+        Ok(manager)
+    }
+}
+
+impl Drop for OffscreenManager {
+    fn drop(&mut self) {
+        unsafe {
+            for &image in self.images.iter() {
+                self.device.destroy_image(image, None);
+            }
+        }
+    }
+}
@@ -1,15 +1,17 @@
-use ash::{khr::surface, vk};
+use ash::vk;
 use thiserror::Error;
 
 use crate::vulkan::{
+    features::{DeviceFeature, DeviceRequirements, EnabledDeviceFeatures},
     queue::{QueueFamilyIndices, find_queue_family_indices},
+    surface::SurfaceManager,
     swapchain::{SwapchainSupportDetails, SwapchainSupportError, get_swapchain_support_details},
 };
 
 #[derive(Debug, Error)]
 pub enum PhysicalDeviceManagerError {
     #[error("Failed to enumerate physical devices: {0}")]
-    EnumeratePhysicalDevicesFailed(String),
+    EnumeratePhysicalDevicesFailed(vk::Result),
 
     #[error("Swapchain support query failed: {0}")]
     SwapchainSupportQueryFailed(#[from] SwapchainSupportError),
@@ -18,111 +20,304 @@ pub enum PhysicalDeviceManagerError {
     NoSuitablePhysicalDevice,
 }
 
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub enum GpuPreference {
+    // Highest scoring suitable device
+    #[default]
+    Auto,
+    // Case-insensitive substring of the device name
+    Name(String),
+    // Position in `vkEnumeratePhysicalDevices`, as reported by `AdapterInfo::index`
+    Index(usize),
+    VendorId(u32),
+}
+
+#[derive(Debug, Clone)]
+pub struct AdapterInfo {
+    pub index: usize,
+    pub name: String,
+    pub vendor_id: u32,
+    pub device_id: u32,
+    pub device_type: vk::PhysicalDeviceType,
+    pub api_version: u32,
+    pub driver_version: u32,
+    pub device_local_memory: vk::DeviceSize,
+    // Whether the device meets the engine's requirements (for the surface, if there is one)
+    pub suitable: bool,
+    pub score: u64,
+}
+
+impl AdapterInfo {
+    pub fn vendor_name(&self) -> &'static str {
+        match self.vendor_id {
+            0x1002 => "AMD",
+            0x106B => "Apple",
+            0x10DE => "NVIDIA",
+            0x13B5 => "ARM",
+            0x5143 => "Qualcomm",
+            0x8086 => "Intel",
+            0x10005 => "Mesa",
+            _ => "Unknown",
+        }
+    }
+
+    fn matches(&self, gpu_preference: &GpuPreference) -> bool {
+        match gpu_preference {
+            GpuPreference::Auto => true,
+            GpuPreference::Name(name) => self.name.to_lowercase().contains(&name.to_lowercase()),
+            GpuPreference::Index(index) => self.index == *index,
+            GpuPreference::VendorId(vendor_id) => self.vendor_id == *vendor_id,
+        }
+    }
+}
+
 pub struct PhysicalDeviceManager {
     pub queue_family_indices: QueueFamilyIndices,
-    pub swapchain_support_details: SwapchainSupportDetails,
+    pub swapchain_support_details: Option<SwapchainSupportDetails>,
     pub physical_device: vk::PhysicalDevice,
+    pub adapter_info: AdapterInfo,
+    pub enabled_features: EnabledDeviceFeatures,
 }
 
 impl PhysicalDeviceManager {
     pub fn new(
         instance: &ash::Instance,
-        surface_loader: &surface::Instance,
-        surface: vk::SurfaceKHR,
+        surface_manager: Option<&SurfaceManager>,
+        gpu_preference: &GpuPreference,
+        device_requirements: &DeviceRequirements,
     ) -> Result<Self, PhysicalDeviceManagerError> {
-        let physical_devices = unsafe {
-            instance.enumerate_physical_devices().map_err(|e| {
-                PhysicalDeviceManagerError::EnumeratePhysicalDevicesFailed(e.to_string())
-            })?
-        };
-
-        for physical_device in physical_devices {
-            if !has_required_device_features(instance, physical_device)
-                || !has_required_device_extensions(instance, physical_device)
-            {
-                continue;
-            }
-
-            let queue_family_indices =
-                find_queue_family_indices(instance, surface_loader, surface, physical_device);
-            if !queue_family_indices.is_complete() {
-                continue;
-            }
-
-            let swapchain_support_details =
-                get_swapchain_support_details(surface_loader, surface, physical_device)?;
-            if swapchain_support_details.formats.is_empty()
-                || swapchain_support_details.present_modes.is_empty()
-            {
-                continue;
-            }
-
-            return Ok(Self {
-                queue_family_indices,
-                swapchain_support_details,
-                physical_device,
-            });
+        let mut candidates: Vec<Self> =
+            evaluate_physical_devices(instance, surface_manager, device_requirements)?
+                .into_iter()
+                .filter_map(|(_, candidate)| candidate)
+                .collect();
+
+        // Stable sort, so equally scored devices keep the driver's order
+        candidates.sort_by_key(|candidate| std::cmp::Reverse(candidate.adapter_info.score));
+
+        let preferred_position = candidates
+            .iter()
+            .position(|candidate| candidate.adapter_info.matches(gpu_preference));
+
+        if preferred_position.is_none() && !candidates.is_empty() {
+            log::warn!(
+                "No suitable GPU matches {:?}, falling back to the highest scoring one",
+                gpu_preference
+            );
         }
 
-        Err(PhysicalDeviceManagerError::NoSuitablePhysicalDevice)
+        let physical_device_manager = candidates
+            .into_iter()
+            .nth(preferred_position.unwrap_or(0))
+            .ok_or(PhysicalDeviceManagerError::NoSuitablePhysicalDevice)?;
+
+        let adapter_info = &physical_device_manager.adapter_info;
+        log::info!(
+            "Selected GPU {} ({}, {:?}, score {})",
+            adapter_info.name,
+            adapter_info.vendor_name(),
+            adapter_info.device_type,
+            adapter_info.score
+        );
+
+        Ok(physical_device_manager)
     }
-}
 
-pub fn get_required_device_features() -> vk::PhysicalDeviceFeatures {
-    vk::PhysicalDeviceFeatures::default().shader_clip_distance(true)
+    // Every physical device, suitable or not, for GPU pickers and diagnostics.
+    pub fn enumerate_adapters(
+        instance: &ash::Instance,
+        surface_manager: Option<&SurfaceManager>,
+        device_requirements: &DeviceRequirements,
+    ) -> Result<Vec<AdapterInfo>, PhysicalDeviceManagerError> {
+        Ok(
+            evaluate_physical_devices(instance, surface_manager, device_requirements)?
+                .into_iter()
+                .map(|(adapter_info, _)| adapter_info)
+                .collect(),
+        )
+    }
 }
 
-fn has_required_device_features(
+fn evaluate_physical_devices(
     instance: &ash::Instance,
-    physical_device: vk::PhysicalDevice,
-) -> bool {
-    let features = unsafe { instance.get_physical_device_features(physical_device) };
+    surface_manager: Option<&SurfaceManager>,
+    device_requirements: &DeviceRequirements,
+) -> Result<Vec<(AdapterInfo, Option<PhysicalDeviceManager>)>, PhysicalDeviceManagerError> {
+    let physical_devices = unsafe {
+        instance
+            .enumerate_physical_devices()
+            .map_err(PhysicalDeviceManagerError::EnumeratePhysicalDevicesFailed)?
+    };
 
-    if features.shader_clip_distance != vk::TRUE {
-        return false;
+    let mut evaluated = Vec::with_capacity(physical_devices.len());
+
+    for (index, physical_device) in physical_devices.into_iter().enumerate() {
+        let mut adapter_info = query_adapter_info(instance, physical_device, index);
+
+        let mut candidate = check_suitability(
+            instance,
+            surface_manager,
+            device_requirements,
+            physical_device,
+            &adapter_info,
+        )?;
+
+        if let Some(candidate) = candidate.as_mut() {
+            adapter_info.suitable = true;
+            adapter_info.score = score_physical_device(instance, candidate);
+            candidate.adapter_info = adapter_info.clone();
+        }
+
+        evaluated.push((adapter_info, candidate));
     }
 
-    true
+    Ok(evaluated)
 }
 
-pub fn get_required_device_extensions() -> Vec<&'static std::ffi::CStr> {
-    let mut required_extensions = vec![ash::khr::swapchain::NAME];
+fn check_suitability(
+    instance: &ash::Instance,
+    surface_manager: Option<&SurfaceManager>,
+    device_requirements: &DeviceRequirements,
+    physical_device: vk::PhysicalDevice,
+    adapter_info: &AdapterInfo,
+) -> Result<Option<PhysicalDeviceManager>, PhysicalDeviceManagerError> {
+    let presentable = surface_manager.is_some();
+
+    let Some(enabled_features) = base_device_requirements(presentable)
+        .merge(device_requirements)
+        .negotiate(instance, physical_device)
+    else {
+        return Ok(None);
+    };
+
+    let queue_family_indices =
+        find_queue_family_indices(instance, surface_manager, physical_device);
 
-    #[cfg(any(target_os = "macos", target_os = "ios"))]
+    let Some(surface_manager) = surface_manager else {
+        if !queue_family_indices.is_complete_headless() {
+            return Ok(None);
+        }
+
+        return Ok(Some(PhysicalDeviceManager {
+            queue_family_indices,
+            swapchain_support_details: None,
+            physical_device,
+            adapter_info: adapter_info.clone(),
+            enabled_features,
+        }));
+    };
+
+    if !queue_family_indices.is_complete() {
+        return Ok(None);
+    }
+
+    let swapchain_support_details = get_swapchain_support_details(
+        &surface_manager.surface_loader,
+        surface_manager.surface,
+        physical_device,
+    )?;
+    if swapchain_support_details.formats.is_empty()
+        || swapchain_support_details.present_modes.is_empty()
     {
-        required_extensions.push(ash::khr::portability_subset::NAME);
+        return Ok(None);
     }
 
-    required_extensions
+    Ok(Some(PhysicalDeviceManager {
+        queue_family_indices,
+        swapchain_support_details: Some(swapchain_support_details),
+        physical_device,
+        adapter_info: adapter_info.clone(),
+        enabled_features,
+    }))
 }
 
-fn has_required_device_extensions(
+fn query_adapter_info(
     instance: &ash::Instance,
     physical_device: vk::PhysicalDevice,
-) -> bool {
-    let extensions = unsafe {
-        instance
-            .enumerate_device_extension_properties(physical_device)
-            .unwrap_or_else(|_| Vec::new())
+    index: usize,
+) -> AdapterInfo {
+    let properties = unsafe { instance.get_physical_device_properties(physical_device) };
+    let memory_properties =
+        unsafe { instance.get_physical_device_memory_properties(physical_device) };
+
+    let device_local_memory = memory_properties.memory_heaps
+        [..memory_properties.memory_heap_count as usize]
+        .iter()
+        .filter(|heap| heap.flags.contains(vk::MemoryHeapFlags::DEVICE_LOCAL))
+        .map(|heap| heap.size)
+        .sum();
+
+    AdapterInfo {
+        index,
+        name: properties
+            .device_name_as_c_str()
+            .map(|name| name.to_string_lossy().into_owned())
+            .unwrap_or_default(),
+        vendor_id: properties.vendor_id,
+        device_id: properties.device_id,
+        device_type: properties.device_type,
+        api_version: properties.api_version,
+        driver_version: properties.driver_version,
+        device_local_memory,
+        suitable: false,
+        score: 0,
+    }
+}
+
+fn score_physical_device(instance: &ash::Instance, candidate: &PhysicalDeviceManager) -> u64 {
+    let adapter_info = &candidate.adapter_info;
+
+    // Device type dominates; memory and features only break ties between devices of one kind.
+    let mut score = match adapter_info.device_type {
+        vk::PhysicalDeviceType::DISCRETE_GPU => 100_000,
+        vk::PhysicalDeviceType::INTEGRATED_GPU => 50_000,
+        vk::PhysicalDeviceType::VIRTUAL_GPU => 20_000,
+        vk::PhysicalDeviceType::CPU => 0,
+        _ => 10_000,
     };
 
-    let required_extensions = get_required_device_extensions();
+    // One point per 64 MiB of device-local memory
+    score += adapter_info.device_local_memory / (64 * 1024 * 1024);
 
-    for required_ext_name_cstr in required_extensions.iter() {
-        let required_ext_name =
-            unsafe { std::ffi::CStr::from_ptr(required_ext_name_cstr.as_ptr()) };
+    let features = unsafe { instance.get_physical_device_features(candidate.physical_device) };
+    if features.sampler_anisotropy == vk::TRUE {
+        score += 100;
+    }
+    if features.fill_mode_non_solid == vk::TRUE {
+        score += 50;
+    }
 
-        let found = extensions.iter().any(|ext| {
-            let available_ext_name =
-                unsafe { std::ffi::CStr::from_ptr(ext.extension_name.as_ptr()) };
+    let queue_family_indices = &candidate.queue_family_indices;
+    if queue_family_indices.transfer_queue_family_index.is_some() {
+        score += 100;
+    }
+    if queue_family_indices.compute_queue_family_index.is_some() {
+        score += 100;
+    }
 
-            available_ext_name == required_ext_name
-        });
+    // Required entries count the same for every candidate, so this rewards the optional ones
+    score += 10
+        * (candidate.enabled_features.features.len() + candidate.enabled_features.extensions.len())
+            as u64;
 
-        if !found {
-            return false;
-        }
+    score
+}
+
+// What the engine itself needs, regardless of renderer.
+pub fn base_device_requirements(presentable: bool) -> DeviceRequirements {
+    let mut requirements =
+        DeviceRequirements::default().require_feature(DeviceFeature::ShaderClipDistance);
+
+    if presentable {
+        requirements = requirements
+            .require_extension(ash::khr::swapchain::NAME)
+            .optional_extension(ash::ext::hdr_metadata::NAME);
+    }
+
+    #[cfg(any(target_os = "macos", target_os = "ios"))]
+    {
+        requirements = requirements.require_extension(ash::khr::portability_subset::NAME);
     }
 
-    true
+    requirements
 }
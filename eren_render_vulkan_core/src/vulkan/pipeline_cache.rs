@@ -0,0 +1,155 @@
+use std::path::{Path, PathBuf};
+
+use ash::vk;
+use thiserror::Error;
+
+// Size of VkPipelineCacheHeaderVersionOne: length, version, vendor ID, device ID and cache UUID.
+const PIPELINE_CACHE_HEADER_SIZE: usize = 16 + vk::UUID_SIZE;
+
+#[derive(Debug, Error)]
+pub enum PipelineCacheManagerError {
+    #[error("Failed to create pipeline cache: {0}")]
+    CreatePipelineCacheFailed(vk::Result),
+
+    #[error("Failed to get pipeline cache data: {0}")]
+    GetPipelineCacheDataFailed(vk::Result),
+
+    #[error("Failed to write pipeline cache file: {0}")]
+    WriteFileFailed(std::io::Error),
+}
+
+pub struct PipelineCacheManager {
+    device: ash::Device,
+    pub pipeline_cache: vk::PipelineCache,
+    // None keeps the cache in memory only
+    path: Option<PathBuf>,
+}
+
+impl PipelineCacheManager {
+    pub fn new(
+        instance: &ash::Instance,
+        physical_device: vk::PhysicalDevice,
+        device: &ash::Device,
+        cache_dir: Option<&Path>,
+    ) -> Result<Self, PipelineCacheManagerError> {
+        let mut id_properties = vk::PhysicalDeviceIDProperties::default();
+        let mut properties2 =
+            vk::PhysicalDeviceProperties2::default().push_next(&mut id_properties);
+        unsafe { instance.get_physical_device_properties2(physical_device, &mut properties2) };
+        let properties = properties2.properties;
+
+        // A driver update or a different GPU gets a file of its own instead of an unusable blob.
+        let path = cache_dir.map(|cache_dir| {
+            cache_dir.join(format!(
+                "pipeline_cache_{}_{:08x}.bin",
+                to_hex(&id_properties.device_uuid),
+                properties.driver_version
+            ))
+        });
+
+        let initial_data = path
+            .as_deref()
+            .and_then(read_cache_file)
+            .filter(|data| is_compatible(data, &properties))
+            .unwrap_or_default();
+
+        let pipeline_cache = match create_pipeline_cache(device, &initial_data) {
+            Ok(pipeline_cache) => pipeline_cache,
+            // Drivers may still reject data that passed the header check, so start over empty.
+            Err(e) if !initial_data.is_empty() => {
+                log::warn!("Discarding pipeline cache rejected by the driver: {}", e);
+                create_pipeline_cache(device, &[])?
+            }
+            Err(e) => return Err(e),
+        };
+
+        Ok(Self {
+            device: device.clone(),
+            pipeline_cache,
+            path,
+        })
+    }
+
+    // Written to a temporary file first, so a crash mid-write never leaves a truncated cache behind.
+    pub fn save(&self) -> Result<(), PipelineCacheManagerError> {
+        let Some(path) = &self.path else {
+            return Ok(());
+        };
+
+        let data = unsafe {
+            self.device
+                .get_pipeline_cache_data(self.pipeline_cache)
+                .map_err(PipelineCacheManagerError::GetPipelineCacheDataFailed)?
+        };
+
+        let temp_path = path.with_extension("tmp");
+
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent).map_err(PipelineCacheManagerError::WriteFileFailed)?;
+        }
+
+        std::fs::write(&temp_path, &data)
+            .and_then(|_| std::fs::rename(&temp_path, path))
+            .map_err(PipelineCacheManagerError::WriteFileFailed)
+    }
+}
+
+impl Drop for PipelineCacheManager {
+    fn drop(&mut self) {
+        if let Err(e) = self.save() {
+            log::warn!("Failed to save pipeline cache: {}", e);
+        }
+
+        unsafe {
+            self.device
+                .destroy_pipeline_cache(self.pipeline_cache, None);
+        }
+    }
+}
+
+fn create_pipeline_cache(
+    device: &ash::Device,
+    initial_data: &[u8],
+) -> Result<vk::PipelineCache, PipelineCacheManagerError> {
+    unsafe {
+        device
+            .create_pipeline_cache(
+                &vk::PipelineCacheCreateInfo::default().initial_data(initial_data),
+                None,
+            )
+            .map_err(PipelineCacheManagerError::CreatePipelineCacheFailed)
+    }
+}
+
+fn read_cache_file(path: &Path) -> Option<Vec<u8>> {
+    match std::fs::read(path) {
+        Ok(data) => Some(data),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => None,
+        Err(e) => {
+            log::warn!("Failed to read pipeline cache {}: {}", path.display(), e);
+            None
+        }
+    }
+}
+
+// Checks the header against the device, since not every driver validates it reliably.
+fn is_compatible(data: &[u8], properties: &vk::PhysicalDeviceProperties) -> bool {
+    let read_u32 = |offset: usize| u32::from_le_bytes(data[offset..offset + 4].try_into().unwrap());
+
+    let compatible = data.len() >= PIPELINE_CACHE_HEADER_SIZE
+        && read_u32(0) as usize >= PIPELINE_CACHE_HEADER_SIZE
+        && read_u32(4) == vk::PipelineCacheHeaderVersion::ONE.as_raw() as u32
+        && read_u32(8) == properties.vendor_id
+        && read_u32(12) == properties.device_id
+        && data[16..PIPELINE_CACHE_HEADER_SIZE] == properties.pipeline_cache_uuid;
+
+    if !compatible {
+        log::warn!("Ignoring pipeline cache created by a different device or driver");
+    }
+
+    compatible
+}
+
+fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
@@ -0,0 +1,388 @@
+use std::{
+    fmt::Write,
+    path::Path,
+    sync::{Mutex, MutexGuard},
+};
+
+use ash::vk;
+use thiserror::Error;
+
+// Scopes beyond this many in one frame are not timed.
+const MAX_SCOPES_PER_FRAME: usize = 64;
+
+#[derive(Debug, Error)]
+pub enum GpuProfilerError {
+    #[error("The queue family does not support timestamps")]
+    TimestampsUnsupported,
+
+    #[error("Failed to create query pool: {0}")]
+    CreateQueryPoolFailed(vk::Result),
+
+    #[error("Failed to write trace file: {0}")]
+    WriteFileFailed(std::io::Error),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct GpuTiming {
+    pub name: &'static str,
+    // Relative to the first scope of the frame
+    pub start_ms: f64,
+    pub duration_ms: f64,
+}
+
+struct TraceEvent {
+    name: &'static str,
+    start_us: f64,
+    duration_us: f64,
+}
+
+struct ProfilerFrame {
+    query_pool: vk::QueryPool,
+    // Scope `i` owns queries `2 * i` (begin) and `2 * i + 1` (end)
+    scopes: Vec<&'static str>,
+}
+
+struct GpuProfilerInner {
+    frames: Vec<ProfilerFrame>,
+    current_frame: usize,
+    last_timings: Vec<GpuTiming>,
+    // Some while a trace is being captured
+    trace: Option<Vec<TraceEvent>>,
+    trace_origin: Option<u64>,
+}
+
+// Timestamp queries around named scopes of a frame. Each frame slot has its own query pool, whose
+// results are read when the slot comes around again, after its completion fence was waited on, so
+// reading them never stalls. Timings therefore lag the frame being recorded by the frames in flight.
+pub struct GpuProfiler {
+    device: ash::Device,
+    // Nanoseconds per tick
+    timestamp_period: f64,
+    timestamp_mask: u64,
+    inner: Mutex<GpuProfilerInner>,
+}
+
+impl GpuProfiler {
+    pub fn new(
+        instance: &ash::Instance,
+        physical_device: vk::PhysicalDevice,
+        device: &ash::Device,
+        queue_family_index: u32,
+        frames_in_flight: usize,
+    ) -> Result<Self, GpuProfilerError> {
+        let properties = unsafe { instance.get_physical_device_properties(physical_device) };
+        let queue_family_properties =
+            unsafe { instance.get_physical_device_queue_family_properties(physical_device) };
+
+        let timestamp_valid_bits = queue_family_properties
+            .get(queue_family_index as usize)
+            .map_or(0, |properties| properties.timestamp_valid_bits);
+
+        if timestamp_valid_bits == 0 || properties.limits.timestamp_period == 0.0 {
+            return Err(GpuProfilerError::TimestampsUnsupported);
+        }
+
+        let timestamp_mask = if timestamp_valid_bits >= 64 {
+            u64::MAX
+        } else {
+            (1 << timestamp_valid_bits) - 1
+        };
+
+        let profiler = Self {
+            device: device.clone(),
+            timestamp_period: properties.limits.timestamp_period as f64,
+            timestamp_mask,
+            inner: Mutex::new(GpuProfilerInner {
+                frames: Vec::new(),
+                current_frame: 0,
+                last_timings: Vec::new(),
+                trace: None,
+                trace_origin: None,
+            }),
+        };
+        profiler.set_frames_in_flight(frames_in_flight)?;
+
+        Ok(profiler)
+    }
+
+    // Timings of the most recently resolved frame, in recording order.
+    pub fn last_frame_timings(&self) -> Vec<GpuTiming> {
+        self.lock().last_timings.clone()
+    }
+
+    // Sum of all scopes with `name` in the most recently resolved frame.
+    pub fn last_frame_milliseconds(&self, name: &str) -> Option<f64> {
+        let inner = self.lock();
+        let mut timings = inner
+            .last_timings
+            .iter()
+            .filter(|timing| timing.name == name)
+            .peekable();
+
+        timings.peek()?;
+        Some(timings.map(|timing| timing.duration_ms).sum())
+    }
+
+    pub fn start_trace(&self) {
+        let mut inner = self.lock();
+        inner.trace = Some(Vec::new());
+        inner.trace_origin = None;
+    }
+
+    pub fn is_tracing(&self) -> bool {
+        self.lock().trace.is_some()
+    }
+
+    // Ends the capture and returns it in the Chrome trace event format, for chrome://tracing or
+    // Perfetto. Events are only added once resolved, so the last frames in flight are missing.
+    pub fn stop_trace(&self) -> Option<String> {
+        let trace = self.lock().trace.take()?;
+
+        let mut json = String::from("{\"traceEvents\":[");
+        for (index, event) in trace.iter().enumerate() {
+            if index > 0 {
+                json.push(',');
+            }
+
+            let _ = write!(
+                json,
+                "{{\"name\":\"{}\",\"cat\":\"gpu\",\"ph\":\"X\",\"ts\":{:.3},\"dur\":{:.3},\"pid\":0,\"tid\":0}}",
+                escape_json(event.name),
+                event.start_us,
+                event.duration_us
+            );
+        }
+        json.push_str("],\"displayTimeUnit\":\"ms\"}");
+
+        Some(json)
+    }
+
+    pub fn stop_trace_to_file(&self, path: &Path) -> Result<(), GpuProfilerError> {
+        let json = self.stop_trace().unwrap_or_default();
+
+        std::fs::write(path, json).map_err(GpuProfilerError::WriteFileFailed)
+    }
+
+    // Must only be called while the device is idle. Pending results are dropped.
+    pub(crate) fn set_frames_in_flight(
+        &self,
+        frames_in_flight: usize,
+    ) -> Result<(), GpuProfilerError> {
+        let mut inner = self.lock();
+
+        for frame in inner.frames.drain(..) {
+            unsafe { self.device.destroy_query_pool(frame.query_pool, None) };
+        }
+        inner.current_frame = 0;
+
+        for _ in 0..frames_in_flight {
+            let query_pool = unsafe {
+                self.device
+                    .create_query_pool(
+                        &vk::QueryPoolCreateInfo::default()
+                            .query_type(vk::QueryType::TIMESTAMP)
+                            .query_count(2 * MAX_SCOPES_PER_FRAME as u32),
+                        None,
+                    )
+                    .map_err(GpuProfilerError::CreateQueryPoolFailed)?
+            };
+
+            inner.frames.push(ProfilerFrame {
+                query_pool,
+                scopes: Vec::new(),
+            });
+        }
+
+        Ok(())
+    }
+
+    // Must only be called once the fence of `frame_index`'s previous submission has signaled, right
+    // after `command_buffer` was begun.
+    pub(crate) fn begin_frame(&self, frame_index: usize, command_buffer: vk::CommandBuffer) {
+        let mut inner = self.lock();
+        inner.current_frame = frame_index;
+
+        self.resolve_frame(&mut inner, frame_index);
+
+        let frame = &mut inner.frames[frame_index];
+        frame.scopes.clear();
+
+        unsafe {
+            self.device.cmd_reset_query_pool(
+                command_buffer,
+                frame.query_pool,
+                0,
+                2 * MAX_SCOPES_PER_FRAME as u32,
+            );
+        }
+    }
+
+    // Returns the scope index to pass to `end_scope`, or None once the frame has run out of queries.
+    pub(crate) fn begin_scope(
+        &self,
+        command_buffer: vk::CommandBuffer,
+        name: &'static str,
+    ) -> Option<usize> {
+        let mut inner = self.lock();
+        let current_frame = inner.current_frame;
+        let frame = &mut inner.frames[current_frame];
+
+        if frame.scopes.len() >= MAX_SCOPES_PER_FRAME {
+            return None;
+        }
+
+        let scope_index = frame.scopes.len();
+        frame.scopes.push(name);
+
+        unsafe {
+            self.device.cmd_write_timestamp(
+                command_buffer,
+                vk::PipelineStageFlags::TOP_OF_PIPE,
+                frame.query_pool,
+                2 * scope_index as u32,
+            );
+        }
+
+        Some(scope_index)
+    }
+
+    pub(crate) fn end_scope(&self, command_buffer: vk::CommandBuffer, scope_index: usize) {
+        let inner = self.lock();
+        let frame = &inner.frames[inner.current_frame];
+
+        unsafe {
+            self.device.cmd_write_timestamp(
+                command_buffer,
+                vk::PipelineStageFlags::BOTTOM_OF_PIPE,
+                frame.query_pool,
+                2 * scope_index as u32 + 1,
+            );
+        }
+    }
+
+    fn resolve_frame(&self, inner: &mut GpuProfilerInner, frame_index: usize) {
+        let frame = &inner.frames[frame_index];
+        if frame.scopes.is_empty() {
+            return;
+        }
+
+        let mut timestamps = vec![0u64; 2 * frame.scopes.len()];
+
+        // Without WAIT this fails instead of blocking if a scope was never ended
+        let result = unsafe {
+            self.device.get_query_pool_results(
+                frame.query_pool,
+                0,
+                &mut timestamps,
+                vk::QueryResultFlags::TYPE_64,
+            )
+        };
+        if result.is_err() {
+            return;
+        }
+
+        let to_ticks = |timestamp: u64| timestamp & self.timestamp_mask;
+        let to_ms = |ticks: u64| ticks as f64 * self.timestamp_period / 1_000_000.0;
+
+        let frame_start = timestamps
+            .iter()
+            .step_by(2)
+            .map(|&timestamp| to_ticks(timestamp))
+            .min()
+            .unwrap_or_default();
+
+        let timings: Vec<GpuTiming> = frame
+            .scopes
+            .iter()
+            .zip(timestamps.chunks_exact(2))
+            .map(|(&name, timestamps)| GpuTiming {
+                name,
+                start_ms: to_ms(
+                    to_ticks(timestamps[0]).wrapping_sub(frame_start) & self.timestamp_mask,
+                ),
+                duration_ms: to_ms(
+                    to_ticks(timestamps[1]).wrapping_sub(to_ticks(timestamps[0]))
+                        & self.timestamp_mask,
+                ),
+            })
+            .collect();
+
+        if inner.trace.is_some() {
+            let trace_origin = *inner.trace_origin.get_or_insert(frame_start);
+            let frame_start_us =
+                to_ms(frame_start.wrapping_sub(trace_origin) & self.timestamp_mask) * 1000.0;
+
+            if let Some(trace) = inner.trace.as_mut() {
+                trace.extend(timings.iter().map(|timing| TraceEvent {
+                    name: timing.name,
+                    start_us: frame_start_us + timing.start_ms * 1000.0,
+                    duration_us: timing.duration_ms * 1000.0,
+                }));
+            }
+        }
+
+        inner.last_timings = timings;
+    }
+
+    fn lock(&self) -> MutexGuard<'_, GpuProfilerInner> {
+        self.inner.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+impl Drop for GpuProfiler {
+    fn drop(&mut self) {
+        let inner = self.inner.get_mut().unwrap_or_else(|e| e.into_inner());
+
+        for frame in inner.frames.drain(..) {
+            unsafe { self.device.destroy_query_pool(frame.query_pool, None) };
+        }
+    }
+}
+
+// Ends its scope when dropped, so a pass can time its whole `record` with one line.
+pub struct GpuScope<'a> {
+    profiler: Option<&'a GpuProfiler>,
+    command_buffer: vk::CommandBuffer,
+    scope_index: Option<usize>,
+}
+
+impl<'a> GpuScope<'a> {
+    pub(crate) fn new(
+        profiler: Option<&'a GpuProfiler>,
+        command_buffer: vk::CommandBuffer,
+        name: &'static str,
+    ) -> Self {
+        let scope_index = profiler.and_then(|profiler| profiler.begin_scope(command_buffer, name));
+
+        Self {
+            profiler,
+            command_buffer,
+            scope_index,
+        }
+    }
+}
+
+impl Drop for GpuScope<'_> {
+    fn drop(&mut self) {
+        if let (Some(profiler), Some(scope_index)) = (self.profiler, self.scope_index) {
+            profiler.end_scope(self.command_buffer, scope_index);
+        }
+    }
+}
+
+fn escape_json(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len());
+
+    for character in value.chars() {
+        match character {
+            '"' => escaped.push_str("\\\""),
+            '\\' => escaped.push_str("\\\\"),
+            character if character.is_control() => {
+                let _ = write!(escaped, "\\u{:04x}", character as u32);
+            }
+            character => escaped.push(character),
+        }
+    }
+
+    escaped
+}
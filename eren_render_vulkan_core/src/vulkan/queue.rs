@@ -1,26 +1,38 @@
 use ash::vk;
 
+use crate::vulkan::surface::SurfaceManager;
+
 #[derive(Debug)]
 pub struct QueueFamilyIndices {
     pub graphics_queue_family_index: Option<u32>,
     pub present_queue_family_index: Option<u32>,
+    // Transfer-only family (usually a DMA engine); uploads fall back to the graphics queue without one
+    pub transfer_queue_family_index: Option<u32>,
+    // Compute family without graphics, for async compute; compute work falls back to the graphics
+    // queue without one
+    pub compute_queue_family_index: Option<u32>,
 }
 
 impl QueueFamilyIndices {
     pub fn is_complete(&self) -> bool {
         self.graphics_queue_family_index.is_some() && self.present_queue_family_index.is_some()
     }
+
+    pub fn is_complete_headless(&self) -> bool {
+        self.graphics_queue_family_index.is_some()
+    }
 }
 
 pub fn find_queue_family_indices(
     instance: &ash::Instance,
-    surface_loader: &ash::khr::surface::Instance,
-    surface: vk::SurfaceKHR,
+    surface_manager: Option<&SurfaceManager>,
     physical_device: vk::PhysicalDevice,
 ) -> QueueFamilyIndices {
     let mut indices = QueueFamilyIndices {
         graphics_queue_family_index: None,
         present_queue_family_index: None,
+        transfer_queue_family_index: None,
+        compute_queue_family_index: None,
     };
 
     let queue_families =
@@ -31,9 +43,22 @@ pub fn find_queue_family_indices(
             indices.graphics_queue_family_index = Some(i as u32);
         }
 
+        let Some(surface_manager) = surface_manager else {
+            // Without a surface there is nothing to present to, so the first graphics family is enough.
+            if indices.is_complete_headless() {
+                break;
+            }
+            continue;
+        };
+
         let present_support = unsafe {
-            surface_loader
-                .get_physical_device_surface_support(physical_device, i as u32, surface)
+            surface_manager
+                .surface_loader
+                .get_physical_device_surface_support(
+                    physical_device,
+                    i as u32,
+                    surface_manager.surface,
+                )
                 .unwrap_or(false)
         };
 
@@ -46,5 +71,23 @@ pub fn find_queue_family_indices(
         }
     }
 
+    indices.transfer_queue_family_index = queue_families
+        .iter()
+        .position(|queue_family| {
+            queue_family.queue_flags.contains(vk::QueueFlags::TRANSFER)
+                && !queue_family
+                    .queue_flags
+                    .intersects(vk::QueueFlags::GRAPHICS | vk::QueueFlags::COMPUTE)
+        })
+        .map(|i| i as u32);
+
+    indices.compute_queue_family_index = queue_families
+        .iter()
+        .position(|queue_family| {
+            queue_family.queue_flags.contains(vk::QueueFlags::COMPUTE)
+                && !queue_family.queue_flags.contains(vk::QueueFlags::GRAPHICS)
+        })
+        .map(|i| i as u32);
+
     indices
 }
@@ -0,0 +1,413 @@
+use std::path::Path;
+
+use ash::vk;
+use thiserror::Error;
+
+use crate::vulkan::{
+    allocator::{Allocation, MemoryAllocator},
+    memory::MemoryError,
+};
+
+#[derive(Debug, Error)]
+pub enum ReadbackError {
+    #[error("Unsupported readback format: {0:?}")]
+    UnsupportedFormat(vk::Format),
+
+    #[error("Failed to create readback buffer: {0}")]
+    CreateBufferFailed(#[from] MemoryError),
+
+    #[error("Failed to map readback memory: {0}")]
+    MemoryMappingFailed(MemoryError),
+
+    #[error("Failed to encode PNG: {0}")]
+    EncodePngFailed(png::EncodingError),
+
+    #[error("Failed to write file: {0}")]
+    WriteFileFailed(#[from] std::io::Error),
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct ReadbackSource {
+    pub image: vk::Image,
+    pub format: vk::Format,
+    // How HDR formats are brought down to the RGBA8 of `CapturedFrame`
+    pub color_space: vk::ColorSpaceKHR,
+    pub extent: vk::Extent2D,
+    // Layout the image is in when the copy is recorded; it is restored afterwards.
+    pub layout: vk::ImageLayout,
+}
+
+#[derive(Debug, Clone)]
+pub struct CapturedFrame {
+    pub width: u32,
+    pub height: u32,
+    // Tightly packed RGBA8 rows, top to bottom.
+    pub pixels: Vec<u8>,
+}
+
+impl CapturedFrame {
+    pub fn encode_png(&self) -> Result<Vec<u8>, ReadbackError> {
+        let mut bytes = Vec::new();
+
+        {
+            let mut encoder = png::Encoder::new(&mut bytes, self.width, self.height);
+            encoder.set_color(png::ColorType::Rgba);
+            encoder.set_depth(png::BitDepth::Eight);
+
+            let mut writer = encoder
+                .write_header()
+                .map_err(ReadbackError::EncodePngFailed)?;
+
+            writer
+                .write_image_data(&self.pixels)
+                .map_err(ReadbackError::EncodePngFailed)?;
+        }
+
+        Ok(bytes)
+    }
+
+    pub fn save_png<P: AsRef<Path>>(&self, path: P) -> Result<(), ReadbackError> {
+        std::fs::write(path, self.encode_png()?)?;
+        Ok(())
+    }
+}
+
+pub struct ReadbackBuffer {
+    device: ash::Device,
+
+    buffer: vk::Buffer,
+    allocation: Allocation,
+    size: vk::DeviceSize,
+
+    format: vk::Format,
+    color_space: vk::ColorSpaceKHR,
+    extent: vk::Extent2D,
+}
+
+impl ReadbackBuffer {
+    pub fn new(
+        device: &ash::Device,
+        allocator: &MemoryAllocator,
+        source: &ReadbackSource,
+    ) -> Result<Self, ReadbackError> {
+        let ReadbackSource {
+            format,
+            color_space,
+            extent,
+            ..
+        } = *source;
+
+        let bytes_per_pixel =
+            bytes_per_pixel(format).ok_or(ReadbackError::UnsupportedFormat(format))?;
+
+        let size = extent.width as vk::DeviceSize
+            * extent.height as vk::DeviceSize
+            * bytes_per_pixel as vk::DeviceSize;
+
+        let (buffer, allocation) = allocator.create_buffer(
+            size,
+            vk::BufferUsageFlags::TRANSFER_DST,
+            vk::MemoryPropertyFlags::HOST_VISIBLE | vk::MemoryPropertyFlags::HOST_COHERENT,
+        )?;
+
+        Ok(Self {
+            device: device.clone(),
+            buffer,
+            allocation,
+            size,
+            format,
+            color_space,
+            extent,
+        })
+    }
+
+    pub fn record_copy(&self, command_buffer: vk::CommandBuffer, source: &ReadbackSource) {
+        let subresource_range = vk::ImageSubresourceRange::default()
+            .aspect_mask(vk::ImageAspectFlags::COLOR)
+            .base_mip_level(0)
+            .level_count(1)
+            .base_array_layer(0)
+            .layer_count(1);
+
+        let to_transfer_barrier = vk::ImageMemoryBarrier::default()
+            .src_access_mask(vk::AccessFlags::MEMORY_WRITE)
+            .dst_access_mask(vk::AccessFlags::TRANSFER_READ)
+            .old_layout(source.layout)
+            .new_layout(vk::ImageLayout::TRANSFER_SRC_OPTIMAL)
+            .src_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+            .dst_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+            .image(source.image)
+            .subresource_range(subresource_range);
+
+        let region = vk::BufferImageCopy::default()
+            .buffer_offset(0)
+            .buffer_row_length(0)
+            .buffer_image_height(0)
+            .image_subresource(
+                vk::ImageSubresourceLayers::default()
+                    .aspect_mask(vk::ImageAspectFlags::COLOR)
+                    .mip_level(0)
+                    .base_array_layer(0)
+                    .layer_count(1),
+            )
+            .image_offset(vk::Offset3D::default())
+            .image_extent(vk::Extent3D {
+                width: self.extent.width,
+                height: self.extent.height,
+                depth: 1,
+            });
+
+        let restore_barrier = vk::ImageMemoryBarrier::default()
+            .src_access_mask(vk::AccessFlags::TRANSFER_READ)
+            .dst_access_mask(vk::AccessFlags::empty())
+            .old_layout(vk::ImageLayout::TRANSFER_SRC_OPTIMAL)
+            .new_layout(source.layout)
+            .src_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+            .dst_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+            .image(source.image)
+            .subresource_range(subresource_range);
+
+        let host_read_barrier = vk::BufferMemoryBarrier::default()
+            .src_access_mask(vk::AccessFlags::TRANSFER_WRITE)
+            .dst_access_mask(vk::AccessFlags::HOST_READ)
+            .src_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+            .dst_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+            .buffer(self.buffer)
+            .offset(0)
+            .size(self.size);
+
+        unsafe {
+            self.device.cmd_pipeline_barrier(
+                command_buffer,
+                vk::PipelineStageFlags::ALL_COMMANDS,
+                vk::PipelineStageFlags::TRANSFER,
+                vk::DependencyFlags::empty(),
+                &[],
+                &[],
+                &[to_transfer_barrier],
+            );
+
+            self.device.cmd_copy_image_to_buffer(
+                command_buffer,
+                source.image,
+                vk::ImageLayout::TRANSFER_SRC_OPTIMAL,
+                self.buffer,
+                &[region],
+            );
+
+            self.device.cmd_pipeline_barrier(
+                command_buffer,
+                vk::PipelineStageFlags::TRANSFER,
+                vk::PipelineStageFlags::BOTTOM_OF_PIPE | vk::PipelineStageFlags::HOST,
+                vk::DependencyFlags::empty(),
+                &[],
+                &[host_read_barrier],
+                &[restore_barrier],
+            );
+        }
+    }
+
+    // Must only be called once the commands recorded by `record_copy` have completed.
+    pub fn read(&self) -> Result<CapturedFrame, ReadbackError> {
+        let data = self
+            .allocation
+            .read_bytes(0, self.size as usize)
+            .map_err(ReadbackError::MemoryMappingFailed)?;
+
+        Ok(CapturedFrame {
+            width: self.extent.width,
+            height: self.extent.height,
+            pixels: convert_to_rgba8(self.format, self.color_space, data)?,
+        })
+    }
+}
+
+impl Drop for ReadbackBuffer {
+    fn drop(&mut self) {
+        unsafe {
+            self.device.destroy_buffer(self.buffer, None);
+        }
+    }
+}
+
+// BT.2408 reference white; the 3D final pass puts SDR white here on HDR surfaces by default
+const PAPER_WHITE_NITS: f32 = 203.0;
+
+// scRGB puts 1.0 at 80 nits
+const SCRGB_WHITE_NITS: f32 = 80.0;
+
+fn bytes_per_pixel(format: vk::Format) -> Option<u32> {
+    match format {
+        vk::Format::R8G8B8A8_UNORM
+        | vk::Format::R8G8B8A8_SRGB
+        | vk::Format::B8G8R8A8_UNORM
+        | vk::Format::B8G8R8A8_SRGB
+        | vk::Format::A2B10G10R10_UNORM_PACK32 => Some(4),
+        vk::Format::R16G16B16A16_SFLOAT => Some(8),
+        _ => None,
+    }
+}
+
+// HDR captures are mapped back to SDR sRGB, clipping everything brighter than paper white.
+fn convert_to_rgba8(
+    format: vk::Format,
+    color_space: vk::ColorSpaceKHR,
+    mut data: Vec<u8>,
+) -> Result<Vec<u8>, ReadbackError> {
+    match format {
+        vk::Format::R8G8B8A8_UNORM | vk::Format::R8G8B8A8_SRGB => Ok(data),
+        vk::Format::B8G8R8A8_UNORM | vk::Format::B8G8R8A8_SRGB => {
+            for pixel in data.chunks_exact_mut(4) {
+                pixel.swap(0, 2);
+            }
+            Ok(data)
+        }
+        vk::Format::A2B10G10R10_UNORM_PACK32 => Ok(data
+            .chunks_exact(4)
+            .flat_map(|pixel| {
+                let packed = u32::from_le_bytes([pixel[0], pixel[1], pixel[2], pixel[3]]);
+                let channel = |shift: u32| ((packed >> shift) & 0x3ff) as f32 / 1023.0;
+                let rgb = [channel(0), channel(10), channel(20)];
+                let alpha = (packed >> 30) as f32 / 3.0;
+
+                let rgb = if color_space == vk::ColorSpaceKHR::HDR10_ST2084_EXT {
+                    let linear =
+                        bt2020_to_bt709(rgb.map(|value| pq_decode(value) / PAPER_WHITE_NITS));
+                    linear.map(srgb_encode)
+                } else {
+                    rgb
+                };
+
+                to_rgba8(rgb, alpha)
+            })
+            .collect()),
+        vk::Format::R16G16B16A16_SFLOAT => Ok(data
+            .chunks_exact(8)
+            .flat_map(|pixel| {
+                let channel = |index: usize| {
+                    f16_to_f32(u16::from_le_bytes([pixel[index * 2], pixel[index * 2 + 1]]))
+                };
+                let scale = if color_space == vk::ColorSpaceKHR::EXTENDED_SRGB_LINEAR_EXT {
+                    SCRGB_WHITE_NITS / PAPER_WHITE_NITS
+                } else {
+                    1.0
+                };
+                let rgb = [channel(0), channel(1), channel(2)];
+
+                to_rgba8(rgb.map(|value| srgb_encode(value * scale)), channel(3))
+            })
+            .collect()),
+        _ => Err(ReadbackError::UnsupportedFormat(format)),
+    }
+}
+
+fn to_rgba8(rgb: [f32; 3], alpha: f32) -> [u8; 4] {
+    let quantize = |value: f32| (value.clamp(0.0, 1.0) * 255.0).round() as u8;
+
+    [
+        quantize(rgb[0]),
+        quantize(rgb[1]),
+        quantize(rgb[2]),
+        quantize(alpha),
+    ]
+}
+
+// ST 2084 constants, written as the fractions the standard defines them by
+const PQ_M1: f32 = 2610.0 / 16384.0;
+const PQ_M2: f32 = 2523.0 / 4096.0 * 128.0;
+const PQ_C1: f32 = 3424.0 / 4096.0;
+const PQ_C2: f32 = 2413.0 / 4096.0 * 32.0;
+const PQ_C3: f32 = 2392.0 / 4096.0 * 32.0;
+
+// ST 2084 signal to absolute luminance in nits
+fn pq_decode(value: f32) -> f32 {
+    let p = value.clamp(0.0, 1.0).powf(1.0 / PQ_M2);
+    ((p - PQ_C1).max(0.0) / (PQ_C2 - PQ_C3 * p)).powf(1.0 / PQ_M1) * 10000.0
+}
+
+// Inverse of the BT.709 to BT.2020 matrix in final.frag
+fn bt2020_to_bt709(rgb: [f32; 3]) -> [f32; 3] {
+    [
+        1.6605 * rgb[0] - 0.5876 * rgb[1] - 0.0728 * rgb[2],
+        -0.1246 * rgb[0] + 1.1329 * rgb[1] - 0.0083 * rgb[2],
+        -0.0182 * rgb[0] - 0.1006 * rgb[1] + 1.1187 * rgb[2],
+    ]
+}
+
+fn srgb_encode(linear: f32) -> f32 {
+    if linear <= 0.0031308 {
+        linear * 12.92
+    } else {
+        1.055 * linear.powf(1.0 / 2.4) - 0.055
+    }
+}
+
+fn f16_to_f32(bits: u16) -> f32 {
+    let sign = if bits & 0x8000 != 0 { -1.0 } else { 1.0 };
+    let exponent = ((bits >> 10) & 0x1f) as i32;
+    let mantissa = (bits & 0x3ff) as f32;
+
+    match exponent {
+        0 => sign * mantissa * 2f32.powi(-24),
+        0x1f if mantissa == 0.0 => sign * f32::INFINITY,
+        0x1f => f32::NAN,
+        _ => sign * (1.0 + mantissa / 1024.0) * 2f32.powi(exponent - 15),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pq_encode(nits: f32) -> f32 {
+        let y = (nits / 10000.0).powf(PQ_M1);
+        ((PQ_C1 + PQ_C2 * y) / (1.0 + PQ_C3 * y)).powf(PQ_M2)
+    }
+
+    #[test]
+    fn hdr10_paper_white_reads_back_as_sdr_white() {
+        let signal = (pq_encode(PAPER_WHITE_NITS) * 1023.0).round() as u32;
+        let packed = signal | signal << 10 | signal << 20 | 3 << 30;
+
+        let pixels = convert_to_rgba8(
+            vk::Format::A2B10G10R10_UNORM_PACK32,
+            vk::ColorSpaceKHR::HDR10_ST2084_EXT,
+            packed.to_le_bytes().to_vec(),
+        )
+        .unwrap();
+
+        for channel in &pixels[..3] {
+            assert!(*channel >= 253, "{:?}", pixels);
+        }
+        assert_eq!(pixels[3], 255);
+    }
+
+    #[test]
+    fn scrgb_is_scaled_to_paper_white_and_clipped() {
+        // 1.0 (80 nits), 2.5375 (paper white) and 4.0 (above paper white) as half floats
+        let pixel: Vec<u8> = [0x3c00u16, 0x4113, 0x4400, 0x3c00]
+            .iter()
+            .flat_map(|bits| bits.to_le_bytes())
+            .collect();
+
+        let pixels = convert_to_rgba8(
+            vk::Format::R16G16B16A16_SFLOAT,
+            vk::ColorSpaceKHR::EXTENDED_SRGB_LINEAR_EXT,
+            pixel,
+        )
+        .unwrap();
+
+        assert_eq!(pixels[0], (srgb_encode(80.0 / 203.0) * 255.0).round() as u8);
+        assert!(pixels[1] >= 254);
+        assert_eq!(&pixels[2..], &[255, 255]);
+    }
+
+    #[test]
+    fn f16_to_f32_decodes_normal_subnormal_and_special_values() {
+        assert_eq!(f16_to_f32(0x3c00), 1.0);
+        assert_eq!(f16_to_f32(0xc000), -2.0);
+        assert_eq!(f16_to_f32(0x0001), 2f32.powi(-24));
+        assert_eq!(f16_to_f32(0x7c00), f32::INFINITY);
+        assert!(f16_to_f32(0x7e00).is_nan());
+    }
+}
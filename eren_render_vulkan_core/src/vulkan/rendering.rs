@@ -0,0 +1,640 @@
+use ash::vk;
+
+use crate::vulkan::{
+    debug::DebugUtils,
+    destruction::{DestructionQueue, ReleasedObject},
+    features::{DeviceFeature, EnabledDeviceFeatures},
+};
+
+const WRITE_ACCESS: vk::AccessFlags2 = vk::AccessFlags2::from_raw(
+    vk::AccessFlags2::SHADER_WRITE.as_raw()
+        | vk::AccessFlags2::COLOR_ATTACHMENT_WRITE.as_raw()
+        | vk::AccessFlags2::DEPTH_STENCIL_ATTACHMENT_WRITE.as_raw()
+        | vk::AccessFlags2::TRANSFER_WRITE.as_raw()
+        | vk::AccessFlags2::MEMORY_WRITE.as_raw(),
+);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RenderingPath {
+    // `vkCmdBeginRendering`, with synchronization2 barriers for the layout transitions
+    Dynamic,
+    // Render pass and framebuffer objects, for devices without the Vulkan 1.3 features
+    RenderPass,
+}
+
+impl RenderingPath {
+    pub fn for_features(enabled_features: &EnabledDeviceFeatures) -> Self {
+        if enabled_features.has_feature(DeviceFeature::DynamicRendering)
+            && enabled_features.has_feature(DeviceFeature::Synchronization2)
+        {
+            Self::Dynamic
+        } else {
+            Self::RenderPass
+        }
+    }
+}
+
+#[derive(Clone, Copy)]
+pub struct RenderingAttachment {
+    pub format: vk::Format,
+    pub load_op: vk::AttachmentLoadOp,
+    pub store_op: vk::AttachmentStoreOp,
+    pub clear_value: vk::ClearValue,
+    // Only meaningful when loading; cleared attachments start out UNDEFINED
+    pub initial_layout: vk::ImageLayout,
+    // Layout the pass leaves the image in, e.g. SHADER_READ_ONLY_OPTIMAL for a later pass to sample
+    pub final_layout: vk::ImageLayout,
+}
+
+impl RenderingAttachment {
+    pub fn new(format: vk::Format, final_layout: vk::ImageLayout) -> Self {
+        Self {
+            format,
+            load_op: vk::AttachmentLoadOp::CLEAR,
+            store_op: vk::AttachmentStoreOp::STORE,
+            clear_value: vk::ClearValue::default(),
+            initial_layout: vk::ImageLayout::UNDEFINED,
+            final_layout,
+        }
+    }
+
+    pub fn clear_value(mut self, clear_value: vk::ClearValue) -> Self {
+        self.load_op = vk::AttachmentLoadOp::CLEAR;
+        self.initial_layout = vk::ImageLayout::UNDEFINED;
+        self.clear_value = clear_value;
+        self
+    }
+
+    // Keeps the previous contents, which must be in `initial_layout` when the pass begins.
+    pub fn load(mut self, initial_layout: vk::ImageLayout) -> Self {
+        self.load_op = vk::AttachmentLoadOp::LOAD;
+        self.initial_layout = initial_layout;
+        self
+    }
+
+    pub fn store_op(mut self, store_op: vk::AttachmentStoreOp) -> Self {
+        self.store_op = store_op;
+        self
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct AttachmentImage {
+    pub image: vk::Image,
+    pub image_view: vk::ImageView,
+    // The layer `image_view` shows, for targets that render into one layer of an array image
+    pub array_layer: u32,
+}
+
+impl AttachmentImage {
+    pub fn new(image: vk::Image, image_view: vk::ImageView) -> Self {
+        Self {
+            image,
+            image_view,
+            array_layer: 0,
+        }
+    }
+
+    pub fn array_layer(mut self, array_layer: u32) -> Self {
+        self.array_layer = array_layer;
+        self
+    }
+}
+
+// The attachments a pass renders to and how they are loaded, stored and left afterwards. On the
+// render pass path this owns the render pass; on the dynamic path there is no object to create.
+pub struct RenderingLayout {
+    device: ash::Device,
+    destruction_queue: DestructionQueue,
+    path: RenderingPath,
+
+    color_attachments: Vec<RenderingAttachment>,
+    color_formats: Vec<vk::Format>,
+    depth_attachment: Option<RenderingAttachment>,
+    clear_values: Vec<vk::ClearValue>,
+
+    render_pass: vk::RenderPass,
+}
+
+impl RenderingLayout {
+    pub fn new(
+        device: &ash::Device,
+        destruction_queue: &DestructionQueue,
+        path: RenderingPath,
+        color_attachments: &[RenderingAttachment],
+        depth_attachment: Option<RenderingAttachment>,
+    ) -> Result<Self, vk::Result> {
+        let mut layout = Self {
+            device: device.clone(),
+            destruction_queue: destruction_queue.clone(),
+            path,
+
+            color_attachments: color_attachments.to_vec(),
+            color_formats: color_attachments
+                .iter()
+                .map(|attachment| attachment.format)
+                .collect(),
+            depth_attachment,
+            // In attachment order, which is also the render pass attachment index
+            clear_values: color_attachments
+                .iter()
+                .chain(depth_attachment.iter())
+                .map(|attachment| attachment.clear_value)
+                .collect(),
+
+            render_pass: vk::RenderPass::null(),
+        };
+
+        if path == RenderingPath::RenderPass {
+            layout.render_pass = layout.create_render_pass()?;
+        }
+
+        Ok(layout)
+    }
+
+    // Chain into the pipeline through `pipeline_info`, which also picks the right path.
+    pub fn pipeline_rendering_info(&self) -> vk::PipelineRenderingCreateInfo<'_> {
+        let depth_format = self
+            .depth_attachment
+            .map_or(vk::Format::UNDEFINED, |attachment| attachment.format);
+        let stencil_format = if has_stencil(depth_format) {
+            depth_format
+        } else {
+            vk::Format::UNDEFINED
+        };
+
+        vk::PipelineRenderingCreateInfo::default()
+            .color_attachment_formats(&self.color_formats)
+            .depth_attachment_format(depth_format)
+            .stencil_attachment_format(stencil_format)
+    }
+
+    // Makes `pipeline_info` compatible with this layout: the render pass on that path, otherwise
+    // `rendering_info` from `pipeline_rendering_info`.
+    pub fn pipeline_info<'a>(
+        &self,
+        pipeline_info: vk::GraphicsPipelineCreateInfo<'a>,
+        rendering_info: &'a mut vk::PipelineRenderingCreateInfo<'_>,
+    ) -> vk::GraphicsPipelineCreateInfo<'a> {
+        match self.path {
+            RenderingPath::Dynamic => pipeline_info.push_next(rendering_info),
+            RenderingPath::RenderPass => pipeline_info.render_pass(self.render_pass).subpass(0),
+        }
+    }
+
+    // Images must match the attachments passed to `new`, in the same order.
+    pub fn create_target(
+        &self,
+        color_images: &[AttachmentImage],
+        depth_image: Option<AttachmentImage>,
+        extent: vk::Extent2D,
+    ) -> Result<RenderingTarget, vk::Result> {
+        let mut target = RenderingTarget {
+            destruction_queue: self.destruction_queue.clone(),
+            framebuffer: vk::Framebuffer::null(),
+            color_images: color_images.to_vec(),
+            depth_image,
+            extent,
+        };
+
+        if self.path == RenderingPath::RenderPass {
+            let image_views: Vec<vk::ImageView> = color_images
+                .iter()
+                .chain(depth_image.iter())
+                .map(|attachment_image| attachment_image.image_view)
+                .collect();
+
+            let framebuffer_info = vk::FramebufferCreateInfo::default()
+                .render_pass(self.render_pass)
+                .attachments(&image_views)
+                .width(extent.width)
+                .height(extent.height)
+                .layers(1);
+
+            target.framebuffer =
+                unsafe { self.device.create_framebuffer(&framebuffer_info, None)? };
+        }
+
+        Ok(target)
+    }
+
+    pub fn begin(&self, command_buffer: vk::CommandBuffer, target: &RenderingTarget) {
+        let render_area = vk::Rect2D::default().extent(target.extent);
+
+        match self.path {
+            RenderingPath::Dynamic => {
+                let barriers: Vec<vk::ImageMemoryBarrier2> = self
+                    .attachments(target)
+                    .map(|(attachment, attachment_image, is_depth)| {
+                        let (attachment_stage, attachment_access) = attachment_scope(is_depth);
+                        // Previous frames may still write the image as an attachment or read it
+                        // in its final layout.
+                        let (previous_stage, previous_access) =
+                            if attachment.initial_layout == vk::ImageLayout::UNDEFINED {
+                                layout_scope(attachment.final_layout)
+                            } else {
+                                layout_scope(attachment.initial_layout)
+                            };
+
+                        image_barrier(attachment, attachment_image, is_depth)
+                            .old_layout(attachment.initial_layout)
+                            .new_layout(attachment_layout(is_depth))
+                            .src_stage_mask(attachment_stage | previous_stage)
+                            .src_access_mask((attachment_access | previous_access) & WRITE_ACCESS)
+                            .dst_stage_mask(attachment_stage)
+                            .dst_access_mask(attachment_access)
+                    })
+                    .collect();
+
+                let color_attachment_infos: Vec<vk::RenderingAttachmentInfo> = self
+                    .color_attachments
+                    .iter()
+                    .zip(&target.color_images)
+                    .map(|(attachment, attachment_image)| {
+                        rendering_attachment_info(attachment, attachment_image, false)
+                    })
+                    .collect();
+                let depth_attachment_info = self.depth_attachment.zip(target.depth_image).map(
+                    |(attachment, attachment_image)| {
+                        rendering_attachment_info(&attachment, &attachment_image, true)
+                    },
+                );
+
+                let mut rendering_info = vk::RenderingInfo::default()
+                    .render_area(render_area)
+                    .layer_count(1)
+                    .color_attachments(&color_attachment_infos);
+                if let Some(depth_attachment_info) = &depth_attachment_info {
+                    rendering_info = rendering_info.depth_attachment(depth_attachment_info);
+                    if self.depth_attachment.is_some_and(|a| has_stencil(a.format)) {
+                        rendering_info = rendering_info.stencil_attachment(depth_attachment_info);
+                    }
+                }
+
+                unsafe {
+                    self.device.cmd_pipeline_barrier2(
+                        command_buffer,
+                        &vk::DependencyInfo::default().image_memory_barriers(&barriers),
+                    );
+                    self.device
+                        .cmd_begin_rendering(command_buffer, &rendering_info);
+                }
+            }
+            RenderingPath::RenderPass => {
+                let render_pass_begin_info = vk::RenderPassBeginInfo::default()
+                    .render_pass(self.render_pass)
+                    .framebuffer(target.framebuffer)
+                    .render_area(render_area)
+                    .clear_values(&self.clear_values);
+
+                let subpass_begin_info =
+                    vk::SubpassBeginInfo::default().contents(vk::SubpassContents::INLINE);
+
+                unsafe {
+                    self.device.cmd_begin_render_pass2(
+                        command_buffer,
+                        &render_pass_begin_info,
+                        &subpass_begin_info,
+                    );
+                }
+            }
+        }
+    }
+
+    pub fn end(&self, command_buffer: vk::CommandBuffer, target: &RenderingTarget) {
+        match self.path {
+            RenderingPath::Dynamic => {
+                let barriers: Vec<vk::ImageMemoryBarrier2> = self
+                    .attachments(target)
+                    .map(|(attachment, attachment_image, is_depth)| {
+                        let (attachment_stage, attachment_access) = attachment_scope(is_depth);
+                        let (next_stage, next_access) = layout_scope(attachment.final_layout);
+
+                        image_barrier(attachment, attachment_image, is_depth)
+                            .old_layout(attachment_layout(is_depth))
+                            .new_layout(attachment.final_layout)
+                            .src_stage_mask(attachment_stage)
+                            .src_access_mask(attachment_access & WRITE_ACCESS)
+                            .dst_stage_mask(next_stage)
+                            .dst_access_mask(next_access)
+                    })
+                    .collect();
+
+                unsafe {
+                    self.device.cmd_end_rendering(command_buffer);
+                    self.device.cmd_pipeline_barrier2(
+                        command_buffer,
+                        &vk::DependencyInfo::default().image_memory_barriers(&barriers),
+                    );
+                }
+            }
+            RenderingPath::RenderPass => unsafe {
+                self.device
+                    .cmd_end_render_pass2(command_buffer, &vk::SubpassEndInfo::default());
+            },
+        }
+    }
+
+    pub fn set_debug_name(&self, debug_utils: &DebugUtils, name: &str) {
+        if self.render_pass != vk::RenderPass::null() {
+            debug_utils.set_object_name(self.render_pass, name);
+        }
+    }
+
+    fn attachments<'a>(
+        &'a self,
+        target: &'a RenderingTarget,
+    ) -> impl Iterator<Item = (&'a RenderingAttachment, &'a AttachmentImage, bool)> {
+        let color = self
+            .color_attachments
+            .iter()
+            .zip(&target.color_images)
+            .map(|(attachment, attachment_image)| (attachment, attachment_image, false));
+        let depth = self
+            .depth_attachment
+            .iter()
+            .zip(&target.depth_image)
+            .map(|(attachment, attachment_image)| (attachment, attachment_image, true));
+
+        color.chain(depth)
+    }
+
+    fn create_render_pass(&self) -> Result<vk::RenderPass, vk::Result> {
+        let attachment_descriptions: Vec<vk::AttachmentDescription2> = self
+            .color_attachments
+            .iter()
+            .chain(self.depth_attachment.iter())
+            .map(|attachment| {
+                vk::AttachmentDescription2::default()
+                    .format(attachment.format)
+                    .samples(vk::SampleCountFlags::TYPE_1)
+                    .load_op(attachment.load_op)
+                    .store_op(attachment.store_op)
+                    .stencil_load_op(vk::AttachmentLoadOp::DONT_CARE)
+                    .stencil_store_op(vk::AttachmentStoreOp::DONT_CARE)
+                    .initial_layout(attachment.initial_layout)
+                    .final_layout(attachment.final_layout)
+            })
+            .collect();
+
+        let color_attachment_refs: Vec<vk::AttachmentReference2> =
+            (0..self.color_attachments.len())
+                .map(|index| {
+                    vk::AttachmentReference2::default()
+                        .attachment(index as u32)
+                        .layout(attachment_layout(false))
+                        .aspect_mask(vk::ImageAspectFlags::COLOR)
+                })
+                .collect();
+        let depth_attachment_ref = self.depth_attachment.map(|attachment| {
+            vk::AttachmentReference2::default()
+                .attachment(self.color_attachments.len() as u32)
+                .layout(attachment_layout(true))
+                .aspect_mask(depth_aspect(attachment.format))
+        });
+
+        let mut subpass = vk::SubpassDescription2::default()
+            .pipeline_bind_point(vk::PipelineBindPoint::GRAPHICS)
+            .color_attachments(&color_attachment_refs);
+        if let Some(depth_attachment_ref) = &depth_attachment_ref {
+            subpass = subpass.depth_stencil_attachment(depth_attachment_ref);
+        }
+
+        // The same scopes the dynamic path puts in its barriers, merged over all attachments.
+        let mut attachment_stage = vk::PipelineStageFlags2::NONE;
+        let mut attachment_access = vk::AccessFlags2::NONE;
+        let mut previous_stage = vk::PipelineStageFlags2::NONE;
+        let mut previous_access = vk::AccessFlags2::NONE;
+        let mut next_stage = vk::PipelineStageFlags2::NONE;
+        let mut next_access = vk::AccessFlags2::NONE;
+
+        for (attachment, is_depth) in self
+            .color_attachments
+            .iter()
+            .map(|attachment| (attachment, false))
+            .chain(
+                self.depth_attachment
+                    .iter()
+                    .map(|attachment| (attachment, true)),
+            )
+        {
+            let (stage, access) = attachment_scope(is_depth);
+            attachment_stage |= stage;
+            attachment_access |= access;
+
+            let (stage, access) = if attachment.initial_layout == vk::ImageLayout::UNDEFINED {
+                layout_scope(attachment.final_layout)
+            } else {
+                layout_scope(attachment.initial_layout)
+            };
+            previous_stage |= stage;
+            previous_access |= access;
+
+            let (stage, access) = layout_scope(attachment.final_layout);
+            next_stage |= stage;
+            next_access |= access;
+        }
+
+        let dependencies = [
+            vk::SubpassDependency2::default()
+                .src_subpass(vk::SUBPASS_EXTERNAL)
+                .dst_subpass(0)
+                .src_stage_mask(to_stage_flags(
+                    attachment_stage | previous_stage,
+                    vk::PipelineStageFlags::TOP_OF_PIPE,
+                ))
+                .src_access_mask(to_access_flags(
+                    (attachment_access | previous_access) & WRITE_ACCESS,
+                ))
+                .dst_stage_mask(to_stage_flags(
+                    attachment_stage,
+                    vk::PipelineStageFlags::BOTTOM_OF_PIPE,
+                ))
+                .dst_access_mask(to_access_flags(attachment_access)),
+            vk::SubpassDependency2::default()
+                .src_subpass(0)
+                .dst_subpass(vk::SUBPASS_EXTERNAL)
+                .src_stage_mask(to_stage_flags(
+                    attachment_stage,
+                    vk::PipelineStageFlags::TOP_OF_PIPE,
+                ))
+                .src_access_mask(to_access_flags(attachment_access & WRITE_ACCESS))
+                .dst_stage_mask(to_stage_flags(
+                    next_stage,
+                    vk::PipelineStageFlags::BOTTOM_OF_PIPE,
+                ))
+                .dst_access_mask(to_access_flags(next_access)),
+        ];
+
+        let render_pass_info = vk::RenderPassCreateInfo2::default()
+            .attachments(&attachment_descriptions)
+            .subpasses(std::slice::from_ref(&subpass))
+            .dependencies(&dependencies);
+
+        unsafe { self.device.create_render_pass2(&render_pass_info, None) }
+    }
+}
+
+impl Drop for RenderingLayout {
+    fn drop(&mut self) {
+        if self.render_pass != vk::RenderPass::null() {
+            self.destruction_queue
+                .release(ReleasedObject::RenderPass(self.render_pass));
+        }
+    }
+}
+
+// The images one `RenderingLayout` renders into, e.g. one per swapchain image. Owns the framebuffer
+// on the render pass path; the images and views stay owned by the caller.
+pub struct RenderingTarget {
+    destruction_queue: DestructionQueue,
+    framebuffer: vk::Framebuffer,
+    color_images: Vec<AttachmentImage>,
+    depth_image: Option<AttachmentImage>,
+    extent: vk::Extent2D,
+}
+
+impl RenderingTarget {
+    pub fn render_area(&self) -> vk::Rect2D {
+        vk::Rect2D::default().extent(self.extent)
+    }
+
+    pub fn set_debug_name(&self, debug_utils: &DebugUtils, name: &str) {
+        if self.framebuffer != vk::Framebuffer::null() {
+            debug_utils.set_object_name(self.framebuffer, name);
+        }
+    }
+}
+
+impl Drop for RenderingTarget {
+    fn drop(&mut self) {
+        if self.framebuffer != vk::Framebuffer::null() {
+            self.destruction_queue
+                .release(ReleasedObject::Framebuffer(self.framebuffer));
+        }
+    }
+}
+
+fn attachment_layout(is_depth: bool) -> vk::ImageLayout {
+    if is_depth {
+        vk::ImageLayout::DEPTH_STENCIL_ATTACHMENT_OPTIMAL
+    } else {
+        vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL
+    }
+}
+
+fn attachment_scope(is_depth: bool) -> (vk::PipelineStageFlags2, vk::AccessFlags2) {
+    if is_depth {
+        (
+            vk::PipelineStageFlags2::EARLY_FRAGMENT_TESTS
+                | vk::PipelineStageFlags2::LATE_FRAGMENT_TESTS,
+            vk::AccessFlags2::DEPTH_STENCIL_ATTACHMENT_READ
+                | vk::AccessFlags2::DEPTH_STENCIL_ATTACHMENT_WRITE,
+        )
+    } else {
+        (
+            vk::PipelineStageFlags2::COLOR_ATTACHMENT_OUTPUT,
+            vk::AccessFlags2::COLOR_ATTACHMENT_READ | vk::AccessFlags2::COLOR_ATTACHMENT_WRITE,
+        )
+    }
+}
+
+// Where an image in `layout` is used outside of the pass. Only flags that exist in both
+// synchronization APIs are used, see `to_stage_flags`.
+fn layout_scope(layout: vk::ImageLayout) -> (vk::PipelineStageFlags2, vk::AccessFlags2) {
+    match layout {
+        vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL
+        | vk::ImageLayout::DEPTH_STENCIL_READ_ONLY_OPTIMAL
+        | vk::ImageLayout::DEPTH_READ_ONLY_OPTIMAL
+        | vk::ImageLayout::READ_ONLY_OPTIMAL => (
+            vk::PipelineStageFlags2::FRAGMENT_SHADER,
+            vk::AccessFlags2::SHADER_READ,
+        ),
+        vk::ImageLayout::TRANSFER_SRC_OPTIMAL => (
+            vk::PipelineStageFlags2::TRANSFER,
+            vk::AccessFlags2::TRANSFER_READ,
+        ),
+        // Presentation waits on a semaphore, which already orders it after the pass.
+        vk::ImageLayout::PRESENT_SRC_KHR => (vk::PipelineStageFlags2::NONE, vk::AccessFlags2::NONE),
+        vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL => attachment_scope(false),
+        vk::ImageLayout::DEPTH_STENCIL_ATTACHMENT_OPTIMAL
+        | vk::ImageLayout::DEPTH_ATTACHMENT_OPTIMAL => attachment_scope(true),
+        _ => (
+            vk::PipelineStageFlags2::ALL_COMMANDS,
+            vk::AccessFlags2::MEMORY_READ | vk::AccessFlags2::MEMORY_WRITE,
+        ),
+    }
+}
+
+fn image_barrier<'a>(
+    attachment: &RenderingAttachment,
+    attachment_image: &AttachmentImage,
+    is_depth: bool,
+) -> vk::ImageMemoryBarrier2<'a> {
+    let aspect_mask = if is_depth {
+        depth_aspect(attachment.format)
+    } else {
+        vk::ImageAspectFlags::COLOR
+    };
+
+    vk::ImageMemoryBarrier2::default()
+        .src_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+        .dst_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+        .image(attachment_image.image)
+        .subresource_range(
+            vk::ImageSubresourceRange::default()
+                .aspect_mask(aspect_mask)
+                .base_mip_level(0)
+                .level_count(1)
+                .base_array_layer(attachment_image.array_layer)
+                .layer_count(1),
+        )
+}
+
+fn rendering_attachment_info<'a>(
+    attachment: &RenderingAttachment,
+    attachment_image: &AttachmentImage,
+    is_depth: bool,
+) -> vk::RenderingAttachmentInfo<'a> {
+    vk::RenderingAttachmentInfo::default()
+        .image_view(attachment_image.image_view)
+        .image_layout(attachment_layout(is_depth))
+        .load_op(attachment.load_op)
+        .store_op(attachment.store_op)
+        .clear_value(attachment.clear_value)
+}
+
+fn has_stencil(format: vk::Format) -> bool {
+    matches!(
+        format,
+        vk::Format::D16_UNORM_S8_UINT
+            | vk::Format::D24_UNORM_S8_UINT
+            | vk::Format::D32_SFLOAT_S8_UINT
+    )
+}
+
+fn depth_aspect(format: vk::Format) -> vk::ImageAspectFlags {
+    if has_stencil(format) {
+        vk::ImageAspectFlags::DEPTH | vk::ImageAspectFlags::STENCIL
+    } else {
+        vk::ImageAspectFlags::DEPTH
+    }
+}
+
+// The legacy stage and access bits are the low bits of their synchronization2 counterparts, so the
+// render pass path can reuse the same scopes. An empty stage mask is only valid with
+// synchronization2, so it becomes `empty` instead.
+fn to_stage_flags(
+    stage: vk::PipelineStageFlags2,
+    empty: vk::PipelineStageFlags,
+) -> vk::PipelineStageFlags {
+    if stage.is_empty() {
+        empty
+    } else {
+        vk::PipelineStageFlags::from_raw(stage.as_raw() as u32)
+    }
+}
+
+fn to_access_flags(access: vk::AccessFlags2) -> vk::AccessFlags {
+    vk::AccessFlags::from_raw(access.as_raw() as u32)
+}
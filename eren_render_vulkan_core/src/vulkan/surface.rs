@@ -10,7 +10,7 @@ use winit::{
 #[derive(Debug, Error)]
 pub enum SurfaceManagerError {
     #[error("Failed to create surface: {0}")]
-    CreateSurfaceFailed(String),
+    CreateSurfaceFailed(vk::Result),
 }
 
 pub struct SurfaceManager {
@@ -34,7 +34,7 @@ impl SurfaceManager {
                 window.window_handle().unwrap().as_raw(),
                 None,
             )
-            .map_err(|e| SurfaceManagerError::CreateSurfaceFailed(e.to_string()))?
+            .map_err(SurfaceManagerError::CreateSurfaceFailed)?
         };
 
         Ok(Self {
@@ -2,7 +2,10 @@ use ash::{khr::swapchain, vk};
 use thiserror::Error;
 use winit::window::Window;
 
-use crate::vulkan::queue::QueueFamilyIndices;
+use crate::{
+    constants::{DEFAULT_FRAMES_IN_FLIGHT, MAX_FRAMES_IN_FLIGHT},
+    vulkan::{physical_device::PhysicalDeviceManager, surface::SurfaceManager},
+};
 
 #[derive(Debug, Error)]
 pub enum SwapchainManagerError {
@@ -10,10 +13,152 @@ pub enum SwapchainManagerError {
     SwapchainSupportQueryFailed(#[from] SwapchainSupportError),
 
     #[error("Failed to create swapchain: {0}")]
-    CreateSwapchainFailed(String),
+    CreateSwapchainFailed(vk::Result),
 
     #[error("Failed to get swapchain images: {0}")]
-    GetSwapchainImagesFailed(String),
+    GetSwapchainImagesFailed(vk::Result),
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum VsyncMode {
+    // Never tears; MAILBOX is used where available for lower latency
+    #[default]
+    On,
+    // Presents immediately and may tear
+    Off,
+    // Syncs while keeping up with the display, tears instead of stuttering when a frame is late
+    Adaptive,
+}
+
+impl VsyncMode {
+    fn present_modes(&self) -> &'static [vk::PresentModeKHR] {
+        match self {
+            VsyncMode::On => &[vk::PresentModeKHR::MAILBOX, vk::PresentModeKHR::FIFO],
+            VsyncMode::Off => &[
+                vk::PresentModeKHR::IMMEDIATE,
+                vk::PresentModeKHR::MAILBOX,
+                vk::PresentModeKHR::FIFO,
+            ],
+            VsyncMode::Adaptive => &[vk::PresentModeKHR::FIFO_RELAXED, vk::PresentModeKHR::FIFO],
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum HdrMode {
+    #[default]
+    Off,
+    // A2B10G10R10 with the ST 2084 (PQ) transfer function and BT.2020 primaries
+    Hdr10,
+    // R16G16B16A16_SFLOAT, linear with BT.709 primaries and 1.0 at 80 nits
+    ScRgb,
+    // HDR10 where available, then scRGB
+    Auto,
+}
+
+impl HdrMode {
+    fn surface_formats(&self) -> &'static [(vk::Format, vk::ColorSpaceKHR)] {
+        const HDR10: (vk::Format, vk::ColorSpaceKHR) = (
+            vk::Format::A2B10G10R10_UNORM_PACK32,
+            vk::ColorSpaceKHR::HDR10_ST2084_EXT,
+        );
+        const SCRGB: (vk::Format, vk::ColorSpaceKHR) = (
+            vk::Format::R16G16B16A16_SFLOAT,
+            vk::ColorSpaceKHR::EXTENDED_SRGB_LINEAR_EXT,
+        );
+
+        match self {
+            HdrMode::Off => &[],
+            HdrMode::Hdr10 => &[HDR10],
+            HdrMode::ScRgb => &[SCRGB],
+            HdrMode::Auto => &[HDR10, SCRGB],
+        }
+    }
+}
+
+// Mastering display description sent with VK_EXT_hdr_metadata, in nits.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct HdrMetadata {
+    pub max_luminance: f32,
+    pub min_luminance: f32,
+    pub max_content_light_level: f32,
+    pub max_frame_average_light_level: f32,
+}
+
+impl Default for HdrMetadata {
+    fn default() -> Self {
+        Self {
+            max_luminance: 1000.0,
+            min_luminance: 0.001,
+            max_content_light_level: 1000.0,
+            max_frame_average_light_level: 400.0,
+        }
+    }
+}
+
+impl HdrMetadata {
+    fn to_vk(self) -> vk::HdrMetadataEXT<'static> {
+        let xy = |x, y| vk::XYColorEXT { x, y };
+
+        // BT.2020 primaries with a D65 white point
+        vk::HdrMetadataEXT::default()
+            .display_primary_red(xy(0.708, 0.292))
+            .display_primary_green(xy(0.170, 0.797))
+            .display_primary_blue(xy(0.131, 0.046))
+            .white_point(xy(0.3127, 0.3290))
+            .max_luminance(self.max_luminance)
+            .min_luminance(self.min_luminance)
+            .max_content_light_level(self.max_content_light_level)
+            .max_frame_average_light_level(self.max_frame_average_light_level)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct PresentConfig {
+    pub vsync: VsyncMode,
+    // Tried in order before falling back to FIFO; overrides `vsync` when not empty
+    pub preferred_present_modes: Vec<vk::PresentModeKHR>,
+    // Clamped to 1..=MAX_FRAMES_IN_FLIGHT
+    pub frames_in_flight: usize,
+    // Falls back to SDR when the surface offers no matching format
+    pub hdr: HdrMode,
+    pub hdr_metadata: HdrMetadata,
+}
+
+impl Default for PresentConfig {
+    fn default() -> Self {
+        Self {
+            vsync: VsyncMode::default(),
+            preferred_present_modes: Vec::new(),
+            frames_in_flight: DEFAULT_FRAMES_IN_FLIGHT,
+            hdr: HdrMode::default(),
+            hdr_metadata: HdrMetadata::default(),
+        }
+    }
+}
+
+impl PresentConfig {
+    pub fn frames_in_flight(&self) -> usize {
+        self.frames_in_flight.clamp(1, MAX_FRAMES_IN_FLIGHT)
+    }
+
+    pub fn select_present_mode(
+        &self,
+        available_present_modes: &[vk::PresentModeKHR],
+    ) -> vk::PresentModeKHR {
+        let preferred_present_modes = if self.preferred_present_modes.is_empty() {
+            self.vsync.present_modes()
+        } else {
+            &self.preferred_present_modes
+        };
+
+        preferred_present_modes
+            .iter()
+            .copied()
+            .find(|present_mode| available_present_modes.contains(present_mode))
+            // FIFO is guaranteed to be available on all platforms
+            .unwrap_or(vk::PresentModeKHR::FIFO)
+    }
 }
 
 pub struct SwapchainManager {
@@ -22,21 +167,29 @@ pub struct SwapchainManager {
     pub swapchain_images: Vec<vk::Image>,
     pub amount_of_images: usize,
     pub preferred_surface_format: vk::Format,
+    pub color_space: vk::ColorSpaceKHR,
     pub image_extent: vk::Extent2D,
+    pub image_usage: vk::ImageUsageFlags,
+    pub present_mode: vk::PresentModeKHR,
 }
 
 impl SwapchainManager {
     pub fn new(
         window: &Window,
         instance: &ash::Instance,
-        surface_loader: &ash::khr::surface::Instance,
-        surface: vk::SurfaceKHR,
-        physical_device: vk::PhysicalDevice,
-        queue_family_indices: &QueueFamilyIndices,
+        surface_manager: &SurfaceManager,
+        physical_device_manager: &PhysicalDeviceManager,
         device: &ash::Device,
+        present_config: &PresentConfig,
+        old_swapchain: vk::SwapchainKHR,
     ) -> Result<Self, SwapchainManagerError> {
-        let support_details =
-            get_swapchain_support_details(surface_loader, surface, physical_device)?;
+        let surface = surface_manager.surface;
+        let queue_family_indices = &physical_device_manager.queue_family_indices;
+        let support_details = get_swapchain_support_details(
+            &surface_manager.surface_loader,
+            surface,
+            physical_device_manager.physical_device,
+        )?;
 
         let mut min_image_count = support_details.capabilities.min_image_count + 1;
         if support_details.capabilities.max_image_count > 0
@@ -45,9 +198,20 @@ impl SwapchainManager {
             min_image_count = support_details.capabilities.max_image_count;
         }
 
-        let surface_format = select_preferred_surface_format(&support_details.formats);
+        let surface_format =
+            select_preferred_surface_format(&support_details.formats, present_config.hdr);
         let image_extent = determine_swapchain_extent(&window, &support_details.capabilities);
-        let present_mode = select_preferred_present_mode(&support_details.present_modes);
+        let present_mode = present_config.select_present_mode(&support_details.present_modes);
+
+        // Frame captures copy out of the swapchain image, which needs transfer source usage
+        let mut image_usage = vk::ImageUsageFlags::COLOR_ATTACHMENT;
+        if support_details
+            .capabilities
+            .supported_usage_flags
+            .contains(vk::ImageUsageFlags::TRANSFER_SRC)
+        {
+            image_usage |= vk::ImageUsageFlags::TRANSFER_SRC;
+        }
 
         let mut swapchain_info = vk::SwapchainCreateInfoKHR::default()
             .surface(surface)
@@ -56,11 +220,12 @@ impl SwapchainManager {
             .image_color_space(surface_format.color_space)
             .image_extent(image_extent)
             .image_array_layers(1)
-            .image_usage(vk::ImageUsageFlags::COLOR_ATTACHMENT)
+            .image_usage(image_usage)
             .pre_transform(support_details.capabilities.current_transform)
             .composite_alpha(vk::CompositeAlphaFlagsKHR::OPAQUE)
             .present_mode(present_mode)
-            .clipped(true);
+            .clipped(true)
+            .old_swapchain(old_swapchain);
 
         let indices = [
             queue_family_indices.graphics_queue_family_index.unwrap(),
@@ -83,24 +248,43 @@ impl SwapchainManager {
         let swapchain = unsafe {
             swapchain_loader
                 .create_swapchain(&swapchain_info, None)
-                .map_err(|e| SwapchainManagerError::CreateSwapchainFailed(e.to_string()))
+                .map_err(SwapchainManagerError::CreateSwapchainFailed)
         }?;
 
         let swapchain_images = unsafe {
             swapchain_loader
                 .get_swapchain_images(swapchain)
-                .map_err(|e| SwapchainManagerError::GetSwapchainImagesFailed(e.to_string()))?
+                .map_err(SwapchainManagerError::GetSwapchainImagesFailed)?
         };
 
         let amount_of_images = swapchain_images.len();
 
+        let is_hdr = matches!(
+            surface_format.color_space,
+            vk::ColorSpaceKHR::HDR10_ST2084_EXT | vk::ColorSpaceKHR::EXTENDED_SRGB_LINEAR_EXT
+        );
+        if is_hdr
+            && physical_device_manager
+                .enabled_features
+                .has_extension(ash::ext::hdr_metadata::NAME)
+        {
+            let hdr_metadata_loader = ash::ext::hdr_metadata::Device::new(instance, device);
+            unsafe {
+                hdr_metadata_loader
+                    .set_hdr_metadata(&[swapchain], &[present_config.hdr_metadata.to_vk()]);
+            }
+        }
+
         Ok(Self {
             swapchain_loader,
             swapchain,
             swapchain_images,
             amount_of_images,
             preferred_surface_format: surface_format.format,
+            color_space: surface_format.color_space,
             image_extent,
+            image_usage,
+            present_mode,
         })
     }
 }
@@ -114,7 +298,30 @@ impl Drop for SwapchainManager {
     }
 }
 
-fn select_preferred_surface_format(formats: &[vk::SurfaceFormatKHR]) -> &vk::SurfaceFormatKHR {
+fn select_preferred_surface_format(
+    formats: &[vk::SurfaceFormatKHR],
+    hdr_mode: HdrMode,
+) -> &vk::SurfaceFormatKHR {
+    let hdr_format = hdr_mode
+        .surface_formats()
+        .iter()
+        .find_map(|&(format, color_space)| {
+            formats
+                .iter()
+                .find(|f| f.format == format && f.color_space == color_space)
+        });
+
+    if let Some(hdr_format) = hdr_format {
+        return hdr_format;
+    }
+
+    if hdr_mode != HdrMode::Off {
+        log::info!(
+            "The surface offers no HDR format for {:?}, using SDR",
+            hdr_mode
+        );
+    }
+
     formats
         .iter()
         .find(|f| {
@@ -144,28 +351,16 @@ fn determine_swapchain_extent(
     vk::Extent2D { width, height }
 }
 
-fn select_preferred_present_mode(
-    available_present_modes: &[vk::PresentModeKHR],
-) -> vk::PresentModeKHR {
-    // Prefer MAILBOX mode for lower latency and less tearing
-    if available_present_modes.contains(&vk::PresentModeKHR::MAILBOX) {
-        vk::PresentModeKHR::MAILBOX
-    } else {
-        // FIFO is guaranteed to be available on all platforms
-        vk::PresentModeKHR::FIFO
-    }
-}
-
 #[derive(Debug, Error)]
 pub enum SwapchainSupportError {
     #[error("Failed to enumerate swapchain support: {0}")]
-    EnumerateSwapchainSupportFailed(String),
+    EnumerateSwapchainSupportFailed(vk::Result),
 
     #[error("Failed to enumerate swapchain formats: {0}")]
-    EnumerateSwapchainFormatsFailed(String),
+    EnumerateSwapchainFormatsFailed(vk::Result),
 
     #[error("Failed to enumerate swapchain present modes: {0}")]
-    EnumerateSwapchainPresentModesFailed(String),
+    EnumerateSwapchainPresentModesFailed(vk::Result),
 }
 
 pub struct SwapchainSupportDetails {
@@ -183,19 +378,13 @@ pub fn get_swapchain_support_details(
         SwapchainSupportDetails {
             capabilities: surface_loader
                 .get_physical_device_surface_capabilities(physical_device, surface)
-                .map_err(|e| {
-                    SwapchainSupportError::EnumerateSwapchainSupportFailed(e.to_string())
-                })?,
+                .map_err(SwapchainSupportError::EnumerateSwapchainSupportFailed)?,
             formats: surface_loader
                 .get_physical_device_surface_formats(physical_device, surface)
-                .map_err(|e| {
-                    SwapchainSupportError::EnumerateSwapchainFormatsFailed(e.to_string())
-                })?,
+                .map_err(SwapchainSupportError::EnumerateSwapchainFormatsFailed)?,
             present_modes: surface_loader
                 .get_physical_device_surface_present_modes(physical_device, surface)
-                .map_err(|e| {
-                    SwapchainSupportError::EnumerateSwapchainPresentModesFailed(e.to_string())
-                })?,
+                .map_err(SwapchainSupportError::EnumerateSwapchainPresentModesFailed)?,
         }
     })
 }
@@ -0,0 +1,662 @@
+use std::collections::VecDeque;
+
+use ash::vk;
+use thiserror::Error;
+
+use crate::vulkan::{
+    allocator::{Allocation, MemoryAllocator},
+    barrier::ResourceBarrier,
+    device::DeviceManager,
+    memory::MemoryError,
+    queue::QueueFamilyIndices,
+};
+
+pub const STAGING_BUFFER_SIZE: vk::DeviceSize = 16 * 1024 * 1024;
+// Keeps staging offsets valid for buffer-to-image copies of any common texel size.
+const STAGING_ALIGNMENT: vk::DeviceSize = 16;
+
+#[derive(Debug, Error)]
+pub enum UploadManagerError {
+    #[error("Failed to create upload resource: {0}")]
+    CreateResourceFailed(#[from] MemoryError),
+
+    #[error("Failed to create command pool: {0}")]
+    CreateCommandPoolFailed(vk::Result),
+
+    #[error("Failed to allocate command buffer: {0}")]
+    AllocateCommandBufferFailed(vk::Result),
+
+    #[error("Failed to begin command buffer: {0}")]
+    BeginCommandBufferFailed(vk::Result),
+
+    #[error("Failed to end command buffer: {0}")]
+    EndCommandBufferFailed(vk::Result),
+
+    #[error("Failed to create synchronization objects: {0}")]
+    CreateSyncObjectsFailed(vk::Result),
+
+    #[error("Failed to queue submit: {0}")]
+    QueueSubmitFailed(vk::Result),
+
+    #[error("Failed to wait for upload: {0}")]
+    WaitForUploadFailed(vk::Result),
+}
+
+// Identifies one submitted upload; see `UploadManager::is_complete` and `UploadManager::wait`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct UploadTicket(u64);
+
+#[derive(Debug, Clone, Copy)]
+enum UploadTarget {
+    Buffer {
+        buffer: vk::Buffer,
+    },
+    Image {
+        image: vk::Image,
+        extent: vk::Extent3D,
+        layer_count: u32,
+        final_layout: vk::ImageLayout,
+    },
+}
+
+impl UploadTarget {
+    fn barrier(&self) -> ResourceBarrier {
+        match *self {
+            UploadTarget::Buffer { buffer } => ResourceBarrier::buffer(buffer),
+            UploadTarget::Image {
+                image, layer_count, ..
+            } => ResourceBarrier::image(
+                image,
+                vk::ImageSubresourceRange::default()
+                    .aspect_mask(vk::ImageAspectFlags::COLOR)
+                    .base_mip_level(0)
+                    .level_count(1)
+                    .base_array_layer(0)
+                    .layer_count(layer_count),
+            ),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+struct StagingRegion {
+    start: vk::DeviceSize,
+    end: vk::DeviceSize,
+}
+
+struct InFlightUpload {
+    ticket: UploadTicket,
+    fence: vk::Fence,
+    // Only set when the copy runs on a separate transfer family and ownership is handed to graphics
+    handoff_semaphore: vk::Semaphore,
+    transfer_command_buffer: vk::CommandBuffer,
+    graphics_command_buffer: vk::CommandBuffer,
+
+    staging_region: Option<StagingRegion>,
+    // Uploads larger than the staging ring get a buffer of their own
+    dedicated_staging: Option<(vk::Buffer, Allocation)>,
+}
+
+pub struct UploadManager {
+    device: ash::Device,
+    allocator: MemoryAllocator,
+
+    transfer_queue: vk::Queue,
+    transfer_queue_family_index: u32,
+    graphics_queue: vk::Queue,
+    graphics_queue_family_index: u32,
+
+    transfer_command_pool: vk::CommandPool,
+    graphics_command_pool: Option<vk::CommandPool>,
+
+    staging_buffer: vk::Buffer,
+    staging_allocation: Allocation,
+    staging_head: vk::DeviceSize,
+
+    in_flight: VecDeque<InFlightUpload>,
+    next_ticket: u64,
+}
+
+impl UploadManager {
+    pub fn new(
+        device_manager: &DeviceManager,
+        allocator: &MemoryAllocator,
+        queue_family_indices: &QueueFamilyIndices,
+    ) -> Result<Self, UploadManagerError> {
+        let device = &device_manager.device;
+
+        let graphics_queue_family_index = queue_family_indices
+            .graphics_queue_family_index
+            .expect("Graphics queue family index not found");
+        let transfer_queue_family_index = queue_family_indices
+            .transfer_queue_family_index
+            .unwrap_or(graphics_queue_family_index);
+
+        let (staging_buffer, staging_allocation) = allocator.create_buffer(
+            STAGING_BUFFER_SIZE,
+            vk::BufferUsageFlags::TRANSFER_SRC,
+            vk::MemoryPropertyFlags::HOST_VISIBLE | vk::MemoryPropertyFlags::HOST_COHERENT,
+        )?;
+
+        let create_command_pool = |queue_family_index: u32| unsafe {
+            device
+                .create_command_pool(
+                    &vk::CommandPoolCreateInfo::default()
+                        .queue_family_index(queue_family_index)
+                        .flags(vk::CommandPoolCreateFlags::TRANSIENT),
+                    None,
+                )
+                .map_err(UploadManagerError::CreateCommandPoolFailed)
+        };
+
+        let transfer_command_pool = match create_command_pool(transfer_queue_family_index) {
+            Ok(command_pool) => command_pool,
+            Err(e) => {
+                unsafe { device.destroy_buffer(staging_buffer, None) };
+                return Err(e);
+            }
+        };
+
+        let graphics_command_pool = if transfer_queue_family_index != graphics_queue_family_index {
+            match create_command_pool(graphics_queue_family_index) {
+                Ok(command_pool) => Some(command_pool),
+                Err(e) => unsafe {
+                    device.destroy_command_pool(transfer_command_pool, None);
+                    device.destroy_buffer(staging_buffer, None);
+                    return Err(e);
+                },
+            }
+        } else {
+            None
+        };
+
+        Ok(Self {
+            device: device.clone(),
+            allocator: allocator.clone(),
+
+            transfer_queue: device_manager.transfer_queue,
+            transfer_queue_family_index,
+            graphics_queue: device_manager.graphics_queue,
+            graphics_queue_family_index,
+
+            transfer_command_pool,
+            graphics_command_pool,
+
+            staging_buffer,
+            staging_allocation,
+            staging_head: 0,
+
+            in_flight: VecDeque::new(),
+            next_ticket: 0,
+        })
+    }
+
+    // Creates a DEVICE_LOCAL buffer and fills it with `data`; it is usable by any later submission.
+    pub fn create_buffer_with_data<T: Copy>(
+        &mut self,
+        data: &[T],
+        usage: vk::BufferUsageFlags,
+    ) -> Result<(vk::Buffer, Allocation, UploadTicket), UploadManagerError> {
+        let size = std::mem::size_of_val(data) as vk::DeviceSize;
+
+        let (buffer, allocation) = self.allocator.create_buffer(
+            size,
+            usage | vk::BufferUsageFlags::TRANSFER_DST,
+            vk::MemoryPropertyFlags::DEVICE_LOCAL,
+        )?;
+
+        match self.submit(data, UploadTarget::Buffer { buffer }) {
+            Ok(ticket) => Ok((buffer, allocation, ticket)),
+            Err(e) => {
+                unsafe { self.device.destroy_buffer(buffer, None) };
+                Err(e)
+            }
+        }
+    }
+
+    // `data` holds tightly packed texels for mip level 0 of every array layer.
+    pub fn create_image_with_data<T: Copy>(
+        &mut self,
+        image_info: &vk::ImageCreateInfo,
+        data: &[T],
+        final_layout: vk::ImageLayout,
+    ) -> Result<(vk::Image, Allocation, UploadTicket), UploadManagerError> {
+        let image_info = image_info
+            .usage(image_info.usage | vk::ImageUsageFlags::TRANSFER_DST)
+            .initial_layout(vk::ImageLayout::UNDEFINED);
+
+        let (image, allocation) = self
+            .allocator
+            .create_image(&image_info, vk::MemoryPropertyFlags::DEVICE_LOCAL)?;
+
+        let target = UploadTarget::Image {
+            image,
+            extent: image_info.extent,
+            layer_count: image_info.array_layers,
+            final_layout,
+        };
+
+        match self.submit(data, target) {
+            Ok(ticket) => Ok((image, allocation, ticket)),
+            Err(e) => {
+                unsafe { self.device.destroy_image(image, None) };
+                Err(e)
+            }
+        }
+    }
+
+    pub fn device(&self) -> &ash::Device {
+        &self.device
+    }
+
+    pub fn is_complete(&mut self, ticket: UploadTicket) -> Result<bool, UploadManagerError> {
+        self.retire_completed()?;
+
+        Ok(!self.in_flight.iter().any(|upload| upload.ticket == ticket))
+    }
+
+    pub fn wait(&mut self, ticket: UploadTicket) -> Result<(), UploadManagerError> {
+        let Some(upload) = self.in_flight.iter().find(|upload| upload.ticket == ticket) else {
+            return Ok(());
+        };
+
+        unsafe {
+            self.device
+                .wait_for_fences(&[upload.fence], true, u64::MAX)
+                .map_err(UploadManagerError::WaitForUploadFailed)?;
+        }
+
+        self.retire_completed()
+    }
+
+    pub fn wait_idle(&mut self) -> Result<(), UploadManagerError> {
+        while let Some(upload) = self.in_flight.back() {
+            let ticket = upload.ticket;
+            self.wait(ticket)?;
+        }
+
+        Ok(())
+    }
+
+    fn submit<T: Copy>(
+        &mut self,
+        data: &[T],
+        target: UploadTarget,
+    ) -> Result<UploadTicket, UploadManagerError> {
+        self.retire_completed()?;
+
+        let size = std::mem::size_of_val(data) as vk::DeviceSize;
+
+        let (staging_buffer, staging_offset, staging_region, dedicated_staging) =
+            if size > STAGING_BUFFER_SIZE {
+                let (buffer, allocation) = self.allocator.create_buffer(
+                    size,
+                    vk::BufferUsageFlags::TRANSFER_SRC,
+                    vk::MemoryPropertyFlags::HOST_VISIBLE | vk::MemoryPropertyFlags::HOST_COHERENT,
+                )?;
+
+                if let Err(e) = allocation.write(0, data) {
+                    unsafe { self.device.destroy_buffer(buffer, None) };
+                    return Err(e.into());
+                }
+
+                (buffer, 0, None, Some((buffer, allocation)))
+            } else {
+                let offset = self.reserve_staging(size)?;
+                self.staging_allocation.write(offset, data)?;
+
+                let region = StagingRegion {
+                    start: offset,
+                    end: offset + size,
+                };
+
+                (self.staging_buffer, offset, Some(region), None)
+            };
+
+        let mut upload = InFlightUpload {
+            ticket: UploadTicket(self.next_ticket),
+            fence: vk::Fence::null(),
+            handoff_semaphore: vk::Semaphore::null(),
+            transfer_command_buffer: vk::CommandBuffer::null(),
+            graphics_command_buffer: vk::CommandBuffer::null(),
+            staging_region,
+            dedicated_staging,
+        };
+
+        // Everything created below is owned by `upload`, so a failure only has to release it.
+        match self.record_and_submit(&mut upload, staging_buffer, staging_offset, size, target) {
+            Ok(()) => {
+                self.next_ticket += 1;
+                let ticket = upload.ticket;
+                self.in_flight.push_back(upload);
+                Ok(ticket)
+            }
+            Err(e) => {
+                self.release(upload);
+                Err(e)
+            }
+        }
+    }
+
+    fn record_and_submit(
+        &self,
+        upload: &mut InFlightUpload,
+        staging_buffer: vk::Buffer,
+        staging_offset: vk::DeviceSize,
+        size: vk::DeviceSize,
+        target: UploadTarget,
+    ) -> Result<(), UploadManagerError> {
+        let device = &self.device;
+
+        unsafe {
+            upload.fence = device
+                .create_fence(&vk::FenceCreateInfo::default(), None)
+                .map_err(UploadManagerError::CreateSyncObjectsFailed)?;
+        }
+
+        upload.transfer_command_buffer = self.begin_command_buffer(self.transfer_command_pool)?;
+
+        let command_buffer = upload.transfer_command_buffer;
+
+        if let UploadTarget::Image { .. } = target {
+            target
+                .barrier()
+                .layout_transition(
+                    vk::ImageLayout::UNDEFINED,
+                    vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+                )
+                .record(
+                    device,
+                    command_buffer,
+                    vk::PipelineStageFlags::TOP_OF_PIPE,
+                    vk::PipelineStageFlags::TRANSFER,
+                    vk::AccessFlags::empty(),
+                    vk::AccessFlags::TRANSFER_WRITE,
+                );
+        }
+
+        unsafe {
+            match target {
+                UploadTarget::Buffer { buffer } => {
+                    device.cmd_copy_buffer(
+                        command_buffer,
+                        staging_buffer,
+                        buffer,
+                        &[vk::BufferCopy::default()
+                            .src_offset(staging_offset)
+                            .dst_offset(0)
+                            .size(size)],
+                    );
+                }
+                UploadTarget::Image {
+                    image,
+                    extent,
+                    layer_count,
+                    ..
+                } => {
+                    device.cmd_copy_buffer_to_image(
+                        command_buffer,
+                        staging_buffer,
+                        image,
+                        vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+                        &[vk::BufferImageCopy::default()
+                            .buffer_offset(staging_offset)
+                            .buffer_row_length(0)
+                            .buffer_image_height(0)
+                            .image_subresource(
+                                vk::ImageSubresourceLayers::default()
+                                    .aspect_mask(vk::ImageAspectFlags::COLOR)
+                                    .mip_level(0)
+                                    .base_array_layer(0)
+                                    .layer_count(layer_count),
+                            )
+                            .image_offset(vk::Offset3D::default())
+                            .image_extent(extent)],
+                    );
+                }
+            }
+        }
+
+        let final_layout = match target {
+            UploadTarget::Buffer { .. } => vk::ImageLayout::UNDEFINED,
+            UploadTarget::Image { final_layout, .. } => final_layout,
+        };
+
+        if self.graphics_command_pool.is_none() {
+            // Same queue as rendering: a barrier covers every later submission.
+            target
+                .barrier()
+                .layout_transition(vk::ImageLayout::TRANSFER_DST_OPTIMAL, final_layout)
+                .record(
+                    device,
+                    command_buffer,
+                    vk::PipelineStageFlags::TRANSFER,
+                    vk::PipelineStageFlags::ALL_COMMANDS,
+                    vk::AccessFlags::TRANSFER_WRITE,
+                    vk::AccessFlags::MEMORY_READ,
+                );
+
+            self.end_command_buffer(command_buffer)?;
+
+            unsafe {
+                device
+                    .queue_submit(
+                        self.transfer_queue,
+                        &[vk::SubmitInfo::default()
+                            .command_buffers(std::slice::from_ref(&command_buffer))],
+                        upload.fence,
+                    )
+                    .map_err(UploadManagerError::QueueSubmitFailed)?;
+            }
+
+            return Ok(());
+        }
+
+        // Separate transfer family: release ownership here and acquire it on the graphics queue.
+        let ownership_barrier = target
+            .barrier()
+            .layout_transition(vk::ImageLayout::TRANSFER_DST_OPTIMAL, final_layout)
+            .queue_family_transfer(
+                self.transfer_queue_family_index,
+                self.graphics_queue_family_index,
+            );
+
+        ownership_barrier.record_release(
+            device,
+            command_buffer,
+            vk::PipelineStageFlags::TRANSFER,
+            vk::AccessFlags::TRANSFER_WRITE,
+        );
+
+        self.end_command_buffer(command_buffer)?;
+
+        unsafe {
+            upload.handoff_semaphore = device
+                .create_semaphore(&vk::SemaphoreCreateInfo::default(), None)
+                .map_err(UploadManagerError::CreateSyncObjectsFailed)?;
+        }
+
+        upload.graphics_command_buffer =
+            self.begin_command_buffer(self.graphics_command_pool.unwrap())?;
+
+        let wait_dst_stage_mask = vk::PipelineStageFlags::ALL_COMMANDS;
+
+        ownership_barrier.record_acquire(
+            device,
+            upload.graphics_command_buffer,
+            wait_dst_stage_mask,
+            vk::AccessFlags::MEMORY_READ,
+        );
+
+        self.end_command_buffer(upload.graphics_command_buffer)?;
+
+        unsafe {
+            device
+                .queue_submit(
+                    self.transfer_queue,
+                    &[vk::SubmitInfo::default()
+                        .command_buffers(std::slice::from_ref(&command_buffer))
+                        .signal_semaphores(std::slice::from_ref(&upload.handoff_semaphore))],
+                    vk::Fence::null(),
+                )
+                .map_err(UploadManagerError::QueueSubmitFailed)?;
+
+            device
+                .queue_submit(
+                    self.graphics_queue,
+                    &[vk::SubmitInfo::default()
+                        .command_buffers(std::slice::from_ref(&upload.graphics_command_buffer))
+                        .wait_semaphores(std::slice::from_ref(&upload.handoff_semaphore))
+                        .wait_dst_stage_mask(std::slice::from_ref(&wait_dst_stage_mask))],
+                    upload.fence,
+                )
+                .map_err(UploadManagerError::QueueSubmitFailed)?;
+        }
+
+        Ok(())
+    }
+
+    fn begin_command_buffer(
+        &self,
+        command_pool: vk::CommandPool,
+    ) -> Result<vk::CommandBuffer, UploadManagerError> {
+        unsafe {
+            let command_buffer = self
+                .device
+                .allocate_command_buffers(
+                    &vk::CommandBufferAllocateInfo::default()
+                        .command_pool(command_pool)
+                        .level(vk::CommandBufferLevel::PRIMARY)
+                        .command_buffer_count(1),
+                )
+                .map_err(UploadManagerError::AllocateCommandBufferFailed)?[0];
+
+            if let Err(e) = self.device.begin_command_buffer(
+                command_buffer,
+                &vk::CommandBufferBeginInfo::default()
+                    .flags(vk::CommandBufferUsageFlags::ONE_TIME_SUBMIT),
+            ) {
+                self.device
+                    .free_command_buffers(command_pool, &[command_buffer]);
+                return Err(UploadManagerError::BeginCommandBufferFailed(e));
+            }
+
+            Ok(command_buffer)
+        }
+    }
+
+    fn end_command_buffer(
+        &self,
+        command_buffer: vk::CommandBuffer,
+    ) -> Result<(), UploadManagerError> {
+        unsafe {
+            self.device
+                .end_command_buffer(command_buffer)
+                .map_err(UploadManagerError::EndCommandBufferFailed)
+        }
+    }
+
+    fn reserve_staging(
+        &mut self,
+        size: vk::DeviceSize,
+    ) -> Result<vk::DeviceSize, UploadManagerError> {
+        loop {
+            let mut staged = self
+                .in_flight
+                .iter()
+                .filter_map(|upload| upload.staging_region);
+
+            if staged.clone().next().is_none() {
+                self.staging_head = 0;
+            }
+
+            let mut offset = self.staging_head.next_multiple_of(STAGING_ALIGNMENT);
+            if offset + size > STAGING_BUFFER_SIZE {
+                offset = 0;
+            }
+
+            if !staged.any(|region| offset < region.end && region.start < offset + size) {
+                self.staging_head = offset + size;
+                return Ok(offset);
+            }
+
+            // The ring is full; the oldest upload frees the space that comes next.
+            let oldest = self.in_flight.front().unwrap().ticket;
+            self.wait(oldest)?;
+        }
+    }
+
+    fn retire_completed(&mut self) -> Result<(), UploadManagerError> {
+        while let Some(upload) = self.in_flight.front() {
+            let completed = unsafe { self.device.get_fence_status(upload.fence) }
+                .map_err(UploadManagerError::WaitForUploadFailed)?;
+
+            if !completed {
+                break;
+            }
+
+            let upload = self.in_flight.pop_front().unwrap();
+            self.release(upload);
+        }
+
+        Ok(())
+    }
+
+    fn release(&self, upload: InFlightUpload) {
+        unsafe {
+            if upload.transfer_command_buffer != vk::CommandBuffer::null() {
+                self.device.free_command_buffers(
+                    self.transfer_command_pool,
+                    &[upload.transfer_command_buffer],
+                );
+            }
+
+            if upload.graphics_command_buffer != vk::CommandBuffer::null() {
+                self.device.free_command_buffers(
+                    self.graphics_command_pool.unwrap(),
+                    &[upload.graphics_command_buffer],
+                );
+            }
+
+            self.device
+                .destroy_semaphore(upload.handoff_semaphore, None);
+            self.device.destroy_fence(upload.fence, None);
+
+            if let Some((buffer, _)) = &upload.dedicated_staging {
+                self.device.destroy_buffer(*buffer, None);
+            }
+        }
+    }
+}
+
+impl Drop for UploadManager {
+    fn drop(&mut self) {
+        let fences: Vec<vk::Fence> = self.in_flight.iter().map(|upload| upload.fence).collect();
+
+        // On a lost device the fences never signal, and nothing is executing anymore either
+        let wait_result = if fences.is_empty() {
+            Ok(())
+        } else {
+            unsafe { self.device.wait_for_fences(&fences, true, u64::MAX) }
+        };
+
+        if let Err(e) = wait_result {
+            log::error!("Failed to wait for uploads: {}", e);
+        }
+
+        while let Some(upload) = self.in_flight.pop_front() {
+            self.release(upload);
+        }
+
+        unsafe {
+            if let Some(graphics_command_pool) = self.graphics_command_pool {
+                self.device
+                    .destroy_command_pool(graphics_command_pool, None);
+            }
+
+            self.device
+                .destroy_command_pool(self.transfer_command_pool, None);
+            self.device.destroy_buffer(self.staging_buffer, None);
+        }
+    }
+}